@@ -0,0 +1,5 @@
+ALTER TABLE users ADD COLUMN api_token text NOT NULL DEFAULT md5(random()::text);
+ALTER TABLE users ALTER COLUMN api_token DROP DEFAULT;
+ALTER TABLE users ADD UNIQUE (api_token);
+
+DROP TABLE sessions;
@@ -0,0 +1,17 @@
+CREATE TABLE sessions(
+	id serial PRIMARY KEY,
+	user_id text NOT NULL,
+	token_hash text NOT NULL,
+	created_at timestamp NOT NULL,
+	last_used_at timestamp NOT NULL,
+	expires_at timestamp NOT NULL,
+	user_agent text,
+	ip text,
+
+	UNIQUE (token_hash),
+	FOREIGN KEY (user_id) REFERENCES users (username) ON DELETE CASCADE
+);
+
+CREATE INDEX sessions_user_id ON sessions (user_id);
+
+ALTER TABLE users DROP COLUMN api_token;
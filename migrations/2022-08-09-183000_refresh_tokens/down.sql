@@ -0,0 +1,5 @@
+DROP TABLE refresh_tokens;
+
+-- old sessions can't be recovered, their bearer tokens were never stored
+DELETE FROM sessions;
+ALTER TABLE sessions ADD COLUMN token_hash text NOT NULL UNIQUE;
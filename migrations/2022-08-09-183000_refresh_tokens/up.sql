@@ -0,0 +1,16 @@
+-- sessions are now authenticated through rotating refresh tokens,
+-- every token minted for a session belongs to the same family
+ALTER TABLE sessions DROP COLUMN token_hash;
+
+CREATE TABLE refresh_tokens(
+	id serial PRIMARY KEY,
+	session_id integer NOT NULL,
+	token_hash text NOT NULL,
+	issued_at timestamp NOT NULL,
+	used_at timestamp,
+
+	UNIQUE (token_hash),
+	FOREIGN KEY (session_id) REFERENCES sessions (id) ON DELETE CASCADE
+);
+
+CREATE INDEX refresh_tokens_session_id ON refresh_tokens (session_id);
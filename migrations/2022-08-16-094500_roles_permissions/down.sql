@@ -0,0 +1,8 @@
+ALTER TABLE users ADD COLUMN role_flag boolean NOT NULL DEFAULT 'f';
+UPDATE users SET role_flag = 't' WHERE role = 'admin';
+ALTER TABLE users DROP COLUMN role;
+ALTER TABLE users RENAME COLUMN role_flag TO role;
+
+DROP TABLE role_permissions;
+DROP TABLE permissions;
+DROP TABLE roles;
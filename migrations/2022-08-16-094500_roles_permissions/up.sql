@@ -0,0 +1,40 @@
+CREATE TABLE roles(
+	name text PRIMARY KEY,
+	description text NOT NULL
+);
+
+CREATE TABLE permissions(
+	name text PRIMARY KEY,
+	description text NOT NULL
+);
+
+CREATE TABLE role_permissions(
+	role text NOT NULL,
+	permission text NOT NULL,
+
+	PRIMARY KEY (role, permission),
+	FOREIGN KEY (role) REFERENCES roles (name) ON DELETE CASCADE,
+	FOREIGN KEY (permission) REFERENCES permissions (name) ON DELETE CASCADE
+);
+
+INSERT INTO roles(name, description) VALUES
+	('admin', 'full control over users'),
+	('support', 'read-only access to users'),
+	('user', 'regular user, manages only their own data');
+
+INSERT INTO permissions(name, description) VALUES
+	('users:read', 'look up any user'),
+	('users:write', 'change any user, including their role'),
+	('users:delete', 'delete any user');
+
+INSERT INTO role_permissions(role, permission) VALUES
+	('admin', 'users:read'),
+	('admin', 'users:write'),
+	('admin', 'users:delete'),
+	('support', 'users:read');
+
+-- the boolean admin flag becomes a reference to a role
+ALTER TABLE users ADD COLUMN role_name text NOT NULL DEFAULT 'user' REFERENCES roles (name);
+UPDATE users SET role_name = 'admin' WHERE role;
+ALTER TABLE users DROP COLUMN role;
+ALTER TABLE users RENAME COLUMN role_name TO role;
@@ -0,0 +1,4 @@
+DELETE FROM permissions WHERE name = 'security:read';
+
+DROP TABLE lockouts;
+DROP TABLE login_attempts;
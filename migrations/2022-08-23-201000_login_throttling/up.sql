@@ -0,0 +1,31 @@
+-- usernames aren't foreign keys, guesses for unknown users count too
+CREATE TABLE login_attempts(
+	id serial PRIMARY KEY,
+	username text NOT NULL,
+	ip text,
+	succeeded boolean NOT NULL,
+	attempted_at timestamp NOT NULL
+);
+
+CREATE INDEX login_attempts_username ON login_attempts (username, attempted_at);
+CREATE INDEX login_attempts_ip ON login_attempts (ip, attempted_at);
+
+-- scope is either 'username' or 'ip', subject the locked username or address
+CREATE TABLE lockouts(
+	id serial PRIMARY KEY,
+	scope text NOT NULL,
+	subject text NOT NULL,
+	failures integer NOT NULL,
+	locked_at timestamp NOT NULL,
+	locked_until timestamp NOT NULL,
+
+	CHECK (scope IN ('username', 'ip'))
+);
+
+CREATE INDEX lockouts_subject ON lockouts (scope, subject, locked_until);
+
+INSERT INTO permissions(name, description) VALUES
+	('security:read', 'see login lockouts');
+
+INSERT INTO role_permissions(role, permission) VALUES
+	('admin', 'security:read');
@@ -0,0 +1,7 @@
+DELETE FROM permissions WHERE name = 'roles:write';
+
+ALTER TABLE roles DROP COLUMN require_2fa;
+ALTER TABLE sessions DROP COLUMN two_factor;
+
+DROP TABLE recovery_codes;
+DROP TABLE totp_secrets;
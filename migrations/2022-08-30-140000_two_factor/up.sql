@@ -0,0 +1,33 @@
+-- a secret stays unconfirmed until the user proves their app has it
+CREATE TABLE totp_secrets(
+	user_id text PRIMARY KEY,
+	secret bytea NOT NULL,
+	created_at timestamp NOT NULL,
+	confirmed_at timestamp,
+	last_used_step bigint,
+
+	FOREIGN KEY (user_id) REFERENCES users (username) ON DELETE CASCADE
+);
+
+CREATE TABLE recovery_codes(
+	id serial PRIMARY KEY,
+	user_id text NOT NULL,
+	code_hash text NOT NULL,
+	used_at timestamp,
+
+	UNIQUE (code_hash),
+	FOREIGN KEY (user_id) REFERENCES users (username) ON DELETE CASCADE
+);
+
+CREATE INDEX recovery_codes_user_id ON recovery_codes (user_id);
+
+-- sessions remember whether they were started with a second factor
+ALTER TABLE sessions ADD COLUMN two_factor boolean NOT NULL DEFAULT false;
+
+ALTER TABLE roles ADD COLUMN require_2fa boolean NOT NULL DEFAULT false;
+
+INSERT INTO permissions(name, description) VALUES
+	('roles:write', 'change what a role requires');
+
+INSERT INTO role_permissions(role, permission) VALUES
+	('admin', 'roles:write');
@@ -0,0 +1,19 @@
+-- keys only work for the scopes they list, and only on the listed
+-- accounts unless account_ids is null
+CREATE TABLE api_keys(
+	id serial PRIMARY KEY,
+	user_id text NOT NULL,
+	name text NOT NULL,
+	prefix text NOT NULL,
+	key_hash text NOT NULL,
+	scopes text[] NOT NULL,
+	account_ids integer[],
+	created_at timestamp NOT NULL,
+	expires_at timestamp,
+	last_used_at timestamp,
+
+	UNIQUE (key_hash),
+	FOREIGN KEY (user_id) REFERENCES users (username) ON DELETE CASCADE
+);
+
+CREATE INDEX api_keys_user_id ON api_keys (user_id);
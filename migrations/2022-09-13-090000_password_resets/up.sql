@@ -0,0 +1,16 @@
+-- where password reset tokens get mailed to
+ALTER TABLE users ADD COLUMN email text UNIQUE;
+
+CREATE TABLE password_resets(
+	id serial PRIMARY KEY,
+	user_id text NOT NULL,
+	token_hash text NOT NULL,
+	created_at timestamp NOT NULL,
+	expires_at timestamp NOT NULL,
+	used_at timestamp,
+
+	UNIQUE (token_hash),
+	FOREIGN KEY (user_id) REFERENCES users (username) ON DELETE CASCADE
+);
+
+CREATE INDEX password_resets_user_id ON password_resets (user_id);
@@ -0,0 +1,34 @@
+-- actor and target aren't foreign keys, the record has to outlive the users in it
+CREATE TABLE audit_events(
+	id serial PRIMARY KEY,
+	actor text,
+	action text NOT NULL,
+	target text,
+	ip text,
+	created_at timestamp NOT NULL
+);
+
+CREATE INDEX audit_events_actor ON audit_events (actor, created_at);
+CREATE INDEX audit_events_action ON audit_events (action, created_at);
+CREATE INDEX audit_events_created_at ON audit_events (created_at);
+
+-- events can only be added, never changed or removed
+CREATE FUNCTION audit_events_append_only() RETURNS trigger AS $$
+BEGIN
+	RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+	BEFORE UPDATE OR DELETE ON audit_events
+	FOR EACH ROW EXECUTE FUNCTION audit_events_append_only();
+
+CREATE TRIGGER audit_events_no_truncate
+	BEFORE TRUNCATE ON audit_events
+	FOR EACH STATEMENT EXECUTE FUNCTION audit_events_append_only();
+
+INSERT INTO permissions(name, description) VALUES
+	('audit:read', 'search the audit log');
+
+INSERT INTO role_permissions(role, permission) VALUES
+	('admin', 'audit:read');
@@ -0,0 +1,8 @@
+-- what exactly was done, like the request an impersonating admin made
+ALTER TABLE audit_events ADD COLUMN detail text;
+
+INSERT INTO permissions(name, description) VALUES
+	('users:impersonate', 'act as another user');
+
+INSERT INTO role_permissions(role, permission) VALUES
+	('admin', 'users:impersonate');
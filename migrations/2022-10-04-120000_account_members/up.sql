@@ -0,0 +1,18 @@
+-- account.user_id stays as whoever opened the account, members decide
+-- who may see and change it
+CREATE TABLE account_members(
+	account_id integer NOT NULL,
+	user_id text NOT NULL,
+	role text NOT NULL,
+	added_at timestamp NOT NULL,
+
+	PRIMARY KEY (account_id, user_id),
+	CHECK (role IN ('owner', 'editor', 'viewer')),
+	FOREIGN KEY (account_id) REFERENCES account (id) ON DELETE CASCADE,
+	FOREIGN KEY (user_id) REFERENCES users (username) ON DELETE CASCADE
+);
+
+CREATE INDEX account_members_user_id ON account_members (user_id);
+
+INSERT INTO account_members(account_id, user_id, role, added_at)
+	SELECT id, user_id, 'owner', now() FROM account;
@@ -0,0 +1,7 @@
+DELETE FROM transaction WHERE user_id IS NULL;
+ALTER TABLE transaction DROP CONSTRAINT transaction_user_id_fkey;
+ALTER TABLE transaction ADD CONSTRAINT transaction_user_id_fkey
+	FOREIGN KEY (user_id) REFERENCES users (username);
+ALTER TABLE transaction ALTER COLUMN user_id SET NOT NULL;
+
+DROP TABLE deletion_requests;
@@ -0,0 +1,15 @@
+-- users asking for their data to be deleted, carried out once the
+-- grace period is over unless they cancel
+CREATE TABLE deletion_requests(
+	user_id text PRIMARY KEY,
+	requested_at timestamp NOT NULL,
+	scheduled_for timestamp NOT NULL,
+
+	FOREIGN KEY (user_id) REFERENCES users (username) ON DELETE CASCADE
+);
+
+-- transactions on shared accounts outlive their author, anonymized
+ALTER TABLE transaction ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE transaction DROP CONSTRAINT transaction_user_id_fkey;
+ALTER TABLE transaction ADD CONSTRAINT transaction_user_id_fkey
+	FOREIGN KEY (user_id) REFERENCES users (username) ON DELETE SET NULL;
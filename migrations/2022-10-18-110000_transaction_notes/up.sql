@@ -0,0 +1,2 @@
+-- titles and notes are stored encrypted, see src/encryption
+ALTER TABLE transaction ADD COLUMN notes TEXT;
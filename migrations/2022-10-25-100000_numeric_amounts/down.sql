@@ -0,0 +1,5 @@
+ALTER TABLE transaction ALTER COLUMN value TYPE text USING value::text;
+
+ALTER TABLE account ALTER COLUMN balance DROP DEFAULT;
+ALTER TABLE account ALTER COLUMN balance TYPE text USING balance::text;
+ALTER TABLE account ALTER COLUMN balance SET DEFAULT '0';
@@ -0,0 +1,41 @@
+-- amounts become NUMERIC, surrounding spaces are dropped on the way and
+-- so are commas, but only where they group thousands like 1,234,567.89.
+-- Anything else still not a number, like the decimal comma in 12,50,
+-- stops the migration so it can be fixed by hand instead of being guessed
+CREATE FUNCTION pg_temp.plain_amount(amount text) RETURNS text AS $$
+	SELECT CASE
+		WHEN btrim(amount) ~ '^-?[0-9]{1,3}(,[0-9]{3})+(\.[0-9]+)?$'
+			THEN replace(btrim(amount), ',', '')
+		ELSE btrim(amount)
+	END
+$$ LANGUAGE sql IMMUTABLE;
+
+DO $$
+DECLARE
+	bad text;
+BEGIN
+	SELECT string_agg('transaction ' || id || ': ' || quote_literal(value), ', ')
+		INTO bad
+		FROM transaction
+		WHERE pg_temp.plain_amount(value) !~ '^-?[0-9]{1,15}(\.[0-9]{1,4})?$';
+	IF bad IS NOT NULL THEN
+		RAISE EXCEPTION 'amounts that are not numbers: %', bad;
+	END IF;
+
+	SELECT string_agg('account ' || id || ': ' || quote_literal(balance), ', ')
+		INTO bad
+		FROM account
+		WHERE pg_temp.plain_amount(balance) !~ '^-?[0-9]{1,15}(\.[0-9]{1,4})?$';
+	IF bad IS NOT NULL THEN
+		RAISE EXCEPTION 'balances that are not numbers: %', bad;
+	END IF;
+END
+$$;
+
+ALTER TABLE transaction
+	ALTER COLUMN value TYPE NUMERIC(19, 4) USING pg_temp.plain_amount(value)::numeric;
+
+ALTER TABLE account ALTER COLUMN balance DROP DEFAULT;
+ALTER TABLE account
+	ALTER COLUMN balance TYPE NUMERIC(19, 4) USING pg_temp.plain_amount(balance)::numeric;
+ALTER TABLE account ALTER COLUMN balance SET DEFAULT 0;
@@ -0,0 +1,2 @@
+DELETE FROM role_permissions WHERE permission = 'maintenance:run';
+DELETE FROM permissions WHERE name = 'maintenance:run';
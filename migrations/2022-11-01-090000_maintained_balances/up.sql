@@ -0,0 +1,12 @@
+-- balances follow transactions from now on, anything clients wrote
+-- into them before is replaced with what the transactions add up to
+UPDATE account SET balance = COALESCE((
+	SELECT SUM(CASE WHEN kind THEN value ELSE -value END)
+	FROM transaction WHERE bank_account = account.id
+), 0);
+
+INSERT INTO permissions(name, description) VALUES
+	('maintenance:run', 'run maintenance like rebuilding balances');
+
+INSERT INTO role_permissions(role, permission) VALUES
+	('admin', 'maintenance:run');
@@ -0,0 +1,10 @@
+-- what the boolean can't tell apart goes by which way it moved the balance
+ALTER TABLE transaction ALTER COLUMN kind TYPE boolean
+	USING CASE
+		WHEN kind = 'expense' THEN false
+		WHEN kind IN ('income', 'refund') THEN true
+		ELSE value >= 0
+	END;
+UPDATE transaction SET value = abs(value) WHERE value < 0;
+
+DROP TYPE transaction_kind;
@@ -0,0 +1,5 @@
+-- true was income and false an expense, see TransactionKind for the rest
+CREATE TYPE transaction_kind AS ENUM ('income', 'expense', 'transfer', 'adjustment', 'refund');
+
+ALTER TABLE transaction ALTER COLUMN kind TYPE transaction_kind
+	USING CASE WHEN kind THEN 'income' ELSE 'expense' END::transaction_kind;
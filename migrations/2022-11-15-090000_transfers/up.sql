@@ -0,0 +1,17 @@
+-- money moved between two accounts, its legs are the transactions
+-- pointing at it: what left one account, what arrived on the other
+-- and the fee, if any
+CREATE TABLE transfers(
+	id serial PRIMARY KEY,
+	user_id text,
+	-- what one unit of the sent currency bought of the received one
+	rate NUMERIC(24, 10) NOT NULL DEFAULT 1,
+	-- charged on the sending account in the sent currency
+	fee NUMERIC(19, 4) NOT NULL DEFAULT 0,
+	created_at timestamp NOT NULL,
+
+	FOREIGN KEY (user_id) REFERENCES users (username) ON DELETE SET NULL
+);
+
+ALTER TABLE transaction ADD COLUMN transfer_id integer REFERENCES transfers (id);
+CREATE INDEX transaction_transfer_id ON transaction (transfer_id);
@@ -0,0 +1,15 @@
+ALTER TABLE transaction DROP COLUMN entry_id;
+DROP TABLE postings;
+DROP TABLE journal_entries;
+
+-- only bank accounts existed before
+DELETE FROM account WHERE kind <> 'asset';
+ALTER TABLE account DROP CONSTRAINT account_user_id_kind_name_key;
+ALTER TABLE account ADD UNIQUE (user_id, name);
+ALTER TABLE account DROP COLUMN kind;
+DROP TYPE account_kind;
+
+UPDATE account SET balance = COALESCE((
+	SELECT SUM(CASE WHEN kind = 'expense' THEN -value ELSE value END)
+	FROM transaction WHERE bank_account = account.id
+), 0);
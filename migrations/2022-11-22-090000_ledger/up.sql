@@ -0,0 +1,88 @@
+-- bank accounts become the asset accounts of a chart of accounts, income
+-- and expense categories and the rest are accounts too
+CREATE TYPE account_kind AS ENUM ('asset', 'liability', 'equity', 'income', 'expense');
+
+ALTER TABLE account ADD COLUMN kind account_kind NOT NULL DEFAULT 'asset';
+ALTER TABLE account DROP CONSTRAINT account_user_id_name_key;
+ALTER TABLE account ADD UNIQUE (user_id, kind, name);
+
+-- a journal entry moves money between accounts with postings that add
+-- up to zero in every currency, debits are positive and credits negative
+CREATE TABLE journal_entries(
+	id serial PRIMARY KEY,
+	user_id text,
+	-- encrypted like transaction titles, transactions keep theirs
+	description text,
+	time date NOT NULL,
+	created_at timestamp NOT NULL,
+
+	FOREIGN KEY (user_id) REFERENCES users (username) ON DELETE SET NULL
+);
+
+CREATE TABLE postings(
+	id serial PRIMARY KEY,
+	entry_id integer NOT NULL,
+	account_id integer NOT NULL,
+	amount NUMERIC(19, 4) NOT NULL,
+	currency currency_type NOT NULL,
+
+	FOREIGN KEY (entry_id) REFERENCES journal_entries (id) ON DELETE CASCADE,
+	FOREIGN KEY (account_id) REFERENCES account (id)
+);
+
+CREATE INDEX postings_entry_id ON postings (entry_id);
+CREATE INDEX postings_account_id ON postings (account_id);
+
+-- which category account of its bank account's owner each kind of
+-- transaction posts against, see TransactionKind::category
+CREATE TEMPORARY TABLE categories(kind transaction_kind, name text, account_kind account_kind);
+INSERT INTO categories VALUES
+	('income', 'Income', 'income'),
+	('expense', 'Expenses', 'expense'),
+	('refund', 'Expenses', 'expense'),
+	('adjustment', 'Adjustments', 'equity'),
+	('transfer', 'Transfers', 'equity');
+
+INSERT INTO account(balance, user_id, name, kind)
+	SELECT DISTINCT 0, account.user_id, categories.name, categories.account_kind
+	FROM transaction
+	JOIN account ON account.id = transaction.bank_account
+	JOIN categories ON categories.kind = transaction.kind;
+
+INSERT INTO account_members(account_id, user_id, role, added_at)
+	SELECT id, user_id, 'owner', now() FROM account WHERE kind <> 'asset';
+
+-- every transaction becomes an entry of its own, debiting or crediting
+-- its bank account against the category
+ALTER TABLE transaction ADD COLUMN entry_id integer;
+UPDATE transaction SET entry_id = nextval('journal_entries_id_seq');
+
+INSERT INTO journal_entries(id, user_id, description, time, created_at)
+	SELECT entry_id, user_id, NULL, time, now() FROM transaction;
+
+INSERT INTO postings(entry_id, account_id, amount, currency)
+	SELECT entry_id, bank_account,
+		CASE WHEN kind = 'expense' THEN -value ELSE value END, currency
+	FROM transaction;
+
+INSERT INTO postings(entry_id, account_id, amount, currency)
+	SELECT transaction.entry_id, category.id,
+		CASE WHEN transaction.kind = 'expense' THEN value ELSE -value END,
+		transaction.currency
+	FROM transaction
+	JOIN account ON account.id = transaction.bank_account
+	JOIN categories ON categories.kind = transaction.kind
+	JOIN account category ON category.user_id = account.user_id
+		AND category.kind = categories.account_kind
+		AND category.name = categories.name;
+
+DROP TABLE categories;
+
+ALTER TABLE transaction ALTER COLUMN entry_id SET NOT NULL;
+ALTER TABLE transaction ADD FOREIGN KEY (entry_id) REFERENCES journal_entries (id) ON DELETE CASCADE;
+CREATE UNIQUE INDEX transaction_entry_id ON transaction (entry_id);
+
+-- balances follow postings from now on
+UPDATE account SET balance = COALESCE((
+	SELECT SUM(amount) FROM postings WHERE account_id = account.id
+), 0);
@@ -0,0 +1,17 @@
+DELETE FROM role_permissions WHERE permission = 'currencies:write';
+DELETE FROM permissions WHERE name = 'currencies:write';
+
+-- only the four currencies the enum had can go back, anything else fails
+CREATE TYPE currency_type AS ENUM('USD', 'IRR', 'CAD', 'Euore');
+
+ALTER TABLE postings DROP CONSTRAINT postings_currency_fkey;
+ALTER TABLE postings ALTER COLUMN currency TYPE currency_type
+	USING (CASE currency WHEN 'EUR' THEN 'Euore' ELSE currency END)::currency_type;
+
+ALTER TABLE transaction DROP CONSTRAINT transaction_currency_fkey;
+ALTER TABLE transaction ALTER COLUMN currency DROP DEFAULT;
+ALTER TABLE transaction ALTER COLUMN currency TYPE currency_type
+	USING (CASE currency WHEN 'EUR' THEN 'Euore' ELSE currency END)::currency_type;
+ALTER TABLE transaction ALTER COLUMN currency SET DEFAULT 'USD';
+
+DROP TABLE currencies;
@@ -0,0 +1,202 @@
+-- currencies are looked up in a registry instead of a fixed enum, with
+-- how many minor units they have and the symbol they're shown with
+CREATE TABLE currencies(
+	-- ISO 4217 codes, custom units like BTC or points follow the same shape
+	code text PRIMARY KEY CHECK (code ~ '^[A-Z][A-Z0-9]{1,9}$'),
+	name text NOT NULL,
+	-- digits after the point, amounts are stored with four at most
+	minor_units smallint NOT NULL CHECK (minor_units BETWEEN 0 AND 4),
+	symbol text NOT NULL,
+	-- added by an admin rather than taken from ISO 4217
+	custom boolean NOT NULL DEFAULT false
+);
+
+-- ISO 4217, leaving out funds and metals that have no minor units
+INSERT INTO currencies(code, name, minor_units, symbol) VALUES
+	('AED', 'UAE Dirham', 2, 'د.إ'),
+	('AFN', 'Afghani', 2, '؋'),
+	('ALL', 'Lek', 2, 'L'),
+	('AMD', 'Armenian Dram', 2, '֏'),
+	('ANG', 'Netherlands Antillean Guilder', 2, 'ƒ'),
+	('AOA', 'Kwanza', 2, 'Kz'),
+	('ARS', 'Argentine Peso', 2, '$'),
+	('AUD', 'Australian Dollar', 2, '$'),
+	('AWG', 'Aruban Florin', 2, 'ƒ'),
+	('AZN', 'Azerbaijan Manat', 2, '₼'),
+	('BAM', 'Convertible Mark', 2, 'KM'),
+	('BBD', 'Barbados Dollar', 2, '$'),
+	('BDT', 'Taka', 2, '৳'),
+	('BGN', 'Bulgarian Lev', 2, 'лв'),
+	('BHD', 'Bahraini Dinar', 3, 'BD'),
+	('BIF', 'Burundi Franc', 0, 'FBu'),
+	('BMD', 'Bermudian Dollar', 2, '$'),
+	('BND', 'Brunei Dollar', 2, '$'),
+	('BOB', 'Boliviano', 2, 'Bs'),
+	('BOV', 'Mvdol', 2, 'BOV'),
+	('BRL', 'Brazilian Real', 2, 'R$'),
+	('BSD', 'Bahamian Dollar', 2, '$'),
+	('BTN', 'Ngultrum', 2, 'Nu.'),
+	('BWP', 'Pula', 2, 'P'),
+	('BYN', 'Belarusian Ruble', 2, 'Br'),
+	('BZD', 'Belize Dollar', 2, '$'),
+	('CAD', 'Canadian Dollar', 2, '$'),
+	('CDF', 'Congolese Franc', 2, 'FC'),
+	('CHE', 'WIR Euro', 2, 'CHE'),
+	('CHF', 'Swiss Franc', 2, 'CHF'),
+	('CHW', 'WIR Franc', 2, 'CHW'),
+	('CLF', 'Unidad de Fomento', 4, 'UF'),
+	('CLP', 'Chilean Peso', 0, '$'),
+	('CNY', 'Yuan Renminbi', 2, '¥'),
+	('COP', 'Colombian Peso', 2, '$'),
+	('COU', 'Unidad de Valor Real', 2, 'COU'),
+	('CRC', 'Costa Rican Colon', 2, '₡'),
+	('CUC', 'Peso Convertible', 2, 'CUC$'),
+	('CUP', 'Cuban Peso', 2, '$'),
+	('CVE', 'Cabo Verde Escudo', 2, '$'),
+	('CZK', 'Czech Koruna', 2, 'Kč'),
+	('DJF', 'Djibouti Franc', 0, 'Fdj'),
+	('DKK', 'Danish Krone', 2, 'kr'),
+	('DOP', 'Dominican Peso', 2, '$'),
+	('DZD', 'Algerian Dinar', 2, 'DA'),
+	('EGP', 'Egyptian Pound', 2, '£'),
+	('ERN', 'Nakfa', 2, 'Nfk'),
+	('ETB', 'Ethiopian Birr', 2, 'Br'),
+	('EUR', 'Euro', 2, '€'),
+	('FJD', 'Fiji Dollar', 2, '$'),
+	('FKP', 'Falkland Islands Pound', 2, '£'),
+	('GBP', 'Pound Sterling', 2, '£'),
+	('GEL', 'Lari', 2, '₾'),
+	('GHS', 'Ghana Cedi', 2, '₵'),
+	('GIP', 'Gibraltar Pound', 2, '£'),
+	('GMD', 'Dalasi', 2, 'D'),
+	('GNF', 'Guinean Franc', 0, 'FG'),
+	('GTQ', 'Quetzal', 2, 'Q'),
+	('GYD', 'Guyana Dollar', 2, '$'),
+	('HKD', 'Hong Kong Dollar', 2, '$'),
+	('HNL', 'Lempira', 2, 'L'),
+	('HRK', 'Kuna', 2, 'kn'),
+	('HTG', 'Gourde', 2, 'G'),
+	('HUF', 'Forint', 2, 'Ft'),
+	('IDR', 'Rupiah', 2, 'Rp'),
+	('ILS', 'New Israeli Sheqel', 2, '₪'),
+	('INR', 'Indian Rupee', 2, '₹'),
+	('IQD', 'Iraqi Dinar', 3, 'ع.د'),
+	('IRR', 'Iranian Rial', 2, '﷼'),
+	('ISK', 'Iceland Krona', 0, 'kr'),
+	('JMD', 'Jamaican Dollar', 2, '$'),
+	('JOD', 'Jordanian Dinar', 3, 'JD'),
+	('JPY', 'Yen', 0, '¥'),
+	('KES', 'Kenyan Shilling', 2, 'KSh'),
+	('KGS', 'Som', 2, 'с'),
+	('KHR', 'Riel', 2, '៛'),
+	('KMF', 'Comorian Franc', 0, 'CF'),
+	('KPW', 'North Korean Won', 2, '₩'),
+	('KRW', 'Won', 0, '₩'),
+	('KWD', 'Kuwaiti Dinar', 3, 'KD'),
+	('KYD', 'Cayman Islands Dollar', 2, '$'),
+	('KZT', 'Tenge', 2, '₸'),
+	('LAK', 'Lao Kip', 2, '₭'),
+	('LBP', 'Lebanese Pound', 2, 'LL'),
+	('LKR', 'Sri Lanka Rupee', 2, 'Rs'),
+	('LRD', 'Liberian Dollar', 2, '$'),
+	('LSL', 'Loti', 2, 'L'),
+	('LYD', 'Libyan Dinar', 3, 'LD'),
+	('MAD', 'Moroccan Dirham', 2, 'DH'),
+	('MDL', 'Moldovan Leu', 2, 'L'),
+	('MGA', 'Malagasy Ariary', 2, 'Ar'),
+	('MKD', 'Denar', 2, 'ден'),
+	('MMK', 'Kyat', 2, 'K'),
+	('MNT', 'Tugrik', 2, '₮'),
+	('MOP', 'Pataca', 2, 'MOP$'),
+	('MRU', 'Ouguiya', 2, 'UM'),
+	('MUR', 'Mauritius Rupee', 2, '₨'),
+	('MVR', 'Rufiyaa', 2, 'Rf'),
+	('MWK', 'Malawi Kwacha', 2, 'MK'),
+	('MXN', 'Mexican Peso', 2, '$'),
+	('MXV', 'Mexican Unidad de Inversion (UDI)', 2, 'MXV'),
+	('MYR', 'Malaysian Ringgit', 2, 'RM'),
+	('MZN', 'Mozambique Metical', 2, 'MT'),
+	('NAD', 'Namibia Dollar', 2, '$'),
+	('NGN', 'Naira', 2, '₦'),
+	('NIO', 'Cordoba Oro', 2, 'C$'),
+	('NOK', 'Norwegian Krone', 2, 'kr'),
+	('NPR', 'Nepalese Rupee', 2, 'Rs'),
+	('NZD', 'New Zealand Dollar', 2, '$'),
+	('OMR', 'Rial Omani', 3, 'RO'),
+	('PAB', 'Balboa', 2, 'B/.'),
+	('PEN', 'Sol', 2, 'S/'),
+	('PGK', 'Kina', 2, 'K'),
+	('PHP', 'Philippine Peso', 2, '₱'),
+	('PKR', 'Pakistan Rupee', 2, 'Rs'),
+	('PLN', 'Zloty', 2, 'zł'),
+	('PYG', 'Guarani', 0, '₲'),
+	('QAR', 'Qatari Rial', 2, 'QR'),
+	('RON', 'Romanian Leu', 2, 'lei'),
+	('RSD', 'Serbian Dinar', 2, 'дин.'),
+	('RUB', 'Russian Ruble', 2, '₽'),
+	('RWF', 'Rwanda Franc', 0, 'FRw'),
+	('SAR', 'Saudi Riyal', 2, 'SR'),
+	('SBD', 'Solomon Islands Dollar', 2, '$'),
+	('SCR', 'Seychelles Rupee', 2, '₨'),
+	('SDG', 'Sudanese Pound', 2, 'SDG'),
+	('SEK', 'Swedish Krona', 2, 'kr'),
+	('SGD', 'Singapore Dollar', 2, '$'),
+	('SHP', 'Saint Helena Pound', 2, '£'),
+	('SLE', 'Leone', 2, 'Le'),
+	('SLL', 'Leone', 2, 'Le'),
+	('SOS', 'Somali Shilling', 2, 'Sh'),
+	('SRD', 'Surinam Dollar', 2, '$'),
+	('SSP', 'South Sudanese Pound', 2, '£'),
+	('STN', 'Dobra', 2, 'Db'),
+	('SVC', 'El Salvador Colon', 2, '₡'),
+	('SYP', 'Syrian Pound', 2, '£'),
+	('SZL', 'Lilangeni', 2, 'E'),
+	('THB', 'Baht', 2, '฿'),
+	('TJS', 'Somoni', 2, 'SM'),
+	('TMT', 'Turkmenistan New Manat', 2, 'm'),
+	('TND', 'Tunisian Dinar', 3, 'DT'),
+	('TOP', 'Pa''anga', 2, 'T$'),
+	('TRY', 'Turkish Lira', 2, '₺'),
+	('TTD', 'Trinidad and Tobago Dollar', 2, '$'),
+	('TWD', 'New Taiwan Dollar', 2, '$'),
+	('TZS', 'Tanzanian Shilling', 2, 'TSh'),
+	('UAH', 'Hryvnia', 2, '₴'),
+	('UGX', 'Uganda Shilling', 0, 'USh'),
+	('USD', 'US Dollar', 2, '$'),
+	('USN', 'US Dollar (Next day)', 2, 'USN'),
+	('UYI', 'Uruguay Peso en Unidades Indexadas (UI)', 0, 'UYI'),
+	('UYU', 'Peso Uruguayo', 2, '$'),
+	('UYW', 'Unidad Previsional', 4, 'UYW'),
+	('UZS', 'Uzbekistan Sum', 2, 'so''m'),
+	('VED', 'Bolivar Soberano', 2, 'Bs.D'),
+	('VES', 'Bolivar Soberano', 2, 'Bs.S'),
+	('VND', 'Dong', 0, '₫'),
+	('VUV', 'Vatu', 0, 'VT'),
+	('WST', 'Tala', 2, 'WS$'),
+	('XAF', 'CFA Franc BEAC', 0, 'FCFA'),
+	('XCD', 'East Caribbean Dollar', 2, '$'),
+	('XOF', 'CFA Franc BCEAO', 0, 'CFA'),
+	('XPF', 'CFP Franc', 0, '₣'),
+	('YER', 'Yemeni Rial', 2, '﷼'),
+	('ZAR', 'Rand', 2, 'R'),
+	('ZMW', 'Zambian Kwacha', 2, 'ZK'),
+	('ZWL', 'Zimbabwe Dollar', 2, '$');
+
+-- the enum spelled euros 'Euore', they become EUR
+ALTER TABLE transaction ALTER COLUMN currency DROP DEFAULT;
+ALTER TABLE transaction ALTER COLUMN currency TYPE text
+	USING (CASE currency WHEN 'Euore' THEN 'EUR' ELSE currency::text END);
+ALTER TABLE transaction ALTER COLUMN currency SET DEFAULT 'USD';
+ALTER TABLE transaction ADD FOREIGN KEY (currency) REFERENCES currencies (code);
+
+ALTER TABLE postings ALTER COLUMN currency TYPE text
+	USING (CASE currency WHEN 'Euore' THEN 'EUR' ELSE currency::text END);
+ALTER TABLE postings ADD FOREIGN KEY (currency) REFERENCES currencies (code);
+
+DROP TYPE currency_type;
+
+INSERT INTO permissions(name, description) VALUES
+	('currencies:write', 'add and remove custom currencies');
+
+INSERT INTO role_permissions(role, permission) VALUES
+	('admin', 'currencies:write');
@@ -0,0 +1,5 @@
+DELETE FROM role_permissions WHERE permission = 'rates:import';
+DELETE FROM permissions WHERE name = 'rates:import';
+
+ALTER TABLE users DROP COLUMN base_currency;
+DROP TABLE exchange_rates;
@@ -0,0 +1,23 @@
+-- what one unit of base bought of quote on a day, like the ECB's daily
+-- reference rates with EUR as the base
+CREATE TABLE exchange_rates(
+	id serial PRIMARY KEY,
+	date date NOT NULL,
+	base text NOT NULL,
+	quote text NOT NULL,
+	rate NUMERIC(24, 10) NOT NULL CHECK (rate > 0),
+
+	FOREIGN KEY (base) REFERENCES currencies (code),
+	FOREIGN KEY (quote) REFERENCES currencies (code),
+	UNIQUE (base, quote, date)
+);
+
+-- the currency a user's reports are converted to
+ALTER TABLE users ADD COLUMN base_currency text NOT NULL DEFAULT 'USD'
+	REFERENCES currencies (code);
+
+INSERT INTO permissions(name, description) VALUES
+	('rates:import', 'import exchange rates');
+
+INSERT INTO role_permissions(role, permission) VALUES
+	('admin', 'rates:import');
@@ -0,0 +1,28 @@
+-- accounts of one name in several currencies merge into the oldest one
+CREATE TEMPORARY TABLE merges AS
+	SELECT account.id, (
+		SELECT min(first.id) FROM account AS first
+		WHERE first.user_id = account.user_id
+		AND first.kind = account.kind
+		AND first.name = account.name
+	) AS into_id
+	FROM account;
+DELETE FROM merges WHERE id = into_id;
+
+UPDATE postings SET account_id = merges.into_id FROM merges
+	WHERE postings.account_id = merges.id;
+UPDATE transaction SET bank_account = merges.into_id FROM merges
+	WHERE transaction.bank_account = merges.id;
+UPDATE api_keys SET account_ids = ARRAY(
+	SELECT id FROM unnest(account_ids) AS id WHERE id NOT IN (SELECT id FROM merges)
+) WHERE account_ids IS NOT NULL;
+DELETE FROM account WHERE id IN (SELECT id FROM merges);
+DROP TABLE merges;
+
+UPDATE account SET balance = COALESCE((
+	SELECT SUM(amount) FROM postings WHERE account_id = account.id
+), 0);
+
+ALTER TABLE account DROP CONSTRAINT account_user_id_kind_name_currency_key;
+ALTER TABLE account ADD UNIQUE (user_id, kind, name);
+ALTER TABLE account DROP COLUMN currency;
@@ -0,0 +1,43 @@
+-- every account keeps its books in one currency, set when it's opened
+ALTER TABLE account ADD COLUMN currency text REFERENCES currencies (code);
+
+-- accounts take the currency most of their postings are in
+UPDATE account SET currency = COALESCE((
+	SELECT currency FROM postings WHERE account_id = account.id
+	GROUP BY currency ORDER BY count(*) DESC, currency LIMIT 1
+), 'USD');
+ALTER TABLE account DROP CONSTRAINT account_user_id_kind_name_key;
+ALTER TABLE account ADD UNIQUE (user_id, kind, name, currency);
+
+-- postings in any other currency move to a new account of the same name
+-- in that currency, with the same members, along with the transactions
+-- behind them
+CREATE TEMPORARY TABLE splits AS
+	SELECT DISTINCT postings.account_id, postings.currency
+	FROM postings JOIN account ON account.id = postings.account_id
+	WHERE postings.currency <> account.currency;
+ALTER TABLE splits ADD COLUMN id integer;
+UPDATE splits SET id = nextval('account_id_seq');
+
+INSERT INTO account(id, balance, user_id, name, kind, currency)
+	SELECT splits.id, 0, account.user_id, account.name, account.kind, splits.currency
+	FROM splits JOIN account ON account.id = splits.account_id;
+INSERT INTO account_members(account_id, user_id, role, added_at)
+	SELECT splits.id, account_members.user_id, account_members.role, account_members.added_at
+	FROM splits JOIN account_members ON account_members.account_id = splits.account_id;
+UPDATE postings SET account_id = splits.id FROM splits
+	WHERE postings.account_id = splits.account_id AND postings.currency = splits.currency;
+UPDATE transaction SET bank_account = splits.id FROM splits
+	WHERE transaction.bank_account = splits.account_id
+	AND transaction.currency = splits.currency;
+-- API keys restricted to an account get the ones split off it too
+UPDATE api_keys SET account_ids = account_ids || ARRAY(
+	SELECT id FROM splits WHERE account_id = ANY(api_keys.account_ids) ORDER BY id
+) WHERE account_ids IS NOT NULL;
+DROP TABLE splits;
+
+UPDATE account SET balance = COALESCE((
+	SELECT SUM(amount) FROM postings WHERE account_id = account.id
+), 0);
+
+ALTER TABLE account ALTER COLUMN currency SET NOT NULL;
@@ -0,0 +1,3 @@
+-- tokens minted for sessions started before this are refused, set when
+-- the password is reset. NULL if it never was
+ALTER TABLE users ADD COLUMN tokens_valid_after timestamp;
@@ -1,23 +1,73 @@
 use super::DatabaseResult;
-use crate::authentication::gaurd;
+use crate::audit::{self, Action as AuditAction};
+use crate::authentication::gaurd::{Require, Scoped};
+use crate::authentication::permission::MaintenanceRun;
+use crate::authentication::scope::{AccountsRead, AccountsWrite};
+use crate::authentication::ClientInfo;
+use crate::authorization::{authorize, Action, Caller};
 use crate::db::DbConn;
-use crate::models::{Account, NewAccount};
+use crate::models::{Account, AccountKind, Amount, CurrencyCode, User};
 use rocket::serde::json::Json;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct AccountData {
     pub name: String,
+}
+
+/// A new account, a bank account unless another kind is asked for, in
+/// the caller's base currency unless another one is
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NewAccountData {
+    pub name: String,
+    #[serde(default)]
+    pub kind: AccountKind,
+    #[serde(default)]
+    pub currency: Option<CurrencyCode>,
+}
+
+/// An account as the API shows it
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AccountView {
+    pub id: i32,
+    pub name: String,
+    pub kind: AccountKind,
+    /// every transaction on it is in this currency
+    pub currency: CurrencyCode,
+    /// debits count positive and credits negative
+    pub balance: Amount,
+    /// username of whoever opened it
     pub user_id: String,
 }
 
+impl From<Account> for AccountView {
+    fn from(account: Account) -> AccountView {
+        AccountView {
+            id: account.id,
+            name: account.name,
+            kind: account.kind,
+            currency: account.currency,
+            balance: account.balance,
+            user_id: account.user_id,
+        }
+    }
+}
+
 // Admin User has no control over other user's accounts
 
 /// get all account
+///
+/// API keys restricted to some accounts only see those
 #[get("/accounts")]
-pub fn get_all_accounts(user: gaurd::UserGaurd, mut conn: DbConn) -> Option<Json<Vec<Account>>> {
-    if let DatabaseResult::Succeful(acc_vec) = Account::all(&mut conn, user.username) {
-        Some(Json(acc_vec))
+pub fn get_all_accounts(
+    user: Scoped<AccountsRead>,
+    mut conn: DbConn,
+) -> Option<Json<Vec<AccountView>>> {
+    if let DatabaseResult::Succeful(mut acc_vec) = Account::all(&mut conn, user.username.clone()) {
+        acc_vec.retain(|acc| user.may_access_account(acc.id));
+        Some(Json(acc_vec.into_iter().map(AccountView::from).collect()))
     } else {
         None
     }
@@ -27,32 +77,54 @@ pub fn get_all_accounts(user: gaurd::UserGaurd, mut conn: DbConn) -> Option<Json
 #[get("/accounts/<identifier>")]
 pub fn get_account(
     identifier: i32,
-    user: gaurd::UserGaurd,
+    user: Scoped<AccountsRead>,
     mut conn: DbConn,
-) -> Option<Json<Account>> {
-    if let DatabaseResult::Succeful(acc) = Account::get(&mut conn, identifier) {
-        Some(Json(acc))
+) -> Option<Json<AccountView>> {
+    if let DatabaseResult::Succeful(acc) =
+        authorize::<Account>(&mut conn, &user, identifier, Action::Read)
+    {
+        Some(Json(acc.into()))
     } else {
         None
     }
 }
 
-/// create an account
+/// create an account owned by the caller, a bank account or another
+/// account of their chart of accounts
+///
+/// API keys restricted to some accounts can't create new ones
 #[post("/accounts", format = "application/json", data = "<new_account>")]
 pub fn create_account(
-    new_account: Json<AccountData>,
-    user: gaurd::UserGaurd,
+    new_account: Json<NewAccountData>,
+    user: Scoped<AccountsWrite>,
     mut conn: DbConn,
-) -> Option<Json<Account>> {
-    let new_account = new_account.0;
-    if let DatabaseResult::Succeful(acc) = Account::add(&mut conn, &new_account.into()) {
-        Some(Json(acc))
+) -> Option<Json<AccountView>> {
+    if user.account_ids.is_some() {
+        return None;
+    }
+    let NewAccountData {
+        name,
+        kind,
+        currency,
+    } = new_account.0;
+    let currency = match currency {
+        Some(currency) => currency,
+        None => match User::get(&mut conn, &user.username) {
+            DatabaseResult::Succeful(owner) => owner.base_currency,
+            _ => return None,
+        },
+    };
+    let new_account = Account::new_account(name, user.username)
+        .kind(kind)
+        .currency(currency);
+    if let DatabaseResult::Succeful(acc) = Account::add(&mut conn, &new_account) {
+        Some(Json(acc.into()))
     } else {
         None
     }
 }
 
-// update an account
+// rename an account
 #[patch(
     "/accounts/<identifier>",
     format = "application/json",
@@ -60,28 +132,70 @@ pub fn create_account(
 )]
 pub fn update_account(
     identifier: i32,
-    account: Json<Account>,
-    user: gaurd::UserGaurd,
+    account: Json<AccountData>,
+    user: Scoped<AccountsWrite>,
     mut conn: DbConn,
-) -> Option<Json<Account>> {
-    let new_update = account.0;
-    if let DatabaseResult::Succeful(acc) = Account::update(&mut conn, identifier, &new_update) {
-        Some(Json(acc))
+) -> Option<Json<AccountView>> {
+    if let DatabaseResult::NotFound =
+        authorize::<Account>(&mut conn, &user, identifier, Action::Update)
+    {
+        return None;
+    }
+    if let DatabaseResult::Succeful(acc) = Account::rename(&mut conn, identifier, &account.0.name) {
+        Some(Json(acc.into()))
     } else {
         None
     }
 }
 
-// delete an account
+// delete an account with its transactions, 404 while other entries still post to it
 #[delete("/accounts/<identifier>")]
 pub fn delete_account(
     identifier: i32,
-    user: gaurd::UserGaurd,
+    user: Scoped<AccountsWrite>,
+    client: ClientInfo,
     mut conn: DbConn,
-) -> Option<Json<Account>> {
+) -> Option<Json<AccountView>> {
+    if let DatabaseResult::NotFound =
+        authorize::<Account>(&mut conn, &user, identifier, Action::Delete)
+    {
+        return None;
+    }
     if let DatabaseResult::Succeful(acc) = Account::delete_by_id(&mut conn, identifier) {
-        Some(Json(acc))
+        let ip = client.ip.as_deref();
+        audit::record(
+            &mut conn,
+            AuditAction::AccountDeleted,
+            Some(&user.username),
+            Some(&acc.id.to_string()),
+            ip,
+        );
+        Some(Json(acc.into()))
     } else {
         None
     }
 }
+
+/// How many accounts had their balance rebuilt
+#[derive(Serialize)]
+pub struct Recomputed {
+    pub accounts: usize,
+}
+
+/// POST to rebuild every balance from its transactions (requires maintenance:run)
+#[post("/admin/accounts/balances")]
+pub fn super_recompute_balances(
+    admin: Require<MaintenanceRun>,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Json<Recomputed>> {
+    match Account::recompute_balances(&mut conn) {
+        DatabaseResult::Succeful(accounts) => {
+            let ip = client.ip.as_deref();
+            let action = AuditAction::BalancesRecomputed;
+            audit::record(&mut conn, action, Some(&admin.username), None, ip);
+            Some(Json(Recomputed { accounts }))
+        }
+        _ => None,
+    }
+}
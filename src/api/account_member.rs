@@ -0,0 +1,122 @@
+use super::DatabaseResult;
+use crate::authentication::gaurd::{self, Scoped};
+use crate::authentication::scope::AccountsRead;
+use crate::authorization::{authorize, Action};
+use crate::db::DbConn;
+use crate::models::{Account, AccountMember, MemberRole, NewAccountMember};
+use rocket::serde::json::Json;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct MemberData {
+    pub username: String,
+    pub role: MemberRole,
+}
+
+#[derive(Deserialize)]
+pub struct MemberRoleData {
+    pub role: MemberRole,
+}
+
+// members are managed with a login, so an API key can't share accounts
+
+/// GET to list an account's members
+#[get("/accounts/<identifier>/members")]
+pub fn get_account_members(
+    identifier: i32,
+    user: Scoped<AccountsRead>,
+    mut conn: DbConn,
+) -> Option<Json<Vec<AccountMember>>> {
+    if let DatabaseResult::NotFound =
+        authorize::<Account>(&mut conn, &user, identifier, Action::Read)
+    {
+        return None;
+    }
+    match AccountMember::all(&mut conn, identifier) {
+        DatabaseResult::Succeful(member_vec) => Some(Json(member_vec)),
+        _ => None,
+    }
+}
+
+/// POST to invite a user to an account (owners only)
+#[post(
+    "/accounts/<identifier>/members",
+    format = "application/json",
+    data = "<member>"
+)]
+pub fn add_account_member(
+    identifier: i32,
+    member: Json<MemberData>,
+    user: gaurd::UserGaurd,
+    mut conn: DbConn,
+) -> Option<Json<AccountMember>> {
+    if let DatabaseResult::NotFound = authorize::<Account>(
+        &mut conn,
+        user.username.as_str(),
+        identifier,
+        Action::Manage,
+    ) {
+        return None;
+    }
+    let member = member.0;
+    let new_member = NewAccountMember::new(identifier, member.username, member.role);
+    match AccountMember::add(&mut conn, &new_member) {
+        DatabaseResult::Succeful(member) => Some(Json(member)),
+        _ => None,
+    }
+}
+
+/// PATCH to change a member's role (owners only)
+///
+/// fails if it would leave the account without an owner
+#[patch(
+    "/accounts/<identifier>/members/<username>",
+    format = "application/json",
+    data = "<update>"
+)]
+pub fn update_account_member(
+    identifier: i32,
+    username: &str,
+    update: Json<MemberRoleData>,
+    user: gaurd::UserGaurd,
+    mut conn: DbConn,
+) -> Option<Json<AccountMember>> {
+    if let DatabaseResult::NotFound = authorize::<Account>(
+        &mut conn,
+        user.username.as_str(),
+        identifier,
+        Action::Manage,
+    ) {
+        return None;
+    }
+    match AccountMember::set_role(&mut conn, identifier, username, update.role) {
+        DatabaseResult::Succeful(member) => Some(Json(member)),
+        _ => None,
+    }
+}
+
+/// DELETE to remove a member (owners only), or to leave an account
+///
+/// fails for the last owner
+#[delete("/accounts/<identifier>/members/<username>")]
+pub fn delete_account_member(
+    identifier: i32,
+    username: &str,
+    user: gaurd::UserGaurd,
+    mut conn: DbConn,
+) -> Option<Json<AccountMember>> {
+    let action = if username == user.username {
+        Action::Read
+    } else {
+        Action::Manage
+    };
+    if let DatabaseResult::NotFound =
+        authorize::<Account>(&mut conn, user.username.as_str(), identifier, action)
+    {
+        return None;
+    }
+    match AccountMember::delete(&mut conn, identifier, username) {
+        DatabaseResult::Succeful(member) => Some(Json(member)),
+        _ => None,
+    }
+}
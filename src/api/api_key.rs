@@ -0,0 +1,88 @@
+use super::DatabaseResult;
+use crate::authentication::{gaurd, scope};
+use crate::authorization::{authorize, Action};
+use crate::db::DbConn;
+use crate::models::{Account, ApiKey, NewApiKey};
+use chrono::NaiveDateTime;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct ApiKeyData {
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// restricts the key to some of the caller's accounts
+    pub account_ids: Option<Vec<i32>>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// A key that was just created, `key` is never shown again
+#[derive(Serialize)]
+pub struct CreatedApiKey {
+    pub key: String,
+    pub api_key: ApiKey,
+}
+
+// keys are managed with a login, so a key can't mint itself more power,
+// and not while impersonating, see `gaurd::CredentialGaurd`
+
+/// GET to list the caller's API keys
+#[get("/api_keys")]
+pub fn get_api_keys(user: gaurd::UserGaurd, mut conn: DbConn) -> Option<Json<Vec<ApiKey>>> {
+    match ApiKey::all(&mut conn, &user.username) {
+        DatabaseResult::Succeful(key_vec) => Some(Json(key_vec)),
+        _ => None,
+    }
+}
+
+/// POST to create an API key
+///
+/// fails if a scope is unknown or an account isn't the caller's
+#[post("/api_keys", format = "application/json", data = "<new_key>")]
+pub fn create_api_key(
+    new_key: Json<ApiKeyData>,
+    user: gaurd::CredentialGaurd,
+    mut conn: DbConn,
+) -> Option<Json<CreatedApiKey>> {
+    let new_key = new_key.0;
+    if new_key.scopes.is_empty()
+        || !new_key
+            .scopes
+            .iter()
+            .all(|s| scope::ALL.contains(&s.as_str()))
+    {
+        return None;
+    }
+    for account_id in new_key.account_ids.iter().flatten() {
+        if let DatabaseResult::NotFound =
+            authorize::<Account>(&mut conn, user.username.as_str(), *account_id, Action::Read)
+        {
+            return None;
+        }
+    }
+
+    let (new_key, key) = NewApiKey::new(
+        user.username,
+        new_key.name,
+        new_key.scopes,
+        new_key.account_ids,
+        new_key.expires_at,
+    );
+    match ApiKey::add(&mut conn, &new_key) {
+        DatabaseResult::Succeful(api_key) => Some(Json(CreatedApiKey { key, api_key })),
+        _ => None,
+    }
+}
+
+/// DELETE to revoke one of the caller's API keys
+#[delete("/api_keys/<identifier>")]
+pub fn delete_api_key(
+    identifier: i32,
+    user: gaurd::CredentialGaurd,
+    mut conn: DbConn,
+) -> Option<Json<ApiKey>> {
+    match ApiKey::delete(&mut conn, identifier, &user.username) {
+        DatabaseResult::Succeful(api_key) => Some(Json(api_key)),
+        _ => None,
+    }
+}
@@ -0,0 +1,49 @@
+use super::DatabaseResult;
+use crate::authentication::gaurd::Require;
+use crate::authentication::permission::AuditRead;
+use crate::db::DbConn;
+use crate::models::{AuditEvent, AuditFilter};
+use chrono::{NaiveDate, NaiveDateTime};
+use rocket::serde::json::Json;
+
+/// parses `2022-09-20T10:00:00`, or `2022-09-20` for the start of that day
+fn parse_time(time: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(time, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// GET to search the audit log, newest first (requires audit:read)
+///
+/// every filter is optional, `from` is inclusive and `until` exclusive,
+/// both in UTC. Fails if a time can't be parsed
+#[get("/admin/audit?<actor>&<action>&<from>&<until>")]
+pub fn super_get_audit_events(
+    actor: Option<String>,
+    action: Option<String>,
+    from: Option<&str>,
+    until: Option<&str>,
+    _admin: Require<AuditRead>,
+    mut conn: DbConn,
+) -> Option<Json<Vec<AuditEvent>>> {
+    let filter = AuditFilter {
+        actor,
+        action,
+        from: match from {
+            Some(from) => Some(parse_time(from)?),
+            None => None,
+        },
+        until: match until {
+            Some(until) => Some(parse_time(until)?),
+            None => None,
+        },
+    };
+    match AuditEvent::search(&mut conn, &filter) {
+        DatabaseResult::Succeful(event_vec) => Some(Json(event_vec)),
+        _ => None,
+    }
+}
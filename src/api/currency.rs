@@ -0,0 +1,151 @@
+use super::DatabaseResult;
+use crate::audit::{self, Action as AuditAction};
+use crate::authentication::gaurd::{Require, Scoped};
+use crate::authentication::permission::{CurrenciesWrite, RatesImport};
+use crate::authentication::scope::TransactionsRead;
+use crate::authentication::ClientInfo;
+use crate::db::DbConn;
+use crate::models::{Currency, CurrencyCode, ExchangeRate, NewCurrency, Rate};
+use crate::rates;
+use chrono::{Local, NaiveDate};
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+
+/// A custom unit like a crypto currency or loyalty points
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CurrencyData {
+    pub code: CurrencyCode,
+    pub name: String,
+    /// digits after the point amounts are rounded to, at most 4
+    pub minor_units: i16,
+    pub symbol: String,
+}
+
+/// Get the currencies amounts can be in
+#[get("/currencies")]
+pub fn get_currencies(
+    _user: Scoped<TransactionsRead>,
+    mut conn: DbConn,
+) -> Option<Json<Vec<Currency>>> {
+    match Currency::all(&mut conn) {
+        DatabaseResult::Succeful(currency_vec) => Some(Json(currency_vec)),
+        _ => None,
+    }
+}
+
+/// POST to add a custom currency (requires currencies:write)
+#[post(
+    "/admin/currencies",
+    format = "application/json",
+    data = "<new_currency>"
+)]
+pub fn super_create_currency(
+    new_currency: Json<CurrencyData>,
+    admin: Require<CurrenciesWrite>,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Json<Currency>> {
+    let data = new_currency.0;
+    let new_currency = NewCurrency::new(data.code, data.name, data.minor_units, data.symbol);
+    if !new_currency.is_valid() {
+        return None;
+    }
+    match Currency::add(&mut conn, &new_currency) {
+        DatabaseResult::Succeful(currency) => {
+            let ip = client.ip.as_deref();
+            let code = Some(currency.code.as_str());
+            let action = AuditAction::CurrencyAdded;
+            audit::record(&mut conn, action, Some(&admin.username), code, ip);
+            Some(Json(currency))
+        }
+        _ => None,
+    }
+}
+
+/// DELETE to remove a custom currency nothing is booked in (requires
+/// currencies:write), ISO 4217 currencies can't be removed
+#[delete("/admin/currencies/<code>")]
+pub fn super_delete_currency(
+    code: &str,
+    admin: Require<CurrenciesWrite>,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Json<Currency>> {
+    let code = code.parse::<CurrencyCode>().ok()?;
+    match Currency::delete(&mut conn, &code) {
+        DatabaseResult::Succeful(currency) => {
+            let ip = client.ip.as_deref();
+            let code = Some(currency.code.as_str());
+            let action = AuditAction::CurrencyDeleted;
+            audit::record(&mut conn, action, Some(&admin.username), code, ip);
+            Some(Json(currency))
+        }
+        _ => None,
+    }
+}
+
+/// The rate between two currencies used for a day
+#[derive(Serialize, Debug, PartialEq)]
+pub struct RateView {
+    pub from: CurrencyCode,
+    pub to: CurrencyCode,
+    pub date: NaiveDate,
+    /// what one unit of `from` buys of `to`
+    pub rate: Rate,
+}
+
+/// Get the rate from one currency to another, the stored one nearest to
+/// `date` or today
+#[get("/exchange-rates/<from>/<to>?<date>")]
+pub fn get_exchange_rate(
+    from: &str,
+    to: &str,
+    date: Option<&str>,
+    _user: Scoped<TransactionsRead>,
+    mut conn: DbConn,
+) -> Option<Json<RateView>> {
+    let from = from.parse::<CurrencyCode>().ok()?;
+    let to = to.parse::<CurrencyCode>().ok()?;
+    let date = match date {
+        Some(date) => date.parse::<NaiveDate>().ok()?,
+        None => Local::today().naive_local(),
+    };
+    match ExchangeRate::rate(&mut conn, &from, &to, date) {
+        DatabaseResult::Succeful(rate) => Some(Json(RateView {
+            from,
+            to,
+            date,
+            rate,
+        })),
+        _ => None,
+    }
+}
+
+/// How many exchange rates an import stored
+#[derive(Serialize)]
+pub struct Imported {
+    pub rates: usize,
+}
+
+/// POST to import the ECB reference rates from the file ECB_RATES_FILE
+/// points at (requires rates:import)
+#[post("/admin/exchange-rates/import")]
+pub fn super_import_exchange_rates(
+    admin: Require<RatesImport>,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Json<Imported>> {
+    match rates::import_configured(&mut conn) {
+        Ok(count) => {
+            let ip = client.ip.as_deref();
+            let action = AuditAction::RatesImported;
+            audit::record(&mut conn, action, Some(&admin.username), None, ip);
+            Some(Json(Imported { rates: count }))
+        }
+        Err(err) => {
+            warn!("importing exchange rates failed: {:?}", err);
+            None
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use super::DatabaseResult;
+use crate::audit::{self, Action as AuditAction};
+use crate::authentication::gaurd::Scoped;
+use crate::authentication::scope::{TransactionsRead, TransactionsWrite};
+use crate::authentication::ClientInfo;
+use crate::authorization::{authorize, authorize_entry, Action};
+use crate::db::DbConn;
+use crate::models::{
+    Account, AccountBalance, AccountKind, Amount, BalanceSheet, CurrencyCode, JournalEntry,
+    NewJournalEntry, NewPosting, Posting, User,
+};
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+
+/// A new journal entry, its postings have to add up to zero in every
+/// currency, checked when the body is parsed
+#[derive(Deserialize, Debug)]
+#[serde(try_from = "UncheckedEntryData")]
+pub struct EntryData {
+    pub description: String,
+    pub time: NaiveDate,
+    pub postings: Vec<NewPosting>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UncheckedEntryData {
+    description: String,
+    #[serde(default)]
+    time: Option<NaiveDate>,
+    postings: Vec<PostingData>,
+}
+
+/// A line of a new entry, debits are positive and credits negative
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PostingData {
+    account_id: i32,
+    amount: Amount,
+    currency: CurrencyCode,
+}
+
+impl TryFrom<UncheckedEntryData> for EntryData {
+    type Error = &'static str;
+
+    fn try_from(data: UncheckedEntryData) -> Result<EntryData, &'static str> {
+        let postings = data
+            .postings
+            .into_iter()
+            .map(|p| NewPosting::new(p.account_id, p.amount, p.currency))
+            .collect::<Vec<_>>();
+        if !NewPosting::balance(&postings) {
+            return Err("postings have to add up to zero in every currency");
+        }
+        Ok(EntryData {
+            description: data.description,
+            time: data.time.unwrap_or_else(|| Local::today().naive_local()),
+            postings,
+        })
+    }
+}
+
+/// A posting as the API shows it
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PostingView {
+    pub account_id: i32,
+    pub amount: Amount,
+    pub currency: CurrencyCode,
+}
+
+impl From<Posting> for PostingView {
+    fn from(posting: Posting) -> PostingView {
+        PostingView {
+            account_id: posting.account_id,
+            amount: posting.amount,
+            currency: posting.currency,
+        }
+    }
+}
+
+/// A journal entry as the API shows it, decrypted
+#[derive(Serialize, Debug, PartialEq)]
+pub struct EntryView {
+    pub id: i32,
+    pub description: Option<String>,
+    pub time: NaiveDate,
+    pub created_at: NaiveDateTime,
+    /// who made it, None once they deleted their data
+    pub user_id: Option<String>,
+    pub postings: Vec<PostingView>,
+}
+
+impl From<(JournalEntry, Vec<Posting>)> for EntryView {
+    fn from((entry, posting_vec): (JournalEntry, Vec<Posting>)) -> EntryView {
+        EntryView {
+            id: entry.id,
+            description: entry.description,
+            time: entry.time,
+            created_at: entry.created_at,
+            user_id: entry.user_id,
+            postings: posting_vec.into_iter().map(PostingView::from).collect(),
+        }
+    }
+}
+
+/// A line of the trial balance, the balance shown on the side it's on
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TrialBalanceView {
+    pub account_id: i32,
+    pub name: String,
+    pub kind: AccountKind,
+    pub currency: CurrencyCode,
+    pub debit: Amount,
+    pub credit: Amount,
+}
+
+impl From<AccountBalance> for TrialBalanceView {
+    fn from(line: AccountBalance) -> TrialBalanceView {
+        let (debit, credit) = match line.balance.is_negative() {
+            true => (Amount::zero(), -line.balance),
+            false => (line.balance, Amount::zero()),
+        };
+        TrialBalanceView {
+            account_id: line.account_id,
+            name: line.name,
+            kind: line.kind,
+            currency: line.currency,
+            debit,
+            credit,
+        }
+    }
+}
+
+/// A balance sheet in one currency as the API shows it
+#[derive(Serialize, Debug, PartialEq)]
+pub struct BalanceSheetView {
+    pub currency: CurrencyCode,
+    pub assets: Amount,
+    pub liabilities: Amount,
+    pub equity: Amount,
+    pub net_income: Amount,
+}
+
+impl From<BalanceSheet> for BalanceSheetView {
+    fn from(sheet: BalanceSheet) -> BalanceSheetView {
+        BalanceSheetView {
+            currency: sheet.currency,
+            assets: sheet.assets,
+            liabilities: sheet.liabilities,
+            equity: sheet.equity,
+            net_income: sheet.net_income,
+        }
+    }
+}
+
+/// Post to book a journal entry, like a split or a card payment
+///
+/// the caller has to be allowed to add to every account and they all have
+/// to be in one owner's books
+#[post("/ledger/entries", format = "application/json", data = "<new_entry>")]
+pub fn create_entry(
+    new_entry: Json<EntryData>,
+    user: Scoped<TransactionsWrite>,
+    mut conn: DbConn,
+) -> Option<Json<EntryView>> {
+    let data = new_entry.0;
+    let mut owners = Vec::new();
+    for posting in &data.postings {
+        match authorize::<Account>(&mut conn, &user, posting.account_id, Action::Update) {
+            DatabaseResult::Succeful(acc) => owners.push(acc.user_id),
+            _ => return None,
+        }
+    }
+    owners.dedup();
+    if owners.len() != 1 {
+        return None;
+    }
+    let entry = NewJournalEntry {
+        user_id: user.username.clone(),
+        description: Some(data.description),
+        time: data.time,
+        postings: data.postings,
+    };
+    if let DatabaseResult::Succeful(added) = JournalEntry::add(&mut conn, &entry) {
+        Some(Json(added.into()))
+    } else {
+        None
+    }
+}
+
+/// Get to retrieve a journal entry with its postings
+#[get("/ledger/entries/<identifier>")]
+pub fn get_entry(
+    identifier: i32,
+    user: Scoped<TransactionsRead>,
+    mut conn: DbConn,
+) -> Option<Json<EntryView>> {
+    if let DatabaseResult::NotFound = authorize_entry(&mut conn, &user, identifier, Action::Read) {
+        return None;
+    }
+    if let DatabaseResult::Succeful(entry) = JournalEntry::get(&mut conn, identifier) {
+        Some(Json(entry.into()))
+    } else {
+        None
+    }
+}
+
+/// Delete to remove a journal entry, entries behind transactions are
+/// deleted through the transaction
+#[delete("/ledger/entries/<identifier>")]
+pub fn delete_entry(
+    identifier: i32,
+    user: Scoped<TransactionsWrite>,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Json<EntryView>> {
+    let posting_vec = match authorize_entry(&mut conn, &user, identifier, Action::Delete) {
+        DatabaseResult::Succeful(posting_vec) => posting_vec,
+        _ => return None,
+    };
+    if let DatabaseResult::Succeful(entry) = JournalEntry::delete(&mut conn, identifier) {
+        let ip = client.ip.as_deref();
+        audit::record(
+            &mut conn,
+            AuditAction::JournalEntryDeleted,
+            Some(&user.username),
+            Some(&entry.id.to_string()),
+            ip,
+        );
+        Some(Json((entry, posting_vec).into()))
+    } else {
+        None
+    }
+}
+
+/// the caller's base currency, reports are converted to it on request
+fn base_currency(conn: &mut DbConn, username: &str) -> Option<CurrencyCode> {
+    match User::get(conn, username) {
+        DatabaseResult::Succeful(user) => Some(user.base_currency),
+        _ => None,
+    }
+}
+
+/// Get the trial balance of the caller's books, with `in_base=true` in
+/// their base currency at today's rates
+///
+/// API keys restricted to some accounts only see part of the books, so
+/// they get no reports
+#[get("/ledger/trial-balance?<in_base>")]
+pub fn get_trial_balance(
+    in_base: Option<bool>,
+    user: Scoped<TransactionsRead>,
+    mut conn: DbConn,
+) -> Option<Json<Vec<TrialBalanceView>>> {
+    if user.account_ids.is_some() {
+        return None;
+    }
+    let mut balances = match AccountBalance::trial_balance(&mut conn, &user.username) {
+        DatabaseResult::Succeful(balances) => balances,
+        _ => return None,
+    };
+    if in_base.unwrap_or(false) {
+        let base = base_currency(&mut conn, &user.username)?;
+        let today = Local::today().naive_local();
+        balances = match AccountBalance::converted(&mut conn, &balances, &base, today) {
+            DatabaseResult::Succeful(converted) => converted,
+            _ => return None,
+        };
+    }
+    Some(Json(
+        balances.into_iter().map(TrialBalanceView::from).collect(),
+    ))
+}
+
+/// Get the balance sheet of the caller's books, one per currency or with
+/// `in_base=true` a single one in their base currency at today's rates
+#[get("/ledger/balance-sheet?<in_base>")]
+pub fn get_balance_sheet(
+    in_base: Option<bool>,
+    user: Scoped<TransactionsRead>,
+    mut conn: DbConn,
+) -> Option<Json<Vec<BalanceSheetView>>> {
+    if user.account_ids.is_some() {
+        return None;
+    }
+    let balances = match AccountBalance::trial_balance(&mut conn, &user.username) {
+        DatabaseResult::Succeful(balances) => balances,
+        _ => return None,
+    };
+    let mut sheets = BalanceSheet::from_balances(&balances);
+    if in_base.unwrap_or(false) {
+        let base = base_currency(&mut conn, &user.username)?;
+        let today = Local::today().naive_local();
+        sheets = match BalanceSheet::converted(&mut conn, &sheets, &base, today) {
+            DatabaseResult::Succeful(sheet) => vec![sheet],
+            _ => return None,
+        };
+    }
+    Some(Json(
+        sheets.into_iter().map(BalanceSheetView::from).collect(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocket::serde::json::from_str;
+
+    fn data(postings: &str) -> String {
+        format!(
+            r#"{{"description": "Card payment", "postings": [{}]}}"#,
+            postings
+        )
+    }
+
+    #[test]
+    fn entry_data_balanced() {
+        let split = data(
+            r#"{"account_id": 1, "amount": "-80", "currency": "USD"},
+            {"account_id": 2, "amount": "50", "currency": "USD"},
+            {"account_id": 3, "amount": "30", "currency": "USD"}"#,
+        );
+        let entry: EntryData = from_str(&split).unwrap();
+        assert_eq!(entry.postings.len(), 3);
+        assert_eq!(entry.time, Local::today().naive_local());
+
+        for invalid in [
+            r#"{"account_id": 1, "amount": "-80", "currency": "USD"},
+            {"account_id": 2, "amount": "79", "currency": "USD"}"#,
+            r#"{"account_id": 1, "amount": "-80", "currency": "USD"},
+            {"account_id": 2, "amount": "80", "currency": "CAD"}"#,
+            r#"{"account_id": 1, "amount": "-80", "currency": "USD"}"#,
+            "",
+        ] {
+            assert!(
+                from_str::<EntryData>(&data(invalid)).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
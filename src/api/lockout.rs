@@ -0,0 +1,21 @@
+use super::DatabaseResult;
+use crate::authentication::gaurd::Require;
+use crate::authentication::permission::SecurityRead;
+use crate::db::DbConn;
+use crate::models::Lockout;
+use rocket::serde::json::Json;
+
+/// GET to list login lockouts, newest first (requires security:read)
+///
+/// `?active=true` leaves out lockouts that already expired
+#[get("/admin/lockouts?<active>")]
+pub fn super_get_lockouts(
+    active: Option<bool>,
+    _admin: Require<SecurityRead>,
+    mut conn: DbConn,
+) -> Option<Json<Vec<Lockout>>> {
+    match Lockout::all(&mut conn, active.unwrap_or(false)) {
+        DatabaseResult::Succeful(lockout_vec) => Some(Json(lockout_vec)),
+        _ => None,
+    }
+}
@@ -1,13 +1,32 @@
 pub mod account;
+pub mod account_member;
+pub mod api_key;
+pub mod audit;
+pub mod currency;
+pub mod ledger;
+pub mod lockout;
+pub mod role;
+pub mod session;
+pub mod totp;
 pub mod transaction;
+pub mod transfer;
 pub mod user;
 
-use crate::establish_connection;
 use crate::models::result_variant::DatabaseResult;
 
 use account::*;
+use account_member::*;
+use api_key::*;
+use audit::*;
+use currency::*;
+use ledger::*;
+use lockout::*;
 use rocket::Route;
+use role::*;
+use session::*;
+use totp::*;
 use transaction::*;
+use transfer::*;
 use user::*;
 pub fn stage() -> Vec<Route> {
     routes![
@@ -15,19 +34,56 @@ pub fn stage() -> Vec<Route> {
         get_user,
         update_user,
         delete_user,
+        export_user,
+        get_user_deletion,
+        cancel_user_deletion,
         super_get_all_user,
         super_get_user,
         super_update_user,
         super_delete_user,
+        super_impersonate_user,
         create_transaction,
         get_account_all_transactions,
         get_transaction,
         delete_account_all_transactions,
         delete_transaction,
+        create_transfer,
+        get_transfer,
+        update_transfer,
+        delete_transfer,
+        create_entry,
+        get_entry,
+        delete_entry,
+        get_trial_balance,
+        get_balance_sheet,
         get_account,
         get_all_accounts,
         create_account,
         delete_account,
-        update_account
+        update_account,
+        super_recompute_balances,
+        get_currencies,
+        super_create_currency,
+        super_delete_currency,
+        get_exchange_rate,
+        super_import_exchange_rates,
+        get_account_members,
+        add_account_member,
+        update_account_member,
+        delete_account_member,
+        get_sessions,
+        delete_session,
+        delete_all_sessions,
+        super_get_lockouts,
+        get_totp,
+        enroll_totp,
+        confirm_totp,
+        replace_recovery_codes,
+        delete_totp,
+        super_update_role,
+        get_api_keys,
+        create_api_key,
+        delete_api_key,
+        super_get_audit_events
     ]
 }
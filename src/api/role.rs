@@ -0,0 +1,48 @@
+use super::DatabaseResult;
+use crate::audit::{self, Action};
+use crate::authentication::gaurd::Require;
+use crate::authentication::permission::RolesWrite;
+use crate::authentication::ClientInfo;
+use crate::db::DbConn;
+use crate::models::Role;
+use rocket::serde::json::Json;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct RoleData {
+    pub require_2fa: bool,
+}
+
+/// PATCH to change what a role requires (requires roles:write)
+///
+/// with `require_2fa` on, members can only use the role's permissions
+/// from sessions started with 2FA. Every change is audited
+#[patch("/admin/roles/<name>", format = "application/json", data = "<update>")]
+pub fn super_update_role(
+    name: &str,
+    update: Json<RoleData>,
+    admin: Require<RolesWrite>,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Json<Role>> {
+    match Role::set_require_2fa(&mut conn, name, update.require_2fa) {
+        DatabaseResult::Succeful(role) => {
+            let detail = if role.require_2fa {
+                "2FA required"
+            } else {
+                "2FA not required"
+            };
+            let ip = client.ip.as_deref();
+            audit::record_detail(
+                &mut conn,
+                Action::RoleUpdated,
+                Some(&admin.username),
+                Some(&role.name),
+                detail,
+                ip,
+            );
+            Some(Json(role))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,37 @@
+use super::DatabaseResult;
+use crate::authentication::gaurd;
+use crate::db::DbConn;
+use crate::models::Session;
+use rocket::serde::json::Json;
+
+/// GET to list the caller's active sessions
+#[get("/sessions")]
+pub fn get_sessions(user: gaurd::UserGaurd, mut conn: DbConn) -> Option<Json<Vec<Session>>> {
+    match Session::all(&mut conn, &user.username) {
+        DatabaseResult::Succeful(session_vec) => Some(Json(session_vec)),
+        _ => None,
+    }
+}
+
+/// DELETE to revoke one of the caller's sessions, its access tokens
+/// stop working along with its refresh token
+#[delete("/sessions/<identifier>")]
+pub fn delete_session(
+    identifier: i32,
+    user: gaurd::UserGaurd,
+    mut conn: DbConn,
+) -> Option<Json<Session>> {
+    match Session::delete(&mut conn, identifier, &user.username) {
+        DatabaseResult::Succeful(session) => Some(Json(session)),
+        _ => None,
+    }
+}
+
+/// DELETE to revoke every session of the caller (log out everywhere)
+#[delete("/sessions")]
+pub fn delete_all_sessions(user: gaurd::UserGaurd, mut conn: DbConn) -> Option<Json<Vec<Session>>> {
+    match Session::delete_all(&mut conn, &user.username) {
+        DatabaseResult::Succeful(session_vec) => Some(Json(session_vec)),
+        _ => None,
+    }
+}
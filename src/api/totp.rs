@@ -0,0 +1,156 @@
+use super::DatabaseResult;
+use crate::authentication::{gaurd, throttle, totp, ClientInfo};
+use crate::db::DbConn;
+use crate::models::{NewTotpSecret, RecoveryCode, TotpSecret};
+use chrono::Utc;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct TotpCodeData {
+    pub code: String,
+}
+
+/// Whether the caller has 2FA on
+#[derive(Serialize)]
+pub struct TotpStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+}
+
+/// A new secret for the caller's authenticator app, scanned from
+/// `otpauth_uri` or typed in as `secret`
+#[derive(Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// One time codes for when the authenticator app is lost, only shown once
+#[derive(Serialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+/// checks a code from the caller's authenticator app, wrong codes
+/// count as failed logins so they can't be guessed with a stolen token
+fn check_totp(
+    conn: &mut DbConn,
+    secret: &TotpSecret,
+    code: &str,
+    client: &ClientInfo,
+) -> Option<()> {
+    let ip = client.ip.as_deref();
+    if throttle::retry_after(conn, &secret.user_id, ip).is_some() {
+        return None;
+    }
+    let now = Utc::now().timestamp();
+    let used = totp::verify(&secret.secret, code, now, secret.last_used_step)
+        .map(|step| TotpSecret::use_step(conn, &secret.user_id, step));
+    match used {
+        Some(DatabaseResult::Succeful(_)) => Some(()),
+        _ => {
+            throttle::record(conn, &secret.user_id, ip, false);
+            None
+        }
+    }
+}
+
+/// GET to see whether the caller has 2FA on
+#[get("/users/totp")]
+pub fn get_totp(user: gaurd::UserGaurd, mut conn: DbConn) -> Option<Json<TotpStatus>> {
+    let enabled = matches!(
+        TotpSecret::get_confirmed(&mut conn, &user.username),
+        DatabaseResult::Succeful(_)
+    );
+    match RecoveryCode::remaining(&mut conn, &user.username) {
+        DatabaseResult::Succeful(recovery_codes_left) => Some(Json(TotpStatus {
+            enabled,
+            recovery_codes_left,
+        })),
+        _ => None,
+    }
+}
+
+/// POST to start setting up 2FA, 2FA stays off until confirmed
+///
+/// starting over replaces the unconfirmed secret, fails if 2FA is already on
+#[post("/users/totp")]
+pub fn enroll_totp(user: gaurd::CredentialGaurd, mut conn: DbConn) -> Option<Json<TotpEnrollment>> {
+    let new_secret = NewTotpSecret::new(user.username.clone(), totp::generate_secret());
+    match TotpSecret::enroll(&mut conn, &new_secret) {
+        DatabaseResult::Succeful(secret) => Some(Json(TotpEnrollment {
+            secret: totp::encode_secret(&secret.secret),
+            otpauth_uri: totp::otpauth_uri(&secret.secret, &user.username),
+        })),
+        _ => None,
+    }
+}
+
+/// POST a code from the authenticator app to turn 2FA on, returns
+/// the recovery codes
+#[post("/users/totp/confirm", format = "application/json", data = "<data>")]
+pub fn confirm_totp(
+    data: Json<TotpCodeData>,
+    user: gaurd::CredentialGaurd,
+    mut conn: DbConn,
+) -> Option<Json<RecoveryCodes>> {
+    let secret = match TotpSecret::get(&mut conn, &user.username) {
+        DatabaseResult::Succeful(secret) if secret.confirmed_at.is_none() => secret,
+        _ => return None,
+    };
+    let now = Utc::now().timestamp();
+    let step = totp::verify(&secret.secret, &data.code, now, None)?;
+    match TotpSecret::confirm(&mut conn, &user.username, step) {
+        DatabaseResult::Succeful(_) => (),
+        _ => return None,
+    }
+    match RecoveryCode::replace(&mut conn, &user.username) {
+        DatabaseResult::Succeful(recovery_codes) => Some(Json(RecoveryCodes { recovery_codes })),
+        _ => None,
+    }
+}
+
+/// POST a code from the authenticator app to replace the recovery codes
+#[post(
+    "/users/totp/recovery_codes",
+    format = "application/json",
+    data = "<data>"
+)]
+pub fn replace_recovery_codes(
+    data: Json<TotpCodeData>,
+    user: gaurd::CredentialGaurd,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Json<RecoveryCodes>> {
+    let secret = match TotpSecret::get_confirmed(&mut conn, &user.username) {
+        DatabaseResult::Succeful(secret) => secret,
+        _ => return None,
+    };
+    check_totp(&mut conn, &secret, &data.code, &client)?;
+    match RecoveryCode::replace(&mut conn, &user.username) {
+        DatabaseResult::Succeful(recovery_codes) => Some(Json(RecoveryCodes { recovery_codes })),
+        _ => None,
+    }
+}
+
+/// DELETE with a code from the authenticator app to turn 2FA off
+#[delete("/users/totp", format = "application/json", data = "<data>")]
+pub fn delete_totp(
+    data: Json<TotpCodeData>,
+    user: gaurd::CredentialGaurd,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Json<TotpStatus>> {
+    let secret = match TotpSecret::get_confirmed(&mut conn, &user.username) {
+        DatabaseResult::Succeful(secret) => secret,
+        _ => return None,
+    };
+    check_totp(&mut conn, &secret, &data.code, &client)?;
+    TotpSecret::delete(&mut conn, &user.username);
+    RecoveryCode::delete_all(&mut conn, &user.username);
+    Some(Json(TotpStatus {
+        enabled: false,
+        recovery_codes_left: 0,
+    }))
+}
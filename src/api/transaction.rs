@@ -1,25 +1,104 @@
-use super::establish_connection;
 use super::DatabaseResult;
-use crate::authentication::gaurd;
+use crate::audit::{self, Action as AuditAction};
+use crate::authentication::gaurd::Scoped;
+use crate::authentication::scope::{TransactionsRead, TransactionsWrite};
+use crate::authentication::ClientInfo;
+use crate::authorization::{authorize, authorize_transfer, Action};
 use crate::db::DbConn;
-use crate::models::{CurrencyType, NewTransaction, Transaction};
-use diesel::PgConnection;
+use crate::models::{Account, Amount, CurrencyCode, NewTransaction, Transaction, TransactionKind};
+use chrono::NaiveDate;
 use rocket::serde::json::Json;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+/// A new transaction, values its kind can't have are refused when the
+/// body is parsed, see `TransactionKind::accepts`, and so are transfers
+/// which only come in pairs of legs
 #[derive(Deserialize, Debug)]
+#[serde(try_from = "UncheckedTransactionData")]
 pub struct TransactionData {
-    pub kind: bool,
+    pub kind: TransactionKind,
     pub title: String,
-    pub value: String,
-    pub currency: CurrencyType,
-    pub user_id: String,
+    pub value: Amount,
+    pub currency: CurrencyCode,
     pub bank_account: i32,
+    pub notes: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UncheckedTransactionData {
+    kind: TransactionKind,
+    title: String,
+    value: Amount,
+    currency: CurrencyCode,
+    bank_account: i32,
+    #[serde(default)]
+    notes: Option<String>,
+}
+
+impl TryFrom<UncheckedTransactionData> for TransactionData {
+    type Error = &'static str;
+
+    fn try_from(data: UncheckedTransactionData) -> Result<TransactionData, &'static str> {
+        if data.kind == TransactionKind::Transfer {
+            return Err("transfers are made through /transfers");
+        }
+        if !data.kind.accepts(&data.value) {
+            return Err(match data.kind.is_signed() {
+                true => "transaction values can't be zero",
+                false => "transaction values have to be positive",
+            });
+        }
+        Ok(TransactionData {
+            kind: data.kind,
+            title: data.title,
+            value: data.value,
+            currency: data.currency,
+            bank_account: data.bank_account,
+            notes: data.notes,
+        })
+    }
+}
+
+/// A transaction as the API shows it, decrypted
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TransactionView {
+    pub id: i32,
+    pub kind: TransactionKind,
+    pub title: String,
+    pub value: Amount,
+    pub currency: CurrencyCode,
+    pub time: NaiveDate,
+    pub notes: Option<String>,
+    /// who added it, None once they deleted their data
+    pub user_id: Option<String>,
+    pub bank_account: i32,
+    /// the transfer this is a leg of
+    pub transfer_id: Option<i32>,
+    /// the journal entry behind it
+    pub entry_id: i32,
+}
+
+impl From<Transaction> for TransactionView {
+    fn from(trans: Transaction) -> TransactionView {
+        TransactionView {
+            id: trans.id,
+            kind: trans.kind,
+            title: trans.title,
+            value: trans.value,
+            currency: trans.currency,
+            time: trans.time,
+            notes: trans.notes,
+            user_id: trans.user_id,
+            bank_account: trans.bank_account,
+            transfer_id: trans.transfer_id,
+            entry_id: trans.entry_id,
+        }
+    }
 }
 
 // admin has no control on user data
 
-/// Post to create a new transaction
+/// Post to create a new transaction on one of the caller's accounts
 #[post(
     "/transaction",
     format = "application/json",
@@ -27,12 +106,18 @@ pub struct TransactionData {
 )]
 pub fn create_transaction(
     new_transaction: Json<TransactionData>,
-    user: gaurd::UserGaurd,
+    user: Scoped<TransactionsWrite>,
     mut conn: DbConn,
-) -> Option<Json<Transaction>> {
+) -> Option<Json<TransactionView>> {
     let trans = new_transaction.0;
-    if let DatabaseResult::Succeful(trans) = Transaction::add(&mut conn, &trans.into()) {
-        Some(Json(trans))
+    if let DatabaseResult::NotFound =
+        authorize::<Account>(&mut conn, &user, trans.bank_account, Action::Update)
+    {
+        return None;
+    }
+    let trans = NewTransaction::from_data(trans, user.username);
+    if let DatabaseResult::Succeful(trans) = Transaction::add(&mut conn, &trans) {
+        Some(Json(trans.into()))
     } else {
         None
     }
@@ -42,11 +127,13 @@ pub fn create_transaction(
 #[get("/transaction/<identifier>")]
 pub fn get_transaction(
     identifier: i32,
-    user: gaurd::UserGaurd,
+    user: Scoped<TransactionsRead>,
     mut conn: DbConn,
-) -> Option<Json<Transaction>> {
-    if let DatabaseResult::Succeful(trans) = Transaction::get(&mut conn, identifier) {
-        Some(Json(trans))
+) -> Option<Json<TransactionView>> {
+    if let DatabaseResult::Succeful(trans) =
+        authorize::<Transaction>(&mut conn, &user, identifier, Action::Read)
+    {
+        Some(Json(trans.into()))
     } else {
         None
     }
@@ -56,40 +143,146 @@ pub fn get_transaction(
 #[get("/transaction?<account_id>")]
 pub fn get_account_all_transactions(
     account_id: i32,
-    user: gaurd::UserGaurd,
+    user: Scoped<TransactionsRead>,
     mut conn: DbConn,
-) -> Option<Json<Vec<Transaction>>> {
+) -> Option<Json<Vec<TransactionView>>> {
+    if let DatabaseResult::NotFound =
+        authorize::<Account>(&mut conn, &user, account_id, Action::Read)
+    {
+        return None;
+    }
     if let DatabaseResult::Succeful(trans_vec) = Transaction::all(&mut conn, account_id) {
-        Some(Json(trans_vec))
+        Some(Json(
+            trans_vec.into_iter().map(TransactionView::from).collect(),
+        ))
     } else {
         None
     }
 }
 
-/// Delete to remove a transaction
+/// Delete to remove a transaction, a leg of a transfer takes the whole
+/// transfer with it so the user has to be allowed on both accounts
 #[delete("/transaction/<identifier>")]
 pub fn delete_transaction(
     identifier: i32,
-    user: gaurd::UserGaurd,
+    user: Scoped<TransactionsWrite>,
+    client: ClientInfo,
     mut conn: DbConn,
-) -> Option<Json<Transaction>> {
+) -> Option<Json<TransactionView>> {
+    let trans = match authorize::<Transaction>(&mut conn, &user, identifier, Action::Delete) {
+        DatabaseResult::Succeful(trans) => trans,
+        _ => return None,
+    };
+    if let Some(transfer_id) = trans.transfer_id {
+        if let DatabaseResult::NotFound =
+            authorize_transfer(&mut conn, &user, transfer_id, Action::Delete)
+        {
+            return None;
+        }
+    }
     if let DatabaseResult::Succeful(trans) = Transaction::delete(&mut conn, identifier) {
-        Some(Json(trans))
+        let ip = client.ip.as_deref();
+        audit::record(
+            &mut conn,
+            AuditAction::TransactionDeleted,
+            Some(&user.username),
+            Some(&trans.id.to_string()),
+            ip,
+        );
+        Some(Json(trans.into()))
     } else {
         None
     }
 }
 
-/// Delete to remove an account's all transactions
+/// Delete to remove an account's all transactions, transfers go with
+/// their legs on other accounts
 #[delete("/transaction?<account_id>")]
 pub fn delete_account_all_transactions(
     account_id: i32,
-    user: gaurd::UserGaurd,
+    user: Scoped<TransactionsWrite>,
+    client: ClientInfo,
     mut conn: DbConn,
-) -> Option<Json<Vec<Transaction>>> {
+) -> Option<Json<Vec<TransactionView>>> {
+    if let DatabaseResult::NotFound =
+        authorize::<Account>(&mut conn, &user, account_id, Action::Delete)
+    {
+        return None;
+    }
+    let transfers = match Transaction::all(&mut conn, account_id) {
+        DatabaseResult::Succeful(trans_vec) => trans_vec.into_iter().filter_map(|t| t.transfer_id),
+        _ => return None,
+    };
+    for transfer_id in transfers {
+        if let DatabaseResult::NotFound =
+            authorize_transfer(&mut conn, &user, transfer_id, Action::Delete)
+        {
+            return None;
+        }
+    }
     if let DatabaseResult::Succeful(trans_vec) = Transaction::delete_all(&mut conn, account_id) {
-        Some(Json(trans_vec))
+        let ip = client.ip.as_deref();
+        let action = AuditAction::AccountTransactionsDeleted;
+        audit::record(
+            &mut conn,
+            action,
+            Some(&user.username),
+            Some(&account_id.to_string()),
+            ip,
+        );
+        Some(Json(
+            trans_vec.into_iter().map(TransactionView::from).collect(),
+        ))
     } else {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocket::serde::json::from_str;
+
+    fn data(kind: &str, value: &str) -> String {
+        format!(
+            r#"{{"kind": "{}", "title": "Rent", "value": {}, "currency": "USD", "bank_account": 1}}"#,
+            kind, value
+        )
+    }
+
+    #[test]
+    fn transaction_data_amounts() {
+        let trans: TransactionData = from_str(&data("expense", r#""1200.50""#)).unwrap();
+        assert_eq!(trans.value.to_string(), "1200.50");
+        assert_eq!(trans.kind, TransactionKind::Expense);
+        for invalid in [
+            r#""abc""#,
+            r#""0""#,
+            r#""-5""#,
+            r#""1.00001""#,
+            "12.5",
+            "null",
+        ] {
+            assert!(
+                from_str::<TransactionData>(&data("expense", invalid)).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn transaction_data_kinds() {
+        // only adjustments carry their own sign
+        let trans: TransactionData = from_str(&data("adjustment", r#""-5""#)).unwrap();
+        assert_eq!(trans.kind.signed(trans.value).to_string(), "-5.00");
+        assert!(from_str::<TransactionData>(&data("adjustment", r#""0""#)).is_err());
+        assert!(from_str::<TransactionData>(&data("refund", r#""-5""#)).is_err());
+        let trans: TransactionData = from_str(&data("refund", r#""5""#)).unwrap();
+        assert_eq!(trans.kind.signed(trans.value).to_string(), "5.00");
+        assert!(from_str::<TransactionData>(&data("true", r#""5""#)).is_err());
+        // a lone transfer leg would leave the clearing account uneven
+        assert!(from_str::<TransactionData>(&data("transfer", r#""-5""#)).is_err());
+        assert!(from_str::<TransactionData>(&data("transfer", r#""5""#)).is_err());
+    }
+}
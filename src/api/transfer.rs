@@ -0,0 +1,295 @@
+use super::transaction::TransactionView;
+use super::DatabaseResult;
+use crate::audit::{self, Action as AuditAction};
+use crate::authentication::gaurd::Scoped;
+use crate::authentication::scope::{TransactionsRead, TransactionsWrite};
+use crate::authentication::ClientInfo;
+use crate::authorization::{authorize, authorize_transfer, Action};
+use crate::db::DbConn;
+use crate::models::{
+    Account, Amount, CurrencyCode, Rate, Transfer, TransferChanges, TransferLegs, TransferOrder,
+};
+use chrono::NaiveDateTime;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+
+/// A new transfer between two accounts, checked when the body is parsed
+///
+/// `to_currency` defaults to `currency`, a rate is needed only when they
+/// differ and the fee is charged on top of `amount`
+#[derive(Deserialize, Debug)]
+#[serde(try_from = "UncheckedTransferData")]
+pub struct TransferData {
+    pub from_account: i32,
+    pub to_account: i32,
+    pub amount: Amount,
+    pub currency: CurrencyCode,
+    pub to_currency: CurrencyCode,
+    pub rate: Rate,
+    pub fee: Amount,
+    pub title: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UncheckedTransferData {
+    from_account: i32,
+    to_account: i32,
+    amount: Amount,
+    currency: CurrencyCode,
+    #[serde(default)]
+    to_currency: Option<CurrencyCode>,
+    #[serde(default)]
+    rate: Option<Rate>,
+    #[serde(default)]
+    fee: Amount,
+    #[serde(default)]
+    title: Option<String>,
+}
+
+impl TryFrom<UncheckedTransferData> for TransferData {
+    type Error = &'static str;
+
+    fn try_from(data: UncheckedTransferData) -> Result<TransferData, &'static str> {
+        if data.from_account == data.to_account {
+            return Err("transfers have to be between two different accounts");
+        }
+        if !data.amount.is_positive() {
+            return Err("transfer amounts have to be positive");
+        }
+        if data.fee.is_negative() {
+            return Err("transfer fees can't be negative");
+        }
+        let to_currency = data.to_currency.unwrap_or_else(|| data.currency.clone());
+        let rate = match (data.rate, to_currency == data.currency) {
+            (None, true) => Rate::one(),
+            (Some(rate), true) if rate == Rate::one() => rate,
+            (Some(_), true) => return Err("transfers in one currency have a rate of 1"),
+            (Some(rate), false) => rate,
+            (None, false) => return Err("transfers between currencies need a rate"),
+        };
+        if data.amount.convert(&rate).is_none() {
+            return Err("the received amount is too large");
+        }
+        Ok(TransferData {
+            from_account: data.from_account,
+            to_account: data.to_account,
+            amount: data.amount,
+            currency: data.currency,
+            to_currency,
+            rate,
+            fee: data.fee,
+            title: data.title.unwrap_or_else(|| "Transfer".to_string()),
+        })
+    }
+}
+
+/// What to change about a transfer, left out fields stay as they are
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TransferUpdate {
+    pub amount: Option<Amount>,
+    pub rate: Option<Rate>,
+    pub fee: Option<Amount>,
+}
+
+impl TransferUpdate {
+    /// whether the changes make sense for the transfer
+    fn is_valid(&self, legs: &TransferLegs) -> bool {
+        let same_currency = legs.sent.currency == legs.received.currency;
+        self.amount.as_ref().is_none_or(Amount::is_positive)
+            && self.fee.as_ref().is_none_or(|fee| !fee.is_negative())
+            && self
+                .rate
+                .as_ref()
+                .is_none_or(|rate| !same_currency || *rate == Rate::one())
+    }
+}
+
+/// A transfer as the API shows it, with its legs
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TransferView {
+    pub id: i32,
+    pub rate: Rate,
+    pub fee: Amount,
+    pub created_at: NaiveDateTime,
+    /// who made it, None once they deleted their data
+    pub user_id: Option<String>,
+    pub legs: Vec<TransactionView>,
+}
+
+impl From<(Transfer, TransferLegs)> for TransferView {
+    fn from((transfer, legs): (Transfer, TransferLegs)) -> TransferView {
+        TransferView {
+            id: transfer.id,
+            rate: transfer.rate,
+            fee: transfer.fee,
+            created_at: transfer.created_at,
+            user_id: transfer.user_id,
+            legs: legs
+                .into_vec()
+                .into_iter()
+                .map(TransactionView::from)
+                .collect(),
+        }
+    }
+}
+
+/// Post to move money between two accounts the caller may add to
+#[post("/transfers", format = "application/json", data = "<new_transfer>")]
+pub fn create_transfer(
+    new_transfer: Json<TransferData>,
+    user: Scoped<TransactionsWrite>,
+    mut conn: DbConn,
+) -> Option<Json<TransferView>> {
+    let data = new_transfer.0;
+    for account_id in [data.from_account, data.to_account] {
+        if let DatabaseResult::NotFound =
+            authorize::<Account>(&mut conn, &user, account_id, Action::Update)
+        {
+            return None;
+        }
+    }
+    let order = TransferOrder {
+        user_id: user.username.clone(),
+        from_account: data.from_account,
+        to_account: data.to_account,
+        amount: data.amount,
+        currency: data.currency,
+        to_currency: data.to_currency,
+        rate: data.rate,
+        fee: data.fee,
+        title: data.title,
+    };
+    if let DatabaseResult::Succeful(transfer) = Transfer::add(&mut conn, &order) {
+        Some(Json(transfer.into()))
+    } else {
+        None
+    }
+}
+
+/// Get to retrieve a transfer with its legs
+#[get("/transfers/<identifier>")]
+pub fn get_transfer(
+    identifier: i32,
+    user: Scoped<TransactionsRead>,
+    mut conn: DbConn,
+) -> Option<Json<TransferView>> {
+    if let DatabaseResult::NotFound = authorize_transfer(&mut conn, &user, identifier, Action::Read)
+    {
+        return None;
+    }
+    if let DatabaseResult::Succeful(transfer) = Transfer::get(&mut conn, identifier) {
+        Some(Json(transfer.into()))
+    } else {
+        None
+    }
+}
+
+/// Patch to change the amount, rate or fee of a transfer, both legs follow
+#[patch(
+    "/transfers/<identifier>",
+    format = "application/json",
+    data = "<update>"
+)]
+pub fn update_transfer(
+    identifier: i32,
+    update: Json<TransferUpdate>,
+    user: Scoped<TransactionsWrite>,
+    mut conn: DbConn,
+) -> Option<Json<TransferView>> {
+    if let DatabaseResult::NotFound =
+        authorize_transfer(&mut conn, &user, identifier, Action::Update)
+    {
+        return None;
+    }
+    match Transfer::get(&mut conn, identifier) {
+        DatabaseResult::Succeful((_, legs)) if update.is_valid(&legs) => (),
+        _ => return None,
+    }
+    let update = update.0;
+    let changes = TransferChanges {
+        amount: update.amount,
+        rate: update.rate,
+        fee: update.fee,
+    };
+    if let DatabaseResult::Succeful(transfer) = Transfer::update(&mut conn, identifier, &changes) {
+        Some(Json(transfer.into()))
+    } else {
+        None
+    }
+}
+
+/// Delete to remove a transfer with all its legs
+#[delete("/transfers/<identifier>")]
+pub fn delete_transfer(
+    identifier: i32,
+    user: Scoped<TransactionsWrite>,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Json<Vec<TransactionView>>> {
+    let legs = match authorize_transfer(&mut conn, &user, identifier, Action::Delete) {
+        DatabaseResult::Succeful(legs) => legs,
+        _ => return None,
+    };
+    if let DatabaseResult::Succeful(transfer) = Transfer::delete(&mut conn, identifier) {
+        let ip = client.ip.as_deref();
+        audit::record(
+            &mut conn,
+            AuditAction::TransferDeleted,
+            Some(&user.username),
+            Some(&transfer.id.to_string()),
+            ip,
+        );
+        Some(Json(legs.into_iter().map(TransactionView::from).collect()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocket::serde::json::from_str;
+
+    fn data(extra: &str) -> String {
+        format!(
+            r#"{{"from_account": 1, "to_account": 2, "amount": "100", "currency": "USD"{}}}"#,
+            extra
+        )
+    }
+
+    #[test]
+    fn transfer_data_currencies() {
+        let transfer: TransferData = from_str(&data("")).unwrap();
+        assert_eq!(transfer.to_currency, "USD".parse().unwrap());
+        assert_eq!(transfer.rate, Rate::one());
+        assert_eq!(transfer.fee, Amount::zero());
+        assert_eq!(transfer.title, "Transfer");
+
+        let transfer: TransferData = from_str(&data(
+            r#", "to_currency": "CAD", "rate": "1.35", "fee": "2""#,
+        ))
+        .unwrap();
+        assert_eq!(transfer.rate.to_string(), "1.35");
+
+        for invalid in [
+            r#", "to_currency": "CAD""#,
+            r#", "rate": "1.35""#,
+            r#", "fee": "-1""#,
+            r#", "user_id": "someone_else""#,
+        ] {
+            assert!(
+                from_str::<TransferData>(&data(invalid)).is_err(),
+                "{}",
+                invalid
+            );
+        }
+        assert!(from_str::<TransferData>(&data("").replace("100", "-100")).is_err());
+        let same_account = data("").replace(r#""to_account": 2"#, r#""to_account": 1"#);
+        assert!(from_str::<TransferData>(&same_account).is_err());
+        let huge = data(r#", "to_currency": "IRR", "rate": "42000""#)
+            .replace(r#""100""#, r#""999999999999""#);
+        assert!(from_str::<TransferData>(&huge).is_err());
+    }
+}
@@ -1,117 +1,511 @@
-use super::establish_connection;
 use super::DatabaseResult;
-use crate::authentication::gaurd;
+use crate::audit::{self, Action};
+use crate::authentication::gaurd::{self, Require};
 use crate::authentication::hasher::Hash;
-use crate::models::User;
+use crate::authentication::permission::{UsersDelete, UsersImpersonate, UsersRead, UsersWrite};
+use crate::authentication::{ClientInfo, TokenIssuer};
+use crate::models::{
+    CurrencyCode, DeletionRequest, NewDeletionRequest, User, UserChanges, UserExport,
+};
 use crate::DbConn;
+use chrono::NaiveDateTime;
+use rocket::http::Header;
 use rocket::serde::json::Json;
-use serde::Deserialize;
+use rocket::State;
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Clone)]
 pub struct UserData {
     pub name: String,
     pub username: String,
     pub password: String,
+    pub email: Option<String>,
 }
 
-/// GET to retrieve all users (Admin level)
+/// What a user may change about themselves, unknown fields like `role`
+/// are refused rather than ignored
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct UserUpdate {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    /// a new password, hashed before it's stored
+    pub password: Option<String>,
+    /// the currency reports are converted to, has to be in the registry
+    pub base_currency: Option<CurrencyCode>,
+}
+
+impl From<UserUpdate> for UserChanges {
+    fn from(update: UserUpdate) -> UserChanges {
+        UserChanges {
+            name: update.name,
+            email: update.email,
+            password: update.password.map(Hash::hash),
+            base_currency: update.base_currency,
+            role: None,
+        }
+    }
+}
+
+/// What an admin may change about a user, what they can change about
+/// themselves and their role
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AdminUserUpdate {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    /// a new password, hashed before it's stored
+    pub password: Option<String>,
+    pub base_currency: Option<CurrencyCode>,
+    /// has to be one of the roles table
+    pub role: Option<String>,
+}
+
+impl From<AdminUserUpdate> for UserChanges {
+    fn from(update: AdminUserUpdate) -> UserChanges {
+        UserChanges {
+            name: update.name,
+            email: update.email,
+            password: update.password.map(Hash::hash),
+            base_currency: update.base_currency,
+            role: update.role,
+        }
+    }
+}
+
+/// A user as the API shows them, without their password hash
+#[derive(Serialize, Debug, PartialEq)]
+pub struct UserView {
+    pub name: String,
+    pub username: String,
+    pub role: String,
+    pub email: Option<String>,
+    pub base_currency: CurrencyCode,
+}
+
+impl From<User> for UserView {
+    fn from(user: User) -> UserView {
+        UserView {
+            name: user.name,
+            username: user.username,
+            role: user.role,
+            email: user.email,
+            base_currency: user.base_currency,
+        }
+    }
+}
+
+/// GET to retrieve all users (requires users:read)
 #[get("/admin/users")]
-pub fn super_get_all_user(admin: gaurd::AdminGaurd, mut conn: DbConn) -> Option<Json<Vec<User>>> {
+pub fn super_get_all_user(
+    admin: Require<UsersRead>,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Json<Vec<UserView>>> {
+    let ip = client.ip.as_deref();
+    audit::record(
+        &mut conn,
+        Action::AdminListedUsers,
+        Some(&admin.username),
+        None,
+        ip,
+    );
     match User::all(&mut conn) {
-        DatabaseResult::Succeful(user_vec) => Some(Json(user_vec)),
+        DatabaseResult::Succeful(user_vec) => {
+            Some(Json(user_vec.into_iter().map(UserView::from).collect()))
+        }
         _ => None,
     }
 }
 
-/// GET to retrieve a user (Admin level)
+/// GET to retrieve a user (requires users:read)
 #[get("/admin/users?<username>")]
 pub fn super_get_user(
     username: &str,
-    user: gaurd::AdminGaurd,
+    admin: Require<UsersRead>,
+    client: ClientInfo,
     mut conn: DbConn,
-) -> Option<Json<User>> {
+) -> Option<Json<UserView>> {
+    let ip = client.ip.as_deref();
+    audit::record(
+        &mut conn,
+        Action::AdminViewedUser,
+        Some(&admin.username),
+        Some(username),
+        ip,
+    );
     match User::get(&mut conn, username) {
-        DatabaseResult::Succeful(user) => Some(Json(user)),
+        DatabaseResult::Succeful(user) => Some(Json(user.into())),
         _ => None,
     }
 }
 
-/// PATCH to update a user info (Admin level)
+/// PATCH to update a user info, their role included (requires users:write)
+///
+/// fails if the role doesn't exist, role changes are audited on their own
 #[patch(
     "/admin/users?<username>",
     format = "application/json",
     data = "<update>"
 )]
 pub fn super_update_user(
-    update: Json<User>,
+    update: Json<AdminUserUpdate>,
     username: &str,
-    user: gaurd::AdminGaurd,
+    admin: Require<UsersWrite>,
+    client: ClientInfo,
     mut conn: DbConn,
-) -> Option<Json<User>> {
-    let mut update = update.0;
-    update.password = update.password.hash();
-    if !username.eq(&update.username) {
-        return None;
-    }
-    match User::update(&mut conn, &update) {
-        DatabaseResult::Succeful(user) => Some(Json(user)),
+) -> Option<Json<UserView>> {
+    let changes = UserChanges::from(update.0);
+    match User::update(&mut conn, username, &changes) {
+        DatabaseResult::Succeful(user) => {
+            let ip = client.ip.as_deref();
+            audit::record(
+                &mut conn,
+                Action::AdminUpdatedUser,
+                Some(&admin.username),
+                Some(username),
+                ip,
+            );
+            if let Some(role) = &changes.role {
+                audit::record_detail(
+                    &mut conn,
+                    Action::AdminChangedRole,
+                    Some(&admin.username),
+                    Some(username),
+                    role,
+                    ip,
+                );
+            }
+            Some(Json(user.into()))
+        }
         _ => None,
     }
 }
 
-/// DELETE to delete a user (Admin level)
+/// DELETE to delete a user right away, without a grace period (requires users:delete)
+///
+/// see `User::close` for what happens to their accounts
 #[delete("/admin/users?<username>")]
 pub fn super_delete_user(
     username: &str,
-    admin: gaurd::AdminGaurd,
+    admin: Require<UsersDelete>,
+    client: ClientInfo,
     mut conn: DbConn,
-) -> Option<Json<User>> {
-    match User::delete_by_username(&mut conn, username) {
-        DatabaseResult::Succeful(user) => Some(Json(user)),
+) -> Option<Json<UserView>> {
+    match User::close(&mut conn, username) {
+        DatabaseResult::Succeful(user) => {
+            let ip = client.ip.as_deref();
+            audit::record(
+                &mut conn,
+                Action::AdminDeletedUser,
+                Some(&admin.username),
+                Some(username),
+                ip,
+            );
+            Some(Json(user.into()))
+        }
         _ => None,
     }
 }
 
+#[derive(Deserialize)]
+pub struct ImpersonationData {
+    pub username: String,
+    /// lets the token change and delete things too
+    #[serde(default)]
+    pub allow_destructive: bool,
+}
+
+/// A token that acts as another user, used as `Authorization: Bearer`
+#[derive(Serialize)]
+pub struct Impersonation {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_at: NaiveDateTime,
+    pub username: String,
+    pub allow_destructive: bool,
+}
+
+/// POST to act as a user (requires users:impersonate)
+///
+/// the token can only look around unless `allow_destructive` is set,
+/// every request made with it is audited
+#[post(
+    "/admin/users/impersonate",
+    format = "application/json",
+    data = "<request>"
+)]
+pub fn super_impersonate_user(
+    request: Json<ImpersonationData>,
+    admin: Require<UsersImpersonate>,
+    client: ClientInfo,
+    issuer: &State<TokenIssuer>,
+    mut conn: DbConn,
+) -> Option<Json<Impersonation>> {
+    let user = match User::get(&mut conn, &request.username) {
+        DatabaseResult::Succeful(user) => user,
+        _ => return None,
+    };
+    let (access_token, expires_at) = issuer.impersonate(
+        admin.claims(),
+        &user.username,
+        &user.role,
+        request.allow_destructive,
+    );
+
+    let detail = if request.allow_destructive {
+        "destructive operations allowed"
+    } else {
+        "read only"
+    };
+    let ip = client.ip.as_deref();
+    let action = Action::ImpersonationStarted;
+    audit::record_detail(
+        &mut conn,
+        action,
+        Some(&admin.username),
+        Some(&user.username),
+        detail,
+        ip,
+    );
+    Some(Json(Impersonation {
+        access_token,
+        token_type: "Bearer",
+        expires_at,
+        username: user.username,
+        allow_destructive: request.allow_destructive,
+    }))
+}
+
 /// POST to creating a new user
 #[post("/users", format = "application/json", data = "<new_user>")]
-pub fn create_user(new_user: Json<UserData>, mut conn: DbConn) -> Option<Json<User>> {
+pub fn create_user(new_user: Json<UserData>, mut conn: DbConn) -> Option<Json<UserView>> {
     let new_user = new_user.0;
     match User::add(&mut conn, &new_user.into()) {
-        DatabaseResult::Succeful(user) => Some(Json(user)),
+        DatabaseResult::Succeful(user) => Some(Json(user.into())),
         _ => None,
     }
 }
 
 /// GET to retrieve a user
 #[get("/users")]
-pub fn get_user(user: gaurd::UserGaurd, mut conn: DbConn) -> Option<Json<User>> {
+pub fn get_user(user: gaurd::UserGaurd, mut conn: DbConn) -> Option<Json<UserView>> {
     match User::get(&mut conn, &user.username) {
-        DatabaseResult::Succeful(user) => Some(Json(user)),
+        DatabaseResult::Succeful(user) => Some(Json(user.into())),
         _ => None,
     }
 }
 
-/// PATCH to update a user info
+/// PATCH to update the caller's name, email, password or base currency
+///
+/// refused to impersonating admins, password resets are mailed to the email
 #[patch("/users", format = "application/json", data = "<update>")]
 pub fn update_user(
-    update: Json<User>,
-    user: gaurd::UserGaurd,
+    update: Json<UserUpdate>,
+    user: gaurd::CredentialGaurd,
+    client: ClientInfo,
     mut conn: DbConn,
-) -> Option<Json<User>> {
-    let mut update = update.0;
-    update.password = update.password.hash();
-    match User::update(&mut conn, &update) {
-        DatabaseResult::Succeful(user) => Some(Json(user)),
+) -> Option<Json<UserView>> {
+    let changes = UserChanges::from(update.0);
+    match User::update(&mut conn, &user.username, &changes) {
+        DatabaseResult::Succeful(updated) => {
+            if changes.password.is_some() {
+                let ip = client.ip.as_deref();
+                audit::record(
+                    &mut conn,
+                    Action::PasswordChanged,
+                    Some(&user.username),
+                    Some(&updated.username),
+                    ip,
+                );
+            }
+            Some(Json(updated.into()))
+        }
         _ => None,
     }
 }
 
-/// DELETE to delete a user
+/// A user's export served as a file download
+#[derive(Responder)]
+pub struct Download {
+    inner: Json<UserExport>,
+    disposition: Header<'static>,
+}
+
+/// Answer to a deletion request, the user's data as it was when they asked
+#[derive(Serialize)]
+pub struct ScheduledDeletion {
+    pub deletion: DeletionRequest,
+    pub export: UserExport,
+}
+
+/// GET to download everything stored about the caller
+#[get("/users/export")]
+pub fn export_user(
+    user: gaurd::UserGaurd,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Download> {
+    let export = match UserExport::of(&mut conn, &user.username) {
+        DatabaseResult::Succeful(export) => export,
+        _ => return None,
+    };
+    let ip = client.ip.as_deref();
+    audit::record(
+        &mut conn,
+        Action::DataExported,
+        Some(&user.username),
+        None,
+        ip,
+    );
+    let disposition = format!("attachment; filename=\"{}-export.json\"", user.username);
+    Some(Download {
+        inner: Json(export),
+        disposition: Header::new("Content-Disposition", disposition),
+    })
+}
+
+/// DELETE to schedule the caller's deletion
+///
+/// everything is deleted once DELETION_GRACE_DAYS pass unless it's cancelled,
+/// the answer carries an export of their data to keep
 #[delete("/users")]
-pub fn delete_user(user: gaurd::UserGaurd, mut conn: DbConn) -> Option<Json<User>> {
-    let username = &user.username;
-    match User::delete_by_username(&mut conn, username) {
-        DatabaseResult::Succeful(user) => Some(Json(user)),
+pub fn delete_user(
+    user: gaurd::UserGaurd,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Json<ScheduledDeletion>> {
+    let export = match UserExport::of(&mut conn, &user.username) {
+        DatabaseResult::Succeful(export) => export,
+        _ => return None,
+    };
+    let new_request = NewDeletionRequest::new(user.username.clone());
+    match DeletionRequest::add(&mut conn, &new_request) {
+        DatabaseResult::Succeful(deletion) => {
+            let ip = client.ip.as_deref();
+            audit::record(
+                &mut conn,
+                Action::DeletionScheduled,
+                Some(&user.username),
+                None,
+                ip,
+            );
+            Some(Json(ScheduledDeletion { deletion, export }))
+        }
+        _ => None,
+    }
+}
+
+/// GET to see when the caller is scheduled to be deleted
+#[get("/users/deletion")]
+pub fn get_user_deletion(
+    user: gaurd::UserGaurd,
+    mut conn: DbConn,
+) -> Option<Json<DeletionRequest>> {
+    match DeletionRequest::get(&mut conn, &user.username) {
+        DatabaseResult::Succeful(deletion) => Some(Json(deletion)),
+        _ => None,
+    }
+}
+
+/// DELETE to cancel the caller's scheduled deletion
+#[delete("/users/deletion")]
+pub fn cancel_user_deletion(
+    user: gaurd::UserGaurd,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Option<Json<DeletionRequest>> {
+    match DeletionRequest::delete(&mut conn, &user.username) {
+        DatabaseResult::Succeful(deletion) => {
+            let ip = client.ip.as_deref();
+            audit::record(
+                &mut conn,
+                Action::DeletionCancelled,
+                Some(&user.username),
+                None,
+                ip,
+            );
+            Some(Json(deletion))
+        }
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::UserExport;
+    use rocket::serde::json::{from_str, to_string};
+
+    fn user() -> User {
+        User {
+            name: "Kimia".to_string(),
+            username: "absolute_trash".to_string(),
+            password: "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA".to_string(),
+            role: "admin".to_string(),
+            email: Some("kimia@example.com".to_string()),
+            base_currency: "USD".parse().unwrap(),
+            tokens_valid_after: None,
+        }
+    }
+
+    #[test]
+    fn user_view_hides_secrets() {
+        let json = to_string(&UserView::from(user())).unwrap();
+        assert!(!json.contains("password"));
+        assert!(!json.contains("argon2"));
+
+        let export = UserExport {
+            exported_at: chrono::Utc::now().naive_utc(),
+            name: "Kimia".to_string(),
+            username: "absolute_trash".to_string(),
+            email: None,
+            role: "user".to_string(),
+            base_currency: "USD".parse().unwrap(),
+            accounts: Vec::new(),
+        };
+        let deletion = DeletionRequest {
+            user_id: "absolute_trash".to_string(),
+            requested_at: export.exported_at,
+            scheduled_for: export.exported_at,
+        };
+        let json = to_string(&ScheduledDeletion { deletion, export }).unwrap();
+        assert!(!json.contains("password"));
+    }
+
+    #[test]
+    fn user_update_fields() {
+        // users can't hand themselves a role or a ready made hash
+        assert!(from_str::<UserUpdate>(r#"{"role": "admin"}"#).is_err());
+        assert!(from_str::<UserUpdate>(r#"{"username": "someone_else"}"#).is_err());
+
+        // only what's sent is changed
+        let update: UserUpdate = from_str(r#"{"name": "Changed"}"#).unwrap();
+        let changes = UserChanges::from(update);
+        assert_eq!(changes.name.as_deref(), Some("Changed"));
+        assert_eq!(changes.password, None);
+
+        let update: UserUpdate = from_str(r#"{"password": "hunter2"}"#).unwrap();
+        let changes = UserChanges::from(update);
+        assert!(changes.password.unwrap().starts_with("$argon2id$"));
+
+        let update: UserUpdate = from_str(r#"{"base_currency": "CAD"}"#).unwrap();
+        let changes = UserChanges::from(update);
+        assert_eq!(changes.base_currency.unwrap().as_str(), "CAD");
+        assert!(from_str::<UserUpdate>(r#"{"base_currency": "cad"}"#).is_err());
+    }
+
+    #[test]
+    fn admin_user_update_fields() {
+        // admins hand out roles, users still can't take one
+        let update: AdminUserUpdate = from_str(r#"{"role": "support"}"#).unwrap();
+        let changes = UserChanges::from(update);
+        assert_eq!(changes.role.as_deref(), Some("support"));
+        assert_eq!(changes.name, None);
+        let changes = UserChanges::from(from_str::<UserUpdate>(r#"{"name": "Kimia"}"#).unwrap());
+        assert_eq!(changes.role, None);
+        assert!(from_str::<UserUpdate>(r#"{"role": "admin"}"#).is_err());
+
+        assert!(from_str::<AdminUserUpdate>(r#"{"password_hash": "x"}"#).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use crate::models::{AuditEvent, NewAuditEvent};
+use diesel::PgConnection;
+
+/// Something that gets recorded in the audit log, stored by its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    LoginSucceeded,
+    LoginFailed,
+    TokenRefreshed,
+    PasswordChanged,
+    PasswordReset,
+    AdminListedUsers,
+    AdminViewedUser,
+    AdminUpdatedUser,
+    AdminChangedRole,
+    AdminDeletedUser,
+    RoleUpdated,
+    ImpersonationStarted,
+    ImpersonatedRequest,
+    DataExported,
+    DeletionScheduled,
+    DeletionCancelled,
+    UserDeleted,
+    AccountDeleted,
+    TransactionDeleted,
+    AccountTransactionsDeleted,
+    TransferDeleted,
+    JournalEntryDeleted,
+    BalancesRecomputed,
+    CurrencyAdded,
+    CurrencyDeleted,
+    RatesImported,
+}
+
+impl Action {
+    /// name the action is stored and searched by
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::LoginSucceeded => "login.succeeded",
+            Action::LoginFailed => "login.failed",
+            Action::TokenRefreshed => "token.refreshed",
+            Action::PasswordChanged => "password.changed",
+            Action::PasswordReset => "password.reset",
+            Action::AdminListedUsers => "admin.users.list",
+            Action::AdminViewedUser => "admin.users.read",
+            Action::AdminUpdatedUser => "admin.users.update",
+            Action::AdminChangedRole => "admin.users.role",
+            Action::AdminDeletedUser => "admin.users.delete",
+            Action::RoleUpdated => "admin.roles.update",
+            Action::ImpersonationStarted => "impersonation.started",
+            Action::ImpersonatedRequest => "impersonation.request",
+            Action::DataExported => "user.exported",
+            Action::DeletionScheduled => "user.deletion.scheduled",
+            Action::DeletionCancelled => "user.deletion.cancelled",
+            Action::UserDeleted => "user.deleted",
+            Action::AccountDeleted => "account.deleted",
+            Action::TransactionDeleted => "transaction.deleted",
+            Action::AccountTransactionsDeleted => "account.transactions.deleted",
+            Action::TransferDeleted => "transfer.deleted",
+            Action::JournalEntryDeleted => "ledger.entry.deleted",
+            Action::BalancesRecomputed => "maintenance.balances.recomputed",
+            Action::CurrencyAdded => "currency.added",
+            Action::CurrencyDeleted => "currency.deleted",
+            Action::RatesImported => "rates.imported",
+        }
+    }
+}
+
+/// records that `actor` did `action` to `target` from `ip`
+///
+/// `actor` is None when nobody could be identified
+pub fn record(
+    conn: &mut PgConnection,
+    action: Action,
+    actor: Option<&str>,
+    target: Option<&str>,
+    ip: Option<&str>,
+) {
+    let event = NewAuditEvent::new(action.name(), actor, target).ip(ip);
+    AuditEvent::add(conn, &event);
+}
+
+/// like `record`, with what exactly was done
+pub fn record_detail(
+    conn: &mut PgConnection,
+    action: Action,
+    actor: Option<&str>,
+    target: Option<&str>,
+    detail: &str,
+    ip: Option<&str>,
+) {
+    let event = NewAuditEvent::new(action.name(), actor, target)
+        .ip(ip)
+        .detail(detail);
+    AuditEvent::add(conn, &event);
+}
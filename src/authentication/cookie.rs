@@ -0,0 +1,125 @@
+use super::{random_token, Claims, GaurdError};
+use crate::db;
+use crate::models::result_variant::DatabaseResult;
+use crate::models::{Session, User};
+use chrono::Utc;
+use rocket::http::{Cookie, CookieJar, Method, SameSite, Status};
+use rocket::outcome::Outcome;
+use rocket::request::{self, Request};
+use rocket::time;
+
+/// private (encrypted) cookie holding `<session id>:<csrf token>`
+pub const SESSION_COOKIE: &str = "session";
+/// readable by scripts so they can echo it in CSRF_HEADER
+pub const CSRF_COOKIE: &str = "csrf_token";
+/// header state changing requests authenticated by cookie have to send
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// sets the session cookie for a new session, returns its CSRF token
+///
+/// the cookies last as long as the session does
+pub fn start(cookies: &CookieJar<'_>, session: &Session) -> String {
+    let csrf_token = random_token();
+    let max_age =
+        time::Duration::seconds((session.expires_at - Utc::now().naive_utc()).num_seconds());
+    let expires = time::OffsetDateTime::now_utc() + max_age;
+    cookies.add_private(
+        Cookie::build(SESSION_COOKIE, format!("{}:{}", session.id, csrf_token))
+            .http_only(true)
+            .secure(true)
+            .same_site(SameSite::Strict)
+            .max_age(max_age)
+            .expires(expires)
+            .finish(),
+    );
+    cookies.add(
+        Cookie::build(CSRF_COOKIE, csrf_token.clone())
+            .http_only(false)
+            .secure(true)
+            .same_site(SameSite::Strict)
+            .max_age(max_age)
+            .expires(expires)
+            .finish(),
+    );
+    csrf_token
+}
+
+/// removes the session cookies
+pub fn end(cookies: &CookieJar<'_>) {
+    cookies.remove_private(Cookie::named(SESSION_COOKIE));
+    cookies.remove(Cookie::named(CSRF_COOKIE));
+}
+
+/// authenticates a request with its session cookie
+///
+/// forwards if there's no valid cookie or its session is gone. Cookies
+/// are sent by browsers on their own, so POST, PUT, PATCH and DELETE
+/// also have to prove they come from our front-end with CSRF_HEADER
+pub async fn claims(req: &Request<'_>) -> request::Outcome<Claims, GaurdError> {
+    let cookie = match req.cookies().get_private(SESSION_COOKIE) {
+        Some(cookie) => cookie,
+        None => return Outcome::Forward(()),
+    };
+    let (session_id, csrf_token) = match cookie.value().split_once(':') {
+        Some((session_id, csrf_token)) => match session_id.parse::<i32>() {
+            Ok(session_id) => (session_id, csrf_token),
+            Err(_) => return Outcome::Forward(()),
+        },
+        None => return Outcome::Forward(()),
+    };
+
+    let changes_state = matches!(
+        req.method(),
+        Method::Post | Method::Put | Method::Patch | Method::Delete
+    );
+    if changes_state {
+        let sent = req.headers().get_one(CSRF_HEADER).unwrap_or_default();
+        if !constant_time_eq(sent.as_bytes(), csrf_token.as_bytes()) {
+            return Outcome::Failure((Status::Forbidden, GaurdError::CsrfTokenMismatch));
+        }
+    }
+
+    // the cookie outlives access tokens, so the session and role are
+    // looked up to honor revoked sessions, password resets and role changes
+    let found = db::run(req, move |conn| match Session::get(conn, session_id) {
+        DatabaseResult::Succeful(session) => match User::get(conn, &session.user_id) {
+            DatabaseResult::Succeful(user) if user.tokens_valid(session.created_at) => {
+                Some((session, user))
+            }
+            _ => None,
+        },
+        _ => None,
+    })
+    .await;
+    match found {
+        Some(Some((session, user))) => Outcome::Success(Claims {
+            sub: user.username,
+            role: user.role,
+            sid: session.id,
+            mfa: session.two_factor,
+            impersonated_by: None,
+            allow_destructive: false,
+            iat: session.created_at.timestamp(),
+            exp: session.expires_at.timestamp(),
+        }),
+        Some(None) => Outcome::Forward(()),
+        None => GaurdError::unavailable(),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csrf_compare() {
+        assert!(constant_time_eq(b"token", b"token"));
+        assert!(!constant_time_eq(b"token", b"tokem"));
+        assert!(!constant_time_eq(b"token", b"token2"));
+        assert!(!constant_time_eq(b"", b"token"));
+    }
+}
@@ -1,17 +1,170 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
 use sha2::Digest;
 use sha2::Sha256;
+use std::env;
 use std::string::String;
 
 pub trait Hash {
     fn hash(self) -> String;
 }
 
+/// hashes a password with Argon2id and a fresh random salt,
+/// the result is a PHC string ("$argon2id$v=19$m=...,t=...,p=...$salt$hash")
 impl Hash for String {
     fn hash(self) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(self.as_bytes());
-        let mut password = hasher.finalize();
+        hash_password(&self)
+    }
+}
+
+/// Result of checking a password against a stored hash
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verification {
+    /// password matches and the stored hash is up to date
+    Valid,
+    /// password matches but the stored hash is legacy SHA-256 or uses
+    /// outdated Argon2 parameters, caller should store a fresh hash
+    NeedsRehash,
+    Invalid,
+}
+
+/// Argon2id cost parameters
+///
+/// read from ARGON2_MEMORY_KIB, ARGON2_ITERATIONS and ARGON2_PARALLELISM,
+/// falling back to the OWASP recommended minimum (19 MiB, 2 passes, 1 lane)
+pub fn params() -> Params {
+    fn var(name: &str, default: u32) -> u32 {
+        env::var(name)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+    Params::new(
+        var("ARGON2_MEMORY_KIB", 19 * 1024),
+        var("ARGON2_ITERATIONS", 2),
+        var("ARGON2_PARALLELISM", 1),
+        None,
+    )
+    .expect("invalid Argon2 parameters")
+}
+
+fn argon2() -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params())
+}
+
+/// hashes a password with the configured Argon2id parameters
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    argon2()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("Argon2 failed to hash password")
+        .to_string()
+}
+
+/// checks a password against a stored hash
+///
+/// accepts both PHC strings and hashes produced by the old unsalted
+/// SHA-256 scheme, the latter always verify as Verification::NeedsRehash
+pub fn verify(password: &str, stored: &str) -> Verification {
+    let parsed = match PasswordHash::new(stored) {
+        Ok(parsed) => parsed,
+        Err(_) if legacy_hash(password) == stored => return Verification::NeedsRehash,
+        Err(_) => return Verification::Invalid,
+    };
+
+    if argon2()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_err()
+    {
+        return Verification::Invalid;
+    }
+
+    let current = params();
+    let outdated = parsed.algorithm != Algorithm::Argon2id.ident()
+        || Params::try_from(&parsed)
+            .map(|used| {
+                used.m_cost() != current.m_cost()
+                    || used.t_cost() != current.t_cost()
+                    || used.p_cost() != current.p_cost()
+            })
+            .unwrap_or(true);
+    if outdated {
+        Verification::NeedsRehash
+    } else {
+        Verification::Valid
+    }
+}
+
+/// digest used to store bearer tokens
+///
+/// tokens are long random strings so a single unsalted SHA-256 round is
+/// enough here, and being deterministic it can be looked up by index
+pub fn token_hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// the scheme used before Argon2: a single unsalted SHA-256 round
+/// squeezed through from_utf8_lossy, kept only to verify old hashes
+fn legacy_hash(password: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(password.as_bytes());
+    let mut password = hasher.finalize();
+
+    String::from_utf8_lossy(password.as_mut_slice()).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hash_is_salted_argon2id() {
+        let first = String::from("hunter2").hash();
+        let second = String::from("hunter2").hash();
+
+        assert!(first.starts_with("$argon2id$"));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn verify_current_hash() {
+        let stored = hash_password("hunter2");
+
+        assert_eq!(verify("hunter2", &stored), Verification::Valid);
+        assert_eq!(verify("hunter3", &stored), Verification::Invalid);
+    }
+
+    #[test]
+    fn verify_legacy_hash() {
+        let stored = legacy_hash("hunter2");
+
+        assert_eq!(verify("hunter2", &stored), Verification::NeedsRehash);
+        assert_eq!(verify("hunter3", &stored), Verification::Invalid);
+    }
+
+    #[test]
+    fn token_hash_is_hex_sha256() {
+        let digest = token_hash("token");
+
+        assert_eq!(digest.len(), 64);
+        assert_eq!(digest, token_hash("token"));
+        assert_ne!(digest, token_hash("other"));
+    }
+
+    #[test]
+    fn verify_outdated_params() {
+        let weak = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(8, 1, 1, None).unwrap(),
+        );
+        let salt = SaltString::generate(&mut OsRng);
+        let stored = weak.hash_password(b"hunter2", &salt).unwrap().to_string();
 
-        String::from_utf8_lossy(password.as_mut_slice()).into_owned()
+        assert_eq!(verify("hunter2", &stored), Verification::NeedsRehash);
     }
 }
@@ -1,90 +1,426 @@
+/// declares a marker type per name for a guard's trait, like `Permission`,
+/// and `ALL` with every name, see `permission` and `scope`
+macro_rules! markers {
+    ($trait:ident; $($(#[$doc:meta])* $marker:ident => $name:literal,)*) => {
+        $(
+            $(#[$doc])*
+            pub struct $marker;
+
+            impl $trait for $marker {
+                const NAME: &'static str = $name;
+            }
+        )*
+
+        /// every name there is
+        pub const ALL: &[&str] = &[$($name),*];
+    };
+}
+
+pub mod cookie;
 pub mod hasher;
+pub mod permission;
+pub mod scope;
+pub mod throttle;
 pub mod token_generator;
-use crate::establish_connection;
+pub mod token_issuer;
+pub mod totp;
+use crate::audit::{self, Action};
+use crate::db;
+pub use permission::Permission;
+pub use scope::Scope;
 pub use token_generator::random_token;
+pub use token_issuer::{Claims, TokenIssuer};
 
+use crate::authorization::Caller;
 use crate::models::result_variant::DatabaseResult;
-use crate::models::User;
-use rocket::http::Status;
-use rocket::http::{Cookie, CookieJar};
-use rocket::outcome::{IntoOutcome, Outcome};
+use crate::models::{ApiKey, Role, Session, User, API_KEY_PREFIX};
+use rocket::http::{Method, Status};
+use rocket::outcome::{try_outcome, Outcome};
 use rocket::request::{self, FromRequest, Request};
+use rocket::State;
 use std::convert::Infallible;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum GaurdError {
-    NotAdmin,
+    /// the user's role isn't granted the permission the route requires
+    MissingPermission(&'static str),
+    /// the user's role only lets its permissions be used after logging in with 2FA
+    TwoFactorRequired,
+    /// the API key wasn't given the scope the route requires
+    MissingScope(&'static str),
+    /// API keys can only be used on routes that take scopes
+    ApiKeyNotAllowed,
+    /// a state changing request authenticated by cookie didn't send
+    /// the session's CSRF token
+    CsrfTokenMismatch,
+    /// impersonation tokens can't change or delete anything unless
+    /// they were minted to allow it
+    ImpersonationReadOnly,
+    /// impersonation tokens can't use admin permissions or manage
+    /// the user's credentials
+    ImpersonationNotAllowed,
+    /// there was no database connection to look the caller up with
+    Unavailable,
+}
+
+impl GaurdError {
+    fn unavailable<S>() -> request::Outcome<S, GaurdError> {
+        Outcome::Failure((Status::ServiceUnavailable, GaurdError::Unavailable))
+    }
+}
+
+/// What a request's login resolved to, kept in its local cache so
+/// every guard on a route shares one lookup
+struct CachedLogin(request::Outcome<Claims, GaurdError>);
+
+/// What a request's API key resolved to, see CachedLogin
+struct CachedApiKey(request::Outcome<ApiKey, GaurdError>);
+
+/// What the caller's role is granted, looked up once per request
+struct Grants {
+    permissions: Vec<String>,
+    require_2fa: bool,
+}
+
+/// extracts the token from an `Authorization: Bearer <token>` header
+fn bearer_token<'r>(req: &'r Request<'_>) -> Option<&'r str> {
+    let values: Vec<_> = req.headers().get("Authorization").collect();
+    if values.len() != 1 {
+        return None;
+    }
+    let mut header_fileds = values[0].split(' ');
+    match header_fileds.next() {
+        Some("Bearer") => header_fileds.next(),
+        _ => None,
+    }
+}
+
+/// whether the request is authenticated with an API key instead of
+/// an access token
+fn uses_api_key(req: &Request<'_>) -> bool {
+    bearer_token(req).is_some_and(|token| token.starts_with(API_KEY_PREFIX))
+}
+
+/// validates the request's bearer access token and returns its claims
+async fn bearer_claims(req: &Request<'_>) -> Option<Claims> {
+    let token = bearer_token(req)?;
+    let issuer = req.guard::<&State<TokenIssuer>>().await.succeeded()?;
+    issuer.verify(token)
+}
+
+/// authenticates a logged in user by their bearer access token or,
+/// without an Authorization header, by their session cookie
+///
+/// resolved once per request, later calls get the cached outcome
+async fn login_claims(req: &Request<'_>) -> request::Outcome<Claims, GaurdError> {
+    let cached = req
+        .local_cache_async(async { CachedLogin(resolve_login(req).await) })
+        .await;
+    cached.0.clone()
+}
+
+async fn resolve_login(req: &Request<'_>) -> request::Outcome<Claims, GaurdError> {
+    if uses_api_key(req) {
+        return Outcome::Failure((Status::Forbidden, GaurdError::ApiKeyNotAllowed));
+    }
+    if req.headers().contains("Authorization") {
+        let claims = match bearer_claims(req).await {
+            Some(claims) => try_outcome!(current_claims(req, claims).await),
+            None => return Outcome::Forward(()),
+        };
+        return match claims.impersonated_by {
+            Some(_) => impersonated(req, claims).await,
+            None => Outcome::Success(claims),
+        };
+    }
+    cookie::claims(req).await
+}
+
+/// brings an access token's claims up to date with the database
+///
+/// forwards tokens whose session was revoked, expired or started before
+/// its user's password was reset, so logging a device out cuts it off
+/// before its token runs out, and swaps the role the token was minted
+/// with for the user's current one
+async fn current_claims(
+    req: &Request<'_>,
+    mut claims: Claims,
+) -> request::Outcome<Claims, GaurdError> {
+    let session_id = claims.sid;
+    let username = claims.sub.clone();
+    let role = db::run(req, move |conn| {
+        let session = match Session::get(conn, session_id) {
+            DatabaseResult::Succeful(session) => session,
+            _ => return None,
+        };
+        // impersonation tokens live on the admin's session
+        match User::get(conn, &session.user_id) {
+            DatabaseResult::Succeful(owner) if owner.tokens_valid(session.created_at) => (),
+            _ => return None,
+        }
+        match User::get(conn, &username) {
+            DatabaseResult::Succeful(user) => Some(user.role),
+            _ => None,
+        }
+    })
+    .await;
+    match role {
+        Some(Some(role)) => {
+            claims.role = role;
+            Outcome::Success(claims)
+        }
+        Some(None) => Outcome::Forward(()),
+        None => GaurdError::unavailable(),
+    }
+}
+
+/// authenticates the request's API key and marks it as used, once per request
+async fn api_key(req: &Request<'_>) -> request::Outcome<ApiKey, GaurdError> {
+    let cached = req
+        .local_cache_async(async {
+            let key = bearer_token(req).unwrap_or_default().to_string();
+            let found = db::run(req, move |conn| ApiKey::authenticate(conn, &key)).await;
+            CachedApiKey(match found {
+                Some(DatabaseResult::Succeful(key)) => Outcome::Success(key),
+                Some(_) => Outcome::Forward(()),
+                None => GaurdError::unavailable(),
+            })
+        })
+        .await;
+    cached.0.clone()
+}
+
+/// looks up what a role is granted, once per request
+///
+/// logins resolve to the user's current role and permissions are looked
+/// up on every request, so role changes apply without waiting for
+/// tokens to expire
+async fn grants<'r>(req: &'r Request<'_>, role: &str) -> Option<&'r Grants> {
+    let role = role.to_string();
+    let grants = req
+        .local_cache_async(async {
+            db::run(req, move |conn| {
+                let permissions = match Role::permissions(conn, &role) {
+                    DatabaseResult::Succeful(permissions) => permissions,
+                    _ => Vec::new(),
+                };
+                let require_2fa = match Role::get(conn, &role) {
+                    DatabaseResult::Succeful(role) => role.require_2fa,
+                    _ => false,
+                };
+                Grants {
+                    permissions,
+                    require_2fa,
+                }
+            })
+            .await
+        })
+        .await;
+    grants.as_ref()
+}
+
+/// records a request made with an impersonation token and refuses it
+/// if it would change something the token isn't allowed to
+async fn impersonated(req: &Request<'_>, claims: Claims) -> request::Outcome<Claims, GaurdError> {
+    let admin = claims.impersonated_by.clone();
+    let username = claims.sub.clone();
+    let request = format!("{} {}", req.method(), req.uri());
+    let ip = req.client_ip().map(|ip| ip.to_string());
+    let recorded = db::run(req, move |conn| {
+        let action = Action::ImpersonatedRequest;
+        audit::record_detail(
+            conn,
+            action,
+            admin.as_deref(),
+            Some(&username),
+            &request,
+            ip.as_deref(),
+        );
+    })
+    .await;
+    // every use has to be on record
+    if recorded.is_none() {
+        return GaurdError::unavailable();
+    }
+
+    let destructive = !matches!(req.method(), Method::Get | Method::Head | Method::Options);
+    if destructive && !claims.allow_destructive {
+        return Outcome::Failure((Status::Forbidden, GaurdError::ImpersonationReadOnly));
+    }
+    Outcome::Success(claims)
+}
+
+/// Where a request came from, recorded on new sessions
+#[derive(Debug)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientInfo {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<ClientInfo, Self::Error> {
+        Outcome::Success(ClientInfo {
+            user_agent: req.headers().get_one("User-Agent").map(String::from),
+            ip: req.client_ip().map(|ip| ip.to_string()),
+        })
+    }
 }
 
 pub mod gaurd {
     use super::*;
+    use std::marker::PhantomData;
+
+    /// Admits users whose role is granted the permission `P`
+    ///
+    /// if the role requires 2FA the request has to come from a session
+    /// that was started with it
     #[derive(Debug)]
-    pub struct AdminGaurd {
+    pub struct Require<P: Permission> {
         pub username: String,
+        pub role: String,
+        claims: Claims,
+        permission: PhantomData<P>,
+    }
+
+    impl<P: Permission> Require<P> {
+        /// what the caller's access token asserts
+        pub fn claims(&self) -> &Claims {
+            &self.claims
+        }
     }
 
     #[rocket::async_trait]
-    impl<'r> FromRequest<'r> for AdminGaurd {
+    impl<'r, P: Permission> FromRequest<'r> for Require<P> {
         type Error = GaurdError;
 
-        async fn from_request(req: &'r Request<'_>) -> request::Outcome<AdminGaurd, Self::Error> {
-            let mut values: Vec<_> = req.headers().get("Authorization").collect();
-            if values.len() != 1 {
-                return Outcome::Forward(());
-            }
-            let mut header_fileds = values[0].split(' ');
-            match header_fileds.next() {
-                Some(method) if method == "Bearer" => (),
-                _ => return Outcome::Forward(()),
+        async fn from_request(req: &'r Request<'_>) -> request::Outcome<Require<P>, Self::Error> {
+            let claims = try_outcome!(login_claims(req).await);
+            // otherwise impersonating an admin would hand out their permissions
+            if claims.impersonated_by.is_some() {
+                return Outcome::Failure((Status::Forbidden, GaurdError::ImpersonationNotAllowed));
             }
-            let token = match header_fileds.next() {
-                Some(token) => token,
-                _ => return Outcome::Forward(()),
+
+            let grants = match grants(req, &claims.role).await {
+                Some(grants) => grants,
+                None => return GaurdError::unavailable(),
             };
+            if !grants.permissions.iter().any(|p| p == P::NAME) {
+                return Outcome::Failure((
+                    Status::Forbidden,
+                    GaurdError::MissingPermission(P::NAME),
+                ));
+            }
+            if !claims.mfa && grants.require_2fa {
+                return Outcome::Failure((Status::Forbidden, GaurdError::TwoFactorRequired));
+            }
+            Outcome::Success(Require {
+                username: claims.sub.clone(),
+                role: claims.role.clone(),
+                claims,
+                permission: PhantomData,
+            })
+        }
+    }
 
-            let mut conn = establish_connection();
-            match User::get_by_token(&mut conn, token) {
-                DatabaseResult::Succeful(user) if user.role == true => {
-                    Outcome::Success(AdminGaurd {
-                        username: user.username,
-                    })
-                }
-                _ => return Outcome::Failure((Status::BadRequest, GaurdError::NotAdmin)),
+    /// Admits callers allowed to use the scope `S`
+    ///
+    /// logged in users may use every scope on all their accounts,
+    /// API keys only the scopes they were given on the accounts they list
+    #[derive(Debug)]
+    pub struct Scoped<S: Scope> {
+        pub username: String,
+        /// None means every account of the user
+        pub account_ids: Option<Vec<i32>>,
+        scope: PhantomData<S>,
+    }
+
+    impl<S: Scope> Caller for Scoped<S> {
+        fn username(&self) -> &str {
+            &self.username
+        }
+
+        fn may_access_account(&self, account_id: i32) -> bool {
+            match &self.account_ids {
+                Some(account_ids) => account_ids.contains(&account_id),
+                None => true,
             }
         }
     }
 
+    #[rocket::async_trait]
+    impl<'r, S: Scope> FromRequest<'r> for Scoped<S> {
+        type Error = GaurdError;
+
+        async fn from_request(req: &'r Request<'_>) -> request::Outcome<Scoped<S>, Self::Error> {
+            if !uses_api_key(req) {
+                let claims = try_outcome!(login_claims(req).await);
+                return Outcome::Success(Scoped {
+                    username: claims.sub,
+                    account_ids: None,
+                    scope: PhantomData,
+                });
+            }
+
+            let key = try_outcome!(api_key(req).await);
+            if !key.scopes.iter().any(|s| s == S::NAME) {
+                return Outcome::Failure((Status::Forbidden, GaurdError::MissingScope(S::NAME)));
+            }
+            Outcome::Success(Scoped {
+                username: key.user_id,
+                account_ids: key.account_ids,
+                scope: PhantomData,
+            })
+        }
+    }
+
+    /// Admits logged in users, by access token or session cookie
+    ///
+    /// API keys are refused since no scope grants everything a user can do
     #[derive(Debug)]
     pub struct UserGaurd {
         pub username: String,
+        /// id of the session the request was authenticated with
+        pub session_id: i32,
     }
     #[rocket::async_trait]
     impl<'r> FromRequest<'r> for UserGaurd {
-        type Error = Infallible;
+        type Error = GaurdError;
 
         async fn from_request(req: &'r Request<'_>) -> request::Outcome<UserGaurd, Self::Error> {
-            let mut values: Vec<_> = req.headers().get("Authorization").collect();
-            if values.len() != 1 {
-                return Outcome::Forward(());
-            }
-            let mut values = values[0].split(' ');
-            match values.next() {
-                Some(method) if method == "Bearer" => (),
-                _ => return Outcome::Forward(()),
-            };
+            let claims = try_outcome!(login_claims(req).await);
+            Outcome::Success(UserGaurd {
+                username: claims.sub,
+                session_id: claims.sid,
+            })
+        }
+    }
 
-            let token = match values.next() {
-                Some(token) => token,
-                _ => return Outcome::Forward(()),
-            };
+    /// Admits logged in users acting as themselves, for routes that
+    /// create or change their credentials: API keys, 2FA and passwords
+    ///
+    /// impersonation tokens are refused, credentials made with them
+    /// would outlive the impersonation
+    #[derive(Debug)]
+    pub struct CredentialGaurd {
+        pub username: String,
+    }
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for CredentialGaurd {
+        type Error = GaurdError;
 
-            let mut conn = establish_connection();
-            match User::get_by_token(&mut conn, token) {
-                DatabaseResult::Succeful(user) => Outcome::Success(UserGaurd {
-                    username: user.username,
-                }),
-                _ => Outcome::Forward(()),
+        async fn from_request(
+            req: &'r Request<'_>,
+        ) -> request::Outcome<CredentialGaurd, Self::Error> {
+            let claims = try_outcome!(login_claims(req).await);
+            if claims.impersonated_by.is_some() {
+                return Outcome::Failure((Status::Forbidden, GaurdError::ImpersonationNotAllowed));
             }
+            Outcome::Success(CredentialGaurd {
+                username: claims.sub,
+            })
         }
     }
 }
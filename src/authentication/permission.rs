@@ -0,0 +1,31 @@
+/// A permission a role can be granted, named as in the permissions table
+///
+/// implemented by the marker types below so routes can ask for one
+/// with `gaurd::Require<permission::UsersRead>`
+pub trait Permission: Send + Sync + 'static {
+    const NAME: &'static str;
+}
+
+markers! {
+    Permission;
+    /// look up any user
+    UsersRead => "users:read",
+    /// change any user, including their role
+    UsersWrite => "users:write",
+    /// delete any user
+    UsersDelete => "users:delete",
+    /// act as any user, see TokenIssuer::impersonate
+    UsersImpersonate => "users:impersonate",
+    /// see login lockouts
+    SecurityRead => "security:read",
+    /// change what a role requires, like 2FA
+    RolesWrite => "roles:write",
+    /// search the audit log
+    AuditRead => "audit:read",
+    /// run maintenance like rebuilding balances
+    MaintenanceRun => "maintenance:run",
+    /// add and remove custom currencies
+    CurrenciesWrite => "currencies:write",
+    /// import exchange rates, see `rates::import_configured`
+    RatesImport => "rates:import",
+}
@@ -0,0 +1,19 @@
+/// Something an API key can be allowed to do, named as stored in api_keys.scopes
+///
+/// implemented by the marker types below so routes can ask for one
+/// with `gaurd::Scoped<scope::TransactionsRead>`
+pub trait Scope: Send + Sync + 'static {
+    const NAME: &'static str;
+}
+
+markers! {
+    Scope;
+    /// list and look up accounts
+    AccountsRead => "accounts:read",
+    /// create, rename and delete accounts
+    AccountsWrite => "accounts:write",
+    /// list and look up transactions
+    TransactionsRead => "transactions:read",
+    /// add and delete transactions
+    TransactionsWrite => "transactions:write",
+}
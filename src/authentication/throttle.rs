@@ -0,0 +1,110 @@
+use crate::models::result_variant::DatabaseResult;
+use crate::models::{Failures, Lockout, LoginAttempt, NewLockout, NewLoginAttempt};
+use chrono::{Duration, Utc};
+use diesel::PgConnection;
+
+/// failures on a username before each retry has to wait
+const USERNAME_BACKOFF_AFTER: i64 = 3;
+/// failures on a username before it gets locked out
+const USERNAME_LOCKOUT_AFTER: i64 = 10;
+/// an address may try many usernames, so it gets more slack
+const IP_BACKOFF_AFTER: i64 = 10;
+const IP_LOCKOUT_AFTER: i64 = 50;
+/// failures older than this are forgotten
+const WINDOW_MINUTES: i64 = 60;
+const LOCKOUT_MINUTES: i64 = 15;
+const MAX_DELAY_SECONDS: i64 = 300;
+
+/// wait required after the last failure, doubling with every failure
+/// past `backoff_after` and capped at five minutes
+pub fn delay(failures: i64, backoff_after: i64) -> Duration {
+    if failures < backoff_after {
+        return Duration::zero();
+    }
+    let exponent = (failures - backoff_after).min(16) as u32;
+    Duration::seconds((1i64 << exponent).min(MAX_DELAY_SECONDS))
+}
+
+/// seconds a login for `username` from `ip` has to wait before it's
+/// checked, None if it can be checked right away
+pub fn retry_after(conn: &mut PgConnection, username: &str, ip: Option<&str>) -> Option<i64> {
+    let now = Utc::now().naive_utc();
+    let since = now - Duration::minutes(WINDOW_MINUTES);
+
+    let mut wait_until = match Lockout::active(conn, username, ip) {
+        DatabaseResult::Succeful(lockout) => Some(lockout.locked_until),
+        _ => None,
+    };
+    let mut backoff = |failures: DatabaseResult<_>, backoff_after| {
+        if let DatabaseResult::Succeful(Failures {
+            count,
+            last: Some(last),
+        }) = failures
+        {
+            let until = last + delay(count, backoff_after);
+            wait_until = wait_until.max(Some(until));
+        }
+    };
+    backoff(
+        LoginAttempt::username_failures(conn, username, since),
+        USERNAME_BACKOFF_AFTER,
+    );
+    if let Some(ip) = ip {
+        backoff(LoginAttempt::ip_failures(conn, ip, since), IP_BACKOFF_AFTER);
+    }
+
+    match wait_until {
+        Some(until) if until > now => {
+            let millis = (until - now).num_milliseconds();
+            Some((millis + 999) / 1000)
+        }
+        _ => None,
+    }
+}
+
+/// records the outcome of a password check, locking the username or
+/// address out once they cross their threshold
+pub fn record(conn: &mut PgConnection, username: &str, ip: Option<&str>, succeeded: bool) {
+    let now = Utc::now().naive_utc();
+    let attempt = NewLoginAttempt::new(username.to_string(), ip.map(String::from), succeeded);
+    LoginAttempt::add(conn, &attempt);
+
+    if succeeded {
+        LoginAttempt::delete_before(conn, now - Duration::minutes(WINDOW_MINUTES));
+        return;
+    }
+
+    let since = now - Duration::minutes(WINDOW_MINUTES);
+    let locked_until = now + Duration::minutes(LOCKOUT_MINUTES);
+    if let DatabaseResult::Succeful(failures) =
+        LoginAttempt::username_failures(conn, username, since)
+    {
+        if failures.count >= USERNAME_LOCKOUT_AFTER {
+            Lockout::add(
+                conn,
+                &NewLockout::username(username, failures.count, locked_until),
+            );
+        }
+    }
+    if let Some(ip) = ip {
+        if let DatabaseResult::Succeful(failures) = LoginAttempt::ip_failures(conn, ip, since) {
+            if failures.count >= IP_LOCKOUT_AFTER {
+                Lockout::add(conn, &NewLockout::ip(ip, failures.count, locked_until));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delay_backs_off_exponentially() {
+        assert_eq!(delay(2, 3), Duration::zero());
+        assert_eq!(delay(3, 3), Duration::seconds(1));
+        assert_eq!(delay(4, 3), Duration::seconds(2));
+        assert_eq!(delay(6, 3), Duration::seconds(8));
+        assert_eq!(delay(100, 3), Duration::seconds(MAX_DELAY_SECONDS));
+    }
+}
@@ -0,0 +1,331 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, RngCore};
+use rocket::fairing::AdHoc;
+use rocket::serde::json::serde_json;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::env;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// What a signed access token asserts about its bearer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Claims {
+    /// username
+    pub sub: String,
+    /// role when it was minted, guards go by the user's current one
+    pub role: String,
+    /// session the token was minted for
+    pub sid: i32,
+    /// whether the login passed a second factor
+    #[serde(default)]
+    pub mfa: bool,
+    /// admin acting as the user, see TokenIssuer::impersonate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonated_by: Option<String>,
+    /// whether an impersonating admin may change or delete anything
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_destructive: bool,
+    /// issued at, seconds since the unix epoch
+    pub iat: i64,
+    /// expires at, seconds since the unix epoch
+    pub exp: i64,
+}
+
+/// Proof that a user got their password right and still owes a TOTP code
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Challenge {
+    sub: String,
+    /// to the nanosecond, so it can be told apart from a password reset
+    /// in the same second, see `User::tokens_valid`
+    issued_at: NaiveDateTime,
+    exp: i64,
+}
+
+/// minutes a user has to enter their TOTP code after their password
+const CHALLENGE_TTL_MINUTES: i64 = 5;
+
+/// Mints and validates short lived access tokens
+///
+/// tokens are `base64url(claims).base64url(HMAC-SHA256(claims))`, signed
+/// with a key derived from Rocket's `secret_key`. Guards still check
+/// that the session they were minted for is live, see `gaurd`
+pub struct TokenIssuer {
+    key: Vec<u8>,
+    /// signs login challenges, so they can't pass as access tokens
+    challenge_key: Vec<u8>,
+    access_ttl: Duration,
+    impersonation_ttl: Duration,
+}
+
+impl TokenIssuer {
+    /// creates an issuer from secret key material
+    ///
+    /// access tokens live for ACCESS_TOKEN_TTL_MINUTES and impersonation
+    /// tokens for IMPERSONATION_TTL_MINUTES, 15 by default
+    pub fn new(secret: &[u8]) -> TokenIssuer {
+        // never sign with the raw secret, Rocket uses it for private cookies too
+        let derive = |label: &[u8]| {
+            let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+            mac.update(label);
+            mac.finalize().into_bytes().to_vec()
+        };
+        let ttl = |var| {
+            let minutes = env::var(var)
+                .ok()
+                .and_then(|minutes| minutes.parse().ok())
+                .unwrap_or(15);
+            Duration::minutes(minutes)
+        };
+        TokenIssuer {
+            key: derive(b"financial_managment access token"),
+            challenge_key: derive(b"financial_managment login challenge"),
+            access_ttl: ttl("ACCESS_TOKEN_TTL_MINUTES"),
+            impersonation_ttl: ttl("IMPERSONATION_TTL_MINUTES"),
+        }
+    }
+
+    /// fairing that builds the issuer from the configured `secret_key`
+    /// and puts it in managed state
+    ///
+    /// without a configured key (debug builds) a random one is used,
+    /// so tokens don't survive a restart
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_ignite("Token Issuer", |rocket| async {
+            let figment = rocket.figment();
+            let secret = match figment.extract_inner::<String>("secret_key") {
+                Ok(encoded) => decode_secret(&encoded),
+                Err(_) => figment.extract_inner::<Vec<u8>>("secret_key").ok(),
+            };
+            let secret = secret.unwrap_or_else(|| {
+                let mut random = vec![0u8; 64];
+                thread_rng().fill_bytes(&mut random);
+                random
+            });
+            rocket.manage(TokenIssuer::new(&secret))
+        })
+    }
+
+    /// mints an access token for a user's session, returns it with its expiry
+    ///
+    /// `mfa` records whether the session was started with a second factor
+    pub fn issue(
+        &self,
+        username: &str,
+        role: &str,
+        session_id: i32,
+        mfa: bool,
+    ) -> (String, NaiveDateTime) {
+        let now = Utc::now().naive_utc();
+        let expires_at = now + self.access_ttl;
+        let claims = Claims {
+            sub: username.to_string(),
+            role: role.to_string(),
+            sid: session_id,
+            mfa,
+            impersonated_by: None,
+            allow_destructive: false,
+            iat: now.timestamp(),
+            exp: expires_at.timestamp(),
+        };
+        (sign(&self.key, &claims), expires_at)
+    }
+
+    /// mints a token that lets an admin act as a user, returns it with its expiry
+    ///
+    /// it's tied to the admin's session and marked with their username.
+    /// Unless `allow_destructive` is set it can only be used to look
+    /// around, see `gaurd`
+    pub fn impersonate(
+        &self,
+        admin: &Claims,
+        username: &str,
+        role: &str,
+        allow_destructive: bool,
+    ) -> (String, NaiveDateTime) {
+        let now = Utc::now().naive_utc();
+        let expires_at = now + self.impersonation_ttl;
+        let claims = Claims {
+            sub: username.to_string(),
+            role: role.to_string(),
+            sid: admin.sid,
+            mfa: admin.mfa,
+            impersonated_by: Some(admin.sub.clone()),
+            allow_destructive,
+            iat: now.timestamp(),
+            exp: expires_at.timestamp(),
+        };
+        (sign(&self.key, &claims), expires_at)
+    }
+
+    /// checks an access token's signature and expiry and returns its claims
+    ///
+    /// whether its session is still live is up to the guards, see `gaurd`
+    pub fn verify(&self, token: &str) -> Option<Claims> {
+        let claims: Claims = open(&self.key, token)?;
+        if claims.exp <= Utc::now().timestamp() {
+            return None;
+        }
+        Some(claims)
+    }
+
+    /// mints a challenge for a user who passed the password check
+    /// but still has to enter a TOTP code, returns it with its expiry
+    pub fn issue_challenge(&self, username: &str) -> (String, NaiveDateTime) {
+        let now = Utc::now().naive_utc();
+        let expires_at = now + Duration::minutes(CHALLENGE_TTL_MINUTES);
+        let challenge = Challenge {
+            sub: username.to_string(),
+            issued_at: now,
+            exp: expires_at.timestamp(),
+        };
+        (sign(&self.challenge_key, &challenge), expires_at)
+    }
+
+    /// checks a login challenge and returns the username it was minted
+    /// for with when it was, for `User::tokens_valid`
+    pub fn verify_challenge(&self, token: &str) -> Option<(String, NaiveDateTime)> {
+        let challenge: Challenge = open(&self.challenge_key, token)?;
+        if challenge.exp <= Utc::now().timestamp() {
+            return None;
+        }
+        Some((challenge.sub, challenge.issued_at))
+    }
+}
+
+fn mac(key: &[u8]) -> HmacSha256 {
+    HmacSha256::new_from_slice(key).expect("HMAC accepts any key length")
+}
+
+fn sign<T: Serialize>(key: &[u8], claims: &T) -> String {
+    let payload = base64::encode_config(
+        serde_json::to_vec(claims).expect("claims always serialize"),
+        base64::URL_SAFE_NO_PAD,
+    );
+    let mut mac = mac(key);
+    mac.update(payload.as_bytes());
+    let signature = base64::encode_config(mac.finalize().into_bytes(), base64::URL_SAFE_NO_PAD);
+    format!("{}.{}", payload, signature)
+}
+
+/// checks a token's signature and decodes its claims, ignoring expiry
+fn open<T: DeserializeOwned>(key: &[u8], token: &str) -> Option<T> {
+    let (payload, signature) = token.split_once('.')?;
+    let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD).ok()?;
+    let mut mac = mac(key);
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&signature).ok()?;
+
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+    serde_json::from_slice(&payload).ok()
+}
+
+/// decodes `secret_key` the way Rocket accepts it, base64 or hex
+fn decode_secret(encoded: &str) -> Option<Vec<u8>> {
+    match encoded.len() {
+        64 => (0..64)
+            .step_by(2)
+            .map(|i| u8::from_str_radix(encoded.get(i..i + 2)?, 16).ok())
+            .collect(),
+        _ => base64::decode(encoded).ok(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn token_round_trip() {
+        let issuer = TokenIssuer::new(b"secret");
+        let (token, _) = issuer.issue("BerserkerMother", "user", 7, false);
+
+        let claims = issuer.verify(&token).unwrap();
+
+        assert_eq!(claims.sub, "BerserkerMother");
+        assert_eq!(claims.role, "user");
+        assert_eq!(claims.sid, 7);
+    }
+
+    #[test]
+    fn token_tampered() {
+        let issuer = TokenIssuer::new(b"secret");
+        let (token, _) = issuer.issue("BerserkerMother", "user", 7, false);
+        let (_, signature) = token.split_once('.').unwrap();
+
+        let forged = Claims {
+            sub: "BerserkerMother".to_string(),
+            role: "admin".to_string(),
+            sid: 7,
+            mfa: true,
+            impersonated_by: None,
+            allow_destructive: false,
+            iat: 0,
+            exp: i64::MAX,
+        };
+        let forged = format!(
+            "{}.{}",
+            base64::encode_config(
+                serde_json::to_vec(&forged).unwrap(),
+                base64::URL_SAFE_NO_PAD
+            ),
+            signature
+        );
+
+        assert_eq!(issuer.verify(&forged), None);
+        assert_eq!(TokenIssuer::new(b"other secret").verify(&token), None);
+    }
+
+    #[test]
+    fn token_expired() {
+        let issuer = TokenIssuer::new(b"secret");
+        let expired = sign(
+            &issuer.key,
+            &Claims {
+                sub: "BerserkerMother".to_string(),
+                role: "user".to_string(),
+                sid: 7,
+                mfa: true,
+                impersonated_by: None,
+                allow_destructive: false,
+                iat: 0,
+                exp: Utc::now().timestamp() - 1,
+            },
+        );
+
+        assert_eq!(issuer.verify(&expired), None);
+    }
+
+    #[test]
+    fn challenge_is_not_an_access_token() {
+        let issuer = TokenIssuer::new(b"secret");
+        let (challenge, _) = issuer.issue_challenge("BerserkerMother");
+        let (token, _) = issuer.issue("BerserkerMother", "user", 7, false);
+
+        assert_eq!(
+            issuer.verify_challenge(&challenge).unwrap().0,
+            "BerserkerMother"
+        );
+        assert_eq!(issuer.verify(&challenge), None);
+        assert_eq!(issuer.verify_challenge(&token), None);
+    }
+
+    #[test]
+    fn token_impersonation() {
+        let issuer = TokenIssuer::new(b"secret");
+        let (token, _) = issuer.issue("BerserkerMother", "admin", 7, true);
+        let admin = issuer.verify(&token).unwrap();
+        assert_eq!(admin.impersonated_by, None);
+
+        let (token, _) = issuer.impersonate(&admin, "test_user", "user", false);
+        let claims = issuer.verify(&token).unwrap();
+
+        assert_eq!(claims.sub, "test_user");
+        assert_eq!(claims.role, "user");
+        assert_eq!(claims.sid, 7);
+        assert_eq!(claims.impersonated_by.as_deref(), Some("BerserkerMother"));
+        assert!(!claims.allow_destructive);
+    }
+}
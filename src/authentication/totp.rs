@@ -0,0 +1,164 @@
+use crate::models::result_variant::DatabaseResult;
+use crate::models::{RecoveryCode, TotpSecret};
+use chrono::Utc;
+use diesel::PgConnection;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rand::{thread_rng, RngCore};
+use sha1::Sha1;
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// name authenticator apps show next to the code
+const ISSUER: &str = "financial_managment";
+/// seconds each code is valid for
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+/// steps before and after the current one that are still accepted,
+/// to make up for clock drift
+const SKEW_STEPS: i64 = 1;
+
+/// generates a random 160 bit shared secret, the size RFC 4226 recommends
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; 20];
+    thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// encodes a secret the way authenticator apps expect it typed in
+pub fn encode_secret(secret: &[u8]) -> String {
+    base32::encode(base32::Alphabet::RFC4648 { padding: false }, secret)
+}
+
+/// the `otpauth://` URI authenticator apps scan from a QR code
+pub fn otpauth_uri(secret: &[u8], username: &str) -> String {
+    let issuer = utf8_percent_encode(ISSUER, NON_ALPHANUMERIC);
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer,
+        utf8_percent_encode(username, NON_ALPHANUMERIC),
+        encode_secret(secret),
+        issuer,
+        DIGITS,
+        STEP_SECONDS
+    )
+}
+
+/// the time step a unix timestamp falls in
+pub fn step_at(timestamp: i64) -> i64 {
+    timestamp.div_euclid(STEP_SECONDS)
+}
+
+/// the code for a time step, as specified by RFC 6238 with HMAC-SHA1
+fn code_at(secret: &[u8], step: i64, digits: u32) -> u32 {
+    let mut mac = HmacSha1::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // dynamic truncation, RFC 4226 section 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(digits)
+}
+
+/// checks a code against the steps around `timestamp`
+///
+/// returns the step the code matched, only steps after `last_used_step`
+/// count so a code can't be replayed
+pub fn verify(
+    secret: &[u8],
+    code: &str,
+    timestamp: i64,
+    last_used_step: Option<i64>,
+) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let current = step_at(timestamp);
+    (current - SKEW_STEPS..=current + SKEW_STEPS)
+        .filter(|step| last_used_step.is_none_or(|last| *step > last))
+        .find(|step| code_at(secret, *step, DIGITS) == code)
+}
+
+/// checks a user's TOTP code, or failing that one of their recovery
+/// codes, and uses it up
+pub fn check_code(conn: &mut PgConnection, secret: &TotpSecret, code: &str) -> bool {
+    let now = Utc::now().timestamp();
+    if let Some(step) = verify(&secret.secret, code, now, secret.last_used_step) {
+        return matches!(
+            TotpSecret::use_step(conn, &secret.user_id, step),
+            DatabaseResult::Succeful(_)
+        );
+    }
+    matches!(
+        RecoveryCode::redeem(conn, &secret.user_id, code),
+        DatabaseResult::Succeful(_)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // the SHA1 test vectors from RFC 6238 appendix B
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn totp_rfc_vectors() {
+        let vectors = [
+            (59, 94287082),
+            (1111111109, 7081804),
+            (1111111111, 14050471),
+            (1234567890, 89005924),
+            (2000000000, 69279037),
+            (20000000000, 65353130),
+        ];
+        for (timestamp, code) in vectors {
+            assert_eq!(code_at(SECRET, step_at(timestamp), 8), code);
+        }
+    }
+
+    #[test]
+    fn totp_verify() {
+        let timestamp = 1111111111;
+        let code = format!("{:06}", code_at(SECRET, step_at(timestamp), DIGITS));
+
+        assert_eq!(
+            verify(SECRET, &code, timestamp, None),
+            Some(step_at(timestamp))
+        );
+        // a code from the previous step is still accepted
+        assert!(verify(SECRET, &code, timestamp + STEP_SECONDS, None).is_some());
+        assert_eq!(
+            verify(SECRET, &code, timestamp + 3 * STEP_SECONDS, None),
+            None
+        );
+        assert_eq!(verify(SECRET, "12345", timestamp, None), None);
+    }
+
+    #[test]
+    fn totp_replay() {
+        let timestamp = 1111111111;
+        let code = format!("{:06}", code_at(SECRET, step_at(timestamp), DIGITS));
+
+        assert_eq!(
+            verify(SECRET, &code, timestamp, Some(step_at(timestamp))),
+            None
+        );
+    }
+
+    #[test]
+    fn totp_uri() {
+        let uri = otpauth_uri(SECRET, "Berserker Mother");
+
+        assert!(uri.starts_with("otpauth://totp/financial%5Fmanagment:Berserker%20Mother?"));
+        assert!(uri.contains("secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"));
+    }
+}
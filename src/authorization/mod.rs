@@ -0,0 +1,278 @@
+use crate::models::result_variant::DatabaseResult;
+use crate::models::{
+    Account, AccountMember, JournalEntry, MemberRole, Posting, Transaction, Transfer,
+};
+use diesel::PgConnection;
+
+/// What a user wants to do with a resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Read,
+    Update,
+    Delete,
+    /// invite, change and remove members
+    Manage,
+}
+
+/// Who is asking for a resource
+pub trait Caller {
+    fn username(&self) -> &str;
+
+    /// whether the caller may touch the bank account, callers
+    /// restricted to some accounts (API keys) override this
+    fn may_access_account(&self, _account_id: i32) -> bool {
+        true
+    }
+}
+
+/// a bare username may access all of that user's accounts
+impl Caller for str {
+    fn username(&self) -> &str {
+        self
+    }
+}
+
+/// Access rules of a resource that belongs to a bank account
+///
+/// the account's members may touch it as far as their role allows
+pub trait Policy: Sized {
+    /// loads the resource by id
+    fn find(conn: &mut PgConnection, id: i32) -> DatabaseResult<Self>;
+
+    /// id of the bank account the resource belongs to
+    fn account_id(&self) -> i32;
+
+    /// least role a member needs to perform `action`
+    fn required_role(action: Action) -> MemberRole;
+}
+
+/// loads a resource by id if the user may perform the action on it
+///
+/// resources the user may not touch are reported as DatabaseResult::NotFound,
+/// so other users' ids can't be told apart from ids that don't exist
+pub fn authorize<T: Policy>(
+    conn: &mut PgConnection,
+    caller: &(impl Caller + ?Sized),
+    id: i32,
+    action: Action,
+) -> DatabaseResult<T> {
+    let resource = match T::find(conn, id) {
+        DatabaseResult::Succeful(resource) if caller.may_access_account(resource.account_id()) => {
+            resource
+        }
+        _ => return DatabaseResult::NotFound,
+    };
+    match AccountMember::role(conn, resource.account_id(), caller.username()) {
+        DatabaseResult::Succeful(role) if role >= T::required_role(action) => {
+            DatabaseResult::Succeful(resource)
+        }
+        _ => DatabaseResult::NotFound,
+    }
+}
+
+/// loads the legs of a transfer if the user may perform the action on
+/// every one of them, a transfer touches up to two accounts and the caller
+/// has to be allowed on both
+pub fn authorize_transfer(
+    conn: &mut PgConnection,
+    caller: &(impl Caller + ?Sized),
+    id: i32,
+    action: Action,
+) -> DatabaseResult<Vec<Transaction>> {
+    let legs = match Transfer::legs(conn, id) {
+        DatabaseResult::Succeful(legs) if !legs.is_empty() => legs,
+        _ => return DatabaseResult::NotFound,
+    };
+    let account_ids = legs.iter().map(|leg| leg.bank_account);
+    match may_touch_all(conn, caller, account_ids, action) {
+        true => DatabaseResult::Succeful(legs),
+        false => DatabaseResult::NotFound,
+    }
+}
+
+/// loads the postings of a journal entry if the user may perform the
+/// action on every account it posts to, like `authorize_transfer`
+pub fn authorize_entry(
+    conn: &mut PgConnection,
+    caller: &(impl Caller + ?Sized),
+    id: i32,
+    action: Action,
+) -> DatabaseResult<Vec<Posting>> {
+    let posting_vec = match JournalEntry::postings(conn, id) {
+        DatabaseResult::Succeful(posting_vec) if !posting_vec.is_empty() => posting_vec,
+        _ => return DatabaseResult::NotFound,
+    };
+    let account_ids = posting_vec.iter().map(|posting| posting.account_id);
+    match may_touch_all(conn, caller, account_ids, action) {
+        true => DatabaseResult::Succeful(posting_vec),
+        false => DatabaseResult::NotFound,
+    }
+}
+
+/// whether the user may perform the action on what's booked on each of
+/// the accounts, with the roles transactions need
+fn may_touch_all(
+    conn: &mut PgConnection,
+    caller: &(impl Caller + ?Sized),
+    account_ids: impl Iterator<Item = i32>,
+    action: Action,
+) -> bool {
+    for account_id in account_ids {
+        if !caller.may_access_account(account_id) {
+            return false;
+        }
+        match AccountMember::role(conn, account_id, caller.username()) {
+            DatabaseResult::Succeful(role) if role >= Transaction::required_role(action) => (),
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// viewers see a bank account, editors rename it and add to it,
+/// only owners delete it and manage its members
+impl Policy for Account {
+    fn find(conn: &mut PgConnection, id: i32) -> DatabaseResult<Account> {
+        Account::get(conn, id)
+    }
+
+    fn account_id(&self) -> i32 {
+        self.id
+    }
+
+    fn required_role(action: Action) -> MemberRole {
+        match action {
+            Action::Read => MemberRole::Viewer,
+            Action::Update => MemberRole::Editor,
+            Action::Delete | Action::Manage => MemberRole::Owner,
+        }
+    }
+}
+
+/// viewers see an account's transactions, editors change and delete them
+impl Policy for Transaction {
+    fn find(conn: &mut PgConnection, id: i32) -> DatabaseResult<Transaction> {
+        Transaction::get(conn, id)
+    }
+
+    fn account_id(&self) -> i32 {
+        self.bank_account
+    }
+
+    fn required_role(action: Action) -> MemberRole {
+        match action {
+            Action::Read => MemberRole::Viewer,
+            Action::Update | Action::Delete => MemberRole::Editor,
+            Action::Manage => MemberRole::Owner,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // make sure a test user with username "BerserkerMother" exist in database
+    use super::*;
+    use crate::establish_connection;
+    use crate::models::{NewAccount, NewAccountMember};
+
+    #[test]
+    fn authorize_owner() {
+        let mut conn = establish_connection();
+
+        let new_account = Account::new_account("Owned".to_string(), "BerserkerMother".to_string());
+        let added = Account::add(&mut conn, &new_account).unwrap();
+
+        let query_result =
+            authorize::<Account>(&mut conn, "BerserkerMother", added.id, Action::Read).unwrap();
+
+        assert_eq!(query_result, added);
+
+        // cleans up inserted row
+        Account::delete_by_id(&mut conn, added.id);
+    }
+
+    #[test]
+    fn authorize_other_user() {
+        let mut conn = establish_connection();
+
+        let added = Account::add(&mut conn, &NewAccount::default()).unwrap();
+
+        assert!(matches!(
+            authorize::<Account>(&mut conn, "someone_else", added.id, Action::Delete),
+            DatabaseResult::NotFound
+        ));
+        assert!(matches!(
+            authorize::<Transaction>(&mut conn, "BerserkerMother", -1, Action::Read),
+            DatabaseResult::NotFound
+        ));
+
+        Account::delete_by_id(&mut conn, added.id);
+    }
+
+    #[test]
+    fn authorize_member_roles() {
+        let mut conn = establish_connection();
+
+        let new_account = Account::new_account("Shared".to_string(), "BerserkerMother".to_string());
+        let added = Account::add(&mut conn, &new_account).unwrap();
+        let member = NewAccountMember::new(added.id, "test_user".to_string(), MemberRole::Viewer);
+        AccountMember::add(&mut conn, &member).unwrap();
+
+        assert!(matches!(
+            authorize::<Account>(&mut conn, "test_user", added.id, Action::Read),
+            DatabaseResult::Succeful(_)
+        ));
+        assert!(matches!(
+            authorize::<Account>(&mut conn, "test_user", added.id, Action::Update),
+            DatabaseResult::NotFound
+        ));
+
+        AccountMember::set_role(&mut conn, added.id, "test_user", MemberRole::Editor).unwrap();
+        assert!(matches!(
+            authorize::<Account>(&mut conn, "test_user", added.id, Action::Update),
+            DatabaseResult::Succeful(_)
+        ));
+        assert!(matches!(
+            authorize::<Account>(&mut conn, "test_user", added.id, Action::Manage),
+            DatabaseResult::NotFound
+        ));
+
+        Account::delete_by_id(&mut conn, added.id);
+    }
+
+    /// a caller limited to some accounts, like an API key
+    struct Restricted(Vec<i32>);
+
+    impl Caller for Restricted {
+        fn username(&self) -> &str {
+            "BerserkerMother"
+        }
+
+        fn may_access_account(&self, account_id: i32) -> bool {
+            self.0.contains(&account_id)
+        }
+    }
+
+    #[test]
+    fn authorize_restricted_caller() {
+        let mut conn = establish_connection();
+
+        let new_account = Account::new_account("Scoped".to_string(), "BerserkerMother".to_string());
+        let added = Account::add(&mut conn, &new_account).unwrap();
+
+        assert!(matches!(
+            authorize::<Account>(&mut conn, &Restricted(vec![]), added.id, Action::Read),
+            DatabaseResult::NotFound
+        ));
+        let query_result = authorize::<Account>(
+            &mut conn,
+            &Restricted(vec![added.id]),
+            added.id,
+            Action::Read,
+        )
+        .unwrap();
+        assert_eq!(query_result, added);
+
+        Account::delete_by_id(&mut conn, added.id);
+    }
+}
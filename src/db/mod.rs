@@ -1,14 +1,15 @@
 use core::ops::{Deref, DerefMut};
-use diesel::r2d2::{self, ConnectionManager, Pool, PooledConnection};
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
 use diesel::PgConnection;
 use dotenv::dotenv;
 use rocket::http::Status;
 use rocket::outcome::Outcome;
+use rocket::tokio::task;
 use rocket::State;
 use rocket::{request::FromRequest, Request};
 use std::env;
 
-type ConnPool = Pool<ConnectionManager<PgConnection>>;
+pub type ConnPool = Pool<ConnectionManager<PgConnection>>;
 
 pub struct DbConn(pub PooledConnection<ConnectionManager<PgConnection>>);
 
@@ -39,6 +40,25 @@ impl<'r> FromRequest<'r> for DbConn {
     }
 }
 
+/// runs `f` with a pooled connection on a blocking thread, for guards
+/// and other async code that mustn't stall the executor
+///
+/// returns None if there's no pool, no free connection or `f` panicked
+pub async fn run<T, F>(req: &Request<'_>, f: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut PgConnection) -> T + Send + 'static,
+{
+    let pool = req.guard::<&State<ConnPool>>().await.succeeded()?;
+    let pool = pool.inner().clone();
+    task::spawn_blocking(move || {
+        let mut conn = pool.get().ok()?;
+        Some(f(&mut conn))
+    })
+    .await
+    .ok()?
+}
+
 pub fn get_conn_pool() -> Pool<ConnectionManager<PgConnection>> {
     dotenv().ok();
 
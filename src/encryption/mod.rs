@@ -0,0 +1,241 @@
+//! Field level encryption for sensitive columns
+//!
+//! values are sealed with AES-256-GCM under the current key of a keyring
+//! configured with ENCRYPTION_KEYS, a comma separated list of `id:base64 key`
+//! pairs. The first key seals new values, the rest are only kept around to
+//! open what was sealed before a rotation, until `Transaction::reencrypt`
+//! moved everything over to the new key.
+//!
+//! sealed values look like `enc:<key id>:<base64 nonce and ciphertext>`,
+//! anything else is plaintext from before encryption was turned on
+
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use dotenv::dotenv;
+use rand::{thread_rng, RngCore};
+use rocket::fairing::AdHoc;
+use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+
+const PREFIX: &str = "enc:";
+const NONCE_LEN: usize = 12;
+
+/// keys anyone can read in the repository, nothing new may be sealed with
+/// them. They still open what they sealed, so it can be moved to a new key
+const PUBLIC_KEYS: &[&str] = &[
+    // the example in .env
+    "ZXhhbXBsZSBrZXksIGdlbmVyYXRlIHlvdXIgb3duISE=",
+    // was shipped in .env by mistake
+    "PGH2brDWZu/92CMx5rd5yZGF0Nd77Mr2cd2KhaRtDfk=",
+];
+
+/// Why a sealed value couldn't be opened
+#[derive(Debug, PartialEq, Eq)]
+pub enum EncryptionError {
+    /// sealed with a key that's no longer in the keyring
+    UnknownKey(String),
+    /// not something `seal` produced, or tampered with
+    Malformed,
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::UnknownKey(id) => write!(f, "no encryption key with id {}", id),
+            EncryptionError::Malformed => write!(f, "malformed or tampered encrypted value"),
+        }
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+/// Keys sensitive columns are encrypted with, the first one is current
+pub struct Keyring {
+    keys: Vec<(String, Aes256Gcm)>,
+    /// whether the current key is one of PUBLIC_KEYS
+    current_is_public: bool,
+}
+
+impl Keyring {
+    /// parses `id:base64 key` pairs, keys have to be 32 bytes
+    pub fn parse(config: &str) -> Result<Keyring, String> {
+        let mut keys = Vec::new();
+        let mut current_is_public = false;
+        for pair in config
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+        {
+            let (id, key) = pair
+                .split_once(':')
+                .ok_or_else(|| format!("encryption key {:?} isn't `id:base64 key`", pair))?;
+            if keys.is_empty() {
+                current_is_public = PUBLIC_KEYS.contains(&key);
+            }
+            let key = base64::decode(key)
+                .ok()
+                .filter(|key| key.len() == 32)
+                .ok_or_else(|| format!("encryption key {} isn't 32 base64 encoded bytes", id))?;
+            keys.push((id.to_string(), Aes256Gcm::new(Key::from_slice(&key))));
+        }
+        if keys.is_empty() {
+            return Err("no encryption keys configured".to_string());
+        }
+        Ok(Keyring {
+            keys,
+            current_is_public,
+        })
+    }
+
+    /// whether new values would be sealed with a key from the repository,
+    /// the server refuses to start then, see `fairing`
+    pub fn current_is_public(&self) -> bool {
+        self.current_is_public
+    }
+
+    /// id of the key new values are sealed with
+    pub fn current_id(&self) -> &str {
+        &self.keys[0].0
+    }
+
+    /// encrypts a value under the current key
+    pub fn seal(&self, plaintext: &str) -> String {
+        let (id, cipher) = &self.keys[0];
+        let mut nonce = [0u8; NONCE_LEN];
+        thread_rng().fill_bytes(&mut nonce);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            cipher
+                .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+                .expect("AES-GCM encrypts any plaintext"),
+        );
+        format!("{}{}:{}", PREFIX, id, base64::encode(sealed))
+    }
+
+    /// decrypts a sealed value with whichever key sealed it,
+    /// plaintext comes back as is
+    pub fn open(&self, value: &str) -> Result<String, EncryptionError> {
+        let rest = match value.strip_prefix(PREFIX) {
+            Some(rest) => rest,
+            None => return Ok(value.to_string()),
+        };
+        let (id, sealed) = rest.split_once(':').ok_or(EncryptionError::Malformed)?;
+        let cipher = match self.keys.iter().find(|(key_id, _)| key_id == id) {
+            Some((_, cipher)) => cipher,
+            None => return Err(EncryptionError::UnknownKey(id.to_string())),
+        };
+        let sealed = base64::decode(sealed).map_err(|_| EncryptionError::Malformed)?;
+        if sealed.len() < NONCE_LEN {
+            return Err(EncryptionError::Malformed);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| EncryptionError::Malformed)?;
+        String::from_utf8(plaintext).map_err(|_| EncryptionError::Malformed)
+    }
+
+    /// whether a stored value is sealed with the current key
+    pub fn is_current(&self, value: &str) -> bool {
+        value.starts_with(&self.current_prefix())
+    }
+
+    /// what every value sealed with the current key starts with
+    pub fn current_prefix(&self) -> String {
+        format!("{}{}:", PREFIX, self.current_id())
+    }
+}
+
+/// the keyring from ENCRYPTION_KEYS, loaded on first use
+///
+/// # panics
+/// Panics if ENCRYPTION_KEYS isn't set or can't be parsed
+pub fn keyring() -> &'static Keyring {
+    static KEYRING: OnceLock<Keyring> = OnceLock::new();
+    KEYRING.get_or_init(|| {
+        dotenv().ok();
+        let config = env::var("ENCRYPTION_KEYS").expect("ENCRYPTION_KEYS must be set");
+        Keyring::parse(&config).unwrap_or_else(|err| panic!("Invalid ENCRYPTION_KEYS: {}", err))
+    })
+}
+
+/// fairing that refuses to launch without a usable keyring, instead of
+/// failing on the first request that touches an encrypted column, or
+/// with a current key that's public, like the example in .env
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Encryption Keys", |rocket| async {
+        dotenv().ok();
+        match env::var("ENCRYPTION_KEYS").map(|config| Keyring::parse(&config)) {
+            Ok(Ok(keyring)) if keyring.current_is_public() => {
+                error!(
+                    "ENCRYPTION_KEYS starts with a published example key, put a key of your \
+                     own first, like from `openssl rand -base64 32`"
+                );
+                Err(rocket)
+            }
+            Ok(Ok(_)) => Ok(rocket),
+            Ok(Err(err)) => {
+                error!("Invalid ENCRYPTION_KEYS: {}", err);
+                Err(rocket)
+            }
+            Err(_) => {
+                error!("ENCRYPTION_KEYS must be set");
+                Err(rocket)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OLD: &str = "old:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+    const NEW: &str = "new:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+    #[test]
+    fn keyring_rotation() {
+        let old = Keyring::parse(OLD).unwrap();
+        let sealed = old.seal("Dr. Smith, oncology");
+        assert!(sealed.starts_with("enc:old:"));
+        assert_ne!(sealed, old.seal("Dr. Smith, oncology"));
+        assert_eq!(old.open(&sealed).unwrap(), "Dr. Smith, oncology");
+
+        // the old key still opens what it sealed after a rotation
+        let rotated = Keyring::parse(&format!("{},{}", NEW, OLD)).unwrap();
+        assert!(!rotated.is_current(&sealed));
+        assert_eq!(rotated.open(&sealed).unwrap(), "Dr. Smith, oncology");
+        assert!(rotated.is_current(&rotated.seal("x")));
+
+        let new = Keyring::parse(NEW).unwrap();
+        assert_eq!(
+            new.open(&sealed),
+            Err(EncryptionError::UnknownKey("old".to_string()))
+        );
+        assert_eq!(new.open("plain title").unwrap(), "plain title");
+    }
+
+    #[test]
+    fn keyring_tampering() {
+        let keyring = Keyring::parse(OLD).unwrap();
+        let sealed = keyring.seal("groceries");
+        let mut tampered = base64::decode(sealed.trim_start_matches("enc:old:")).unwrap();
+        tampered[NONCE_LEN] ^= 1;
+        let tampered = format!("enc:old:{}", base64::encode(tampered));
+        assert_eq!(keyring.open(&tampered), Err(EncryptionError::Malformed));
+
+        assert!(Keyring::parse("").is_err());
+        assert!(Keyring::parse("short:AAAA").is_err());
+    }
+
+    #[test]
+    fn keyring_public() {
+        let example = format!("example:{}", PUBLIC_KEYS[0]);
+        assert!(Keyring::parse(&example).unwrap().current_is_public());
+        // rotating away from it is fine
+        let rotated = Keyring::parse(&format!("{},{}", NEW, example)).unwrap();
+        assert!(!rotated.current_is_public());
+        assert!(!Keyring::parse(OLD).unwrap().current_is_public());
+    }
+}
@@ -0,0 +1,81 @@
+use crate::audit::{self, Action};
+use crate::db::ConnPool;
+use crate::models::result_variant::DatabaseResult;
+use crate::models::{DeletionRequest, JournalEntry, Transaction, User};
+use diesel::PgConnection;
+use rocket::fairing::AdHoc;
+use rocket::tokio::task;
+use rocket::tokio::time::{interval, Duration};
+use std::env;
+
+/// deletes the users whose deletion grace period is over, returns how many
+pub fn carry_out_deletions(conn: &mut PgConnection) -> usize {
+    let due = match DeletionRequest::due(conn) {
+        DatabaseResult::Succeful(request_vec) => request_vec,
+        _ => return 0,
+    };
+    let mut deleted = 0;
+    for request in due {
+        if let DatabaseResult::Succeful(user) = User::close(conn, &request.user_id) {
+            audit::record(conn, Action::UserDeleted, None, Some(&user.username), None);
+            deleted += 1;
+        }
+    }
+    deleted
+}
+
+/// moves encrypted columns over to the current key after a rotation,
+/// returns how many transactions and journal entries were rewritten
+pub fn reencrypt(conn: &mut PgConnection) -> usize {
+    let transactions = match Transaction::reencrypt(conn) {
+        DatabaseResult::Succeful(count) => count,
+        _ => 0,
+    };
+    let entries = match JournalEntry::reencrypt(conn) {
+        DatabaseResult::Succeful(count) => count,
+        _ => 0,
+    };
+    transactions + entries
+}
+
+/// fairing that runs the background jobs every JOBS_INTERVAL_MINUTES,
+/// 10 by default, once the server is up
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Background Jobs", |rocket| {
+        Box::pin(async move {
+            let pool = match rocket.state::<ConnPool>() {
+                Some(pool) => pool.clone(),
+                None => return error!("background jobs need a managed connection pool"),
+            };
+            let minutes = env::var("JOBS_INTERVAL_MINUTES")
+                .ok()
+                .and_then(|minutes| minutes.parse().ok())
+                .filter(|minutes| *minutes > 0)
+                .unwrap_or(10);
+            rocket::tokio::spawn(async move {
+                let mut ticks = interval(Duration::from_secs(minutes * 60));
+                loop {
+                    ticks.tick().await;
+                    let pool = pool.clone();
+                    let ran = task::spawn_blocking(move || {
+                        let mut conn = pool.get().ok()?;
+                        Some((carry_out_deletions(&mut conn), reencrypt(&mut conn)))
+                    })
+                    .await;
+                    match ran {
+                        Ok(Some((deleted, reencrypted))) => {
+                            if deleted > 0 {
+                                info!("deleted {} users", deleted);
+                            }
+                            if reencrypted > 0 {
+                                info!("re-encrypted {} transactions and entries", reencrypted);
+                            }
+                        }
+                        Ok(None) => (),
+                        Err(err) => error!("background jobs failed: {}", err),
+                    }
+                }
+            });
+        })
+    })
+}
@@ -1,7 +1,13 @@
 pub mod api;
+pub mod audit;
 pub mod authentication;
+pub mod authorization;
 pub mod db;
+pub mod encryption;
+pub mod jobs;
+pub mod mailer;
 pub mod models;
+pub mod rates;
 pub mod routes;
 mod schema;
 
@@ -18,12 +24,10 @@ use diesel::PgConnection;
 use dotenv::dotenv;
 use std::env;
 
-#[derive(Debug)]
-struct Test(i32, String);
-
 pub fn establish_connection() -> PgConnection {
     dotenv().ok();
 
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    PgConnection::establish(&database_url).expect(&format!("Error connecting to {}", database_url))
+    PgConnection::establish(&database_url)
+        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
 }
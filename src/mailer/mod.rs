@@ -0,0 +1,53 @@
+mod outbox;
+mod smtp;
+pub use outbox::OutboxMailer;
+pub use smtp::SmtpMailer;
+
+use rocket::fairing::AdHoc;
+use std::env;
+
+/// An email to a single recipient
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum MailError {
+    /// the sender or recipient isn't a valid email address
+    InvalidAddress(String),
+    /// the mail couldn't be handed off
+    Delivery(String),
+}
+
+/// Delivers mails, the one in use is picked at launch by `fairing`
+pub trait Mailer: Send + Sync {
+    fn send(&self, mail: &Mail) -> Result<(), MailError>;
+}
+
+/// fairing that puts the configured mailer in managed state
+/// as `Box<dyn Mailer>`
+///
+/// MAILER=smtp sends through SMTP_HOST (and SMTP_PORT, SMTP_USERNAME,
+/// SMTP_PASSWORD) from MAIL_FROM. Otherwise mails are written to
+/// OUTBOX_DIR, `outbox` by default, so nothing leaves the machine
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Mailer", |rocket| async {
+        let mailer: Box<dyn Mailer> = match env::var("MAILER").as_deref() {
+            Ok("smtp") => match SmtpMailer::from_env() {
+                Ok(mailer) => Box::new(mailer),
+                Err(err) => {
+                    error!("couldn't set up the SMTP mailer: {:?}", err);
+                    return Err(rocket);
+                }
+            },
+            _ => {
+                let dir = env::var("OUTBOX_DIR").unwrap_or_else(|_| "outbox".to_string());
+                Box::new(OutboxMailer::new(dir))
+            }
+        };
+        Ok(rocket.manage(mailer))
+    })
+}
@@ -0,0 +1,79 @@
+use super::{Mail, MailError, Mailer};
+use crate::authentication::random_token;
+use chrono::Utc;
+use std::fs;
+use std::path::PathBuf;
+
+/// Writes mails to files in a directory instead of sending them,
+/// for development and tests
+pub struct OutboxMailer {
+    dir: PathBuf,
+}
+
+impl OutboxMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> OutboxMailer {
+        OutboxMailer { dir: dir.into() }
+    }
+}
+
+impl Mailer for OutboxMailer {
+    /// writes the mail to `<dir>/<timestamp>-<random>.eml`
+    fn send(&self, mail: &Mail) -> Result<(), MailError> {
+        if !mail.to.contains('@') {
+            return Err(MailError::InvalidAddress(mail.to.clone()));
+        }
+        fs::create_dir_all(&self.dir).map_err(|err| MailError::Delivery(err.to_string()))?;
+        let name = format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S%.3f"),
+            &random_token()[..8]
+        );
+        let contents = format!(
+            "To: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}",
+            mail.to, mail.subject, mail.body
+        );
+        fs::write(self.dir.join(name), contents).map_err(|err| MailError::Delivery(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn outbox_writes_mail() {
+        let dir = env::temp_dir().join(format!("outbox-{}", random_token()));
+        let mailer = OutboxMailer::new(&dir);
+        let mail = Mail {
+            to: "kimia@example.com".to_string(),
+            subject: "Hi".to_string(),
+            body: "there".to_string(),
+        };
+
+        mailer.send(&mail).unwrap();
+
+        let written: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(written.len(), 1);
+        let contents = fs::read_to_string(written[0].as_ref().unwrap().path()).unwrap();
+        assert!(contents.starts_with("To: kimia@example.com\r\nSubject: Hi\r\n"));
+        assert!(contents.ends_with("\r\n\r\nthere"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn outbox_rejects_bad_address() {
+        let mailer = OutboxMailer::new(env::temp_dir());
+        let mail = Mail {
+            to: "nobody".to_string(),
+            subject: String::new(),
+            body: String::new(),
+        };
+
+        assert!(matches!(
+            mailer.send(&mail),
+            Err(MailError::InvalidAddress(_))
+        ));
+    }
+}
@@ -0,0 +1,73 @@
+use super::{Mail, MailError, Mailer};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use std::env;
+
+/// Sends mails through an SMTP relay over STARTTLS
+pub struct SmtpMailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, String)>,
+        from: &str,
+    ) -> Result<SmtpMailer, MailError> {
+        let from = from
+            .parse()
+            .map_err(|_| MailError::InvalidAddress(from.to_string()))?;
+        let mut transport = SmtpTransport::starttls_relay(host)
+            .map_err(|err| MailError::Delivery(err.to_string()))?
+            .port(port);
+        if let Some((username, password)) = credentials {
+            transport = transport.credentials(Credentials::new(username, password));
+        }
+        Ok(SmtpMailer {
+            transport: transport.build(),
+            from,
+        })
+    }
+
+    /// configures the relay from SMTP_HOST, SMTP_PORT (587 by default),
+    /// SMTP_USERNAME, SMTP_PASSWORD and MAIL_FROM
+    pub fn from_env() -> Result<SmtpMailer, MailError> {
+        let host = env::var("SMTP_HOST")
+            .map_err(|_| MailError::Delivery("SMTP_HOST must be set".to_string()))?;
+        let port = env::var("SMTP_PORT")
+            .ok()
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(587);
+        let credentials = match (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+            (Ok(username), Ok(password)) => Some((username, password)),
+            _ => None,
+        };
+        let from = env::var("MAIL_FROM")
+            .map_err(|_| MailError::InvalidAddress("MAIL_FROM must be set".to_string()))?;
+        SmtpMailer::new(&host, port, credentials, &from)
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, mail: &Mail) -> Result<(), MailError> {
+        let to = mail
+            .to
+            .parse()
+            .map_err(|_| MailError::InvalidAddress(mail.to.clone()))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(&mail.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(mail.body.clone())
+            .map_err(|err| MailError::Delivery(err.to_string()))?;
+        self.transport
+            .send(&message)
+            .map(|_| ())
+            .map_err(|err| MailError::Delivery(err.to_string()))
+    }
+}
@@ -8,4 +8,8 @@ fn rocket() -> _ {
         .mount("/", routes::stage())
         .mount("/api", api::stage())
         .manage(get_conn_pool())
+        .attach(authentication::TokenIssuer::fairing())
+        .attach(encryption::fairing())
+        .attach(mailer::fairing())
+        .attach(jobs::fairing())
 }
@@ -1,24 +1,32 @@
-use super::schema::account;
+use super::schema::{account, account_members};
 use super::*;
-use serde::Serialize;
 
-#[derive(Queryable, Debug, PartialEq, Serialize, AsChangeset, Deserialize)]
+/// An account in its owner's chart of accounts, bank accounts are assets
+///
+/// `balance` follows the postings on it, debits count positive and credits
+/// negative, see `JournalEntry`. Every posting on it is in its currency
+#[derive(Queryable, Debug, PartialEq)]
 #[diesel(table_name = account)]
 pub struct Account {
-    pub balance: String,
+    pub balance: Amount,
     pub user_id: String,
     pub id: i32,
     pub name: String,
+    pub kind: AccountKind,
+    pub currency: CurrencyCode,
 }
 
 impl Account {
     /// Constructor for Account
-    fn new(balance: &str, user_id: &str, id: i32, name: &str) -> Account {
+    #[cfg(test)]
+    fn new(balance: &Amount, user_id: &str, id: i32, name: &str) -> Account {
         Account {
-            balance: String::from(balance),
+            balance: balance.clone(),
             user_id: String::from(user_id),
             id,
             name: String::from(name),
+            kind: AccountKind::Asset,
+            currency: "USD".parse().unwrap(),
         }
     }
 
@@ -43,10 +51,16 @@ impl Account {
             ),
         }
     }
-    /// gets all user accounts
+    /// gets all accounts a user is a member of, owned or shared
     pub fn all(conn: &mut PgConnection, user_id: String) -> DatabaseResult<Vec<Account>> {
-        use super::schema::account::user_id as ui;
-        match account::table.filter(ui.eq(user_id)).load::<Account>(conn) {
+        use super::schema::account::id as i;
+        use super::schema::account_members::{account_id as ai, user_id as ui};
+        let memberships = account_members::table.filter(ui.eq(user_id)).select(ai);
+        match account::table
+            .filter(i.eq_any(memberships))
+            .order(i.asc())
+            .load::<Account>(conn)
+        {
             Ok(acc_vec) => DatabaseResult::Succeful(acc_vec),
             Err(e) => panic!("panics for unknown reason, Error message: {}", e),
         }
@@ -57,12 +71,24 @@ impl Account {
     /// returns DatabaseResult::Successful(Account) if acount doesn't exists
     ///
     /// returns DatabaseResult::AlreadyExists if Account already exist
+    ///
+    /// returns DatabaseResult::NotFound if its currency isn't in the registry
+    ///
+    /// the user opening it becomes its owner
     pub fn add(conn: &mut PgConnection, new_account: &NewAccount) -> DatabaseResult<Account> {
-        match diesel::insert_into(account::table)
-            .values(new_account)
-            .get_result::<Account>(conn)
-        {
+        let added = conn.transaction::<_, Error, _>(|conn| {
+            let acc = diesel::insert_into(account::table)
+                .values(new_account)
+                .get_result::<Account>(conn)?;
+            Account::add_owner(conn, &acc)?;
+            Ok(acc)
+        });
+        match added {
             Ok(acc) => DatabaseResult::Succeful(acc),
+            Err(Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                _,
+            )) => DatabaseResult::NotFound,
             Err(Error::DatabaseError(_, _)) => DatabaseResult::AlreadyExists,
             Err(err) => panic!(
                 "Something went wrong while inserting data, Error message: {}",
@@ -71,15 +97,51 @@ impl Account {
         }
     }
 
-    /// updates an account
-    pub fn update(
+    /// finds the owner's account of that kind, name and currency, opening
+    /// it if there's none yet, run inside a database transaction
+    pub(super) fn category(
         conn: &mut PgConnection,
-        id: i32,
-        new_update: &Account,
-    ) -> DatabaseResult<Account> {
-        use super::schema::account::id as i;
+        owner: &str,
+        kind: AccountKind,
+        name: &str,
+        currency: &CurrencyCode,
+    ) -> QueryResult<Account> {
+        use super::schema::account::{currency as c, kind as k, name as n, user_id as ui};
+        let new_account = NewAccount::new(owner.to_string(), name.to_string())
+            .kind(kind)
+            .currency(currency.clone());
+        let opened = diesel::insert_into(account::table)
+            .values(&new_account)
+            .on_conflict_do_nothing()
+            .get_result::<Account>(conn)
+            .optional()?;
+        match opened {
+            Some(acc) => {
+                Account::add_owner(conn, &acc)?;
+                Ok(acc)
+            }
+            None => account::table
+                .filter(ui.eq(owner))
+                .filter(k.eq(kind))
+                .filter(n.eq(name))
+                .filter(c.eq(currency))
+                .first::<Account>(conn),
+        }
+    }
+
+    /// makes whoever opened the account its owner
+    fn add_owner(conn: &mut PgConnection, acc: &Account) -> QueryResult<usize> {
+        let owner = NewAccountMember::new(acc.id, acc.user_id.clone(), MemberRole::Owner);
+        diesel::insert_into(account_members::table)
+            .values(&owner)
+            .execute(conn)
+    }
+
+    /// renames an account, the only thing about it that's changed directly
+    pub fn rename(conn: &mut PgConnection, id: i32, name: &str) -> DatabaseResult<Account> {
+        use super::schema::account::{id as i, name as n};
         match diesel::update(account::table.filter(i.eq(id)))
-            .set(new_update)
+            .set(n.eq(name))
             .get_result::<Account>(conn)
         {
             Ok(acc) => DatabaseResult::Succeful(acc),
@@ -88,13 +150,31 @@ impl Account {
         }
     }
 
+    /// rebuilds every account's balance from its postings,
+    /// returns how many accounts there are
+    pub fn recompute_balances(conn: &mut PgConnection) -> DatabaseResult<usize> {
+        let recomputed = diesel::sql_query(
+            "UPDATE account SET balance = COALESCE((
+                SELECT SUM(amount) FROM postings WHERE account_id = account.id
+            ), 0)",
+        )
+        .execute(conn);
+        match recomputed {
+            Ok(count) => DatabaseResult::Succeful(count),
+            Err(err) => panic!("something went terribly  wrong, Error message: {}", err),
+        }
+    }
+
     /// delete a bank account from account
     /// return DatabaseResult::Successful(Account) if account is successfully deleted
     ///
     /// returns DatabaseResult::NotFound if there is no such account by id
+    ///
+    /// its transactions go too, transfers with their other legs. Categories
+    /// with postings and accounts journal entries still post to are refused
+    /// with DatabaseResult::NotFound, see `Account::remove`
     pub fn delete_by_id(conn: &mut PgConnection, id: i32) -> DatabaseResult<Account> {
-        use super::schema::account::id as i;
-        match diesel::delete(account::table.filter(i.eq(id))).get_result::<Account>(conn) {
+        match conn.transaction(|conn| Account::remove(conn, id)) {
             Ok(new_acc) => DatabaseResult::Succeful(new_acc),
             Err(Error::NotFound) => DatabaseResult::NotFound,
             Err(err) => panic!(
@@ -114,12 +194,17 @@ impl Account {
         name: &str,
         user_id: &str,
     ) -> DatabaseResult<Account> {
-        use super::schema::account::{name as n, user_id as ui};
-        match diesel::delete(account::table)
-            .filter(ui.eq(user_id))
-            .filter(n.eq(name))
-            .get_result::<Account>(conn)
-        {
+        use super::schema::account::{id as i, kind as k, name as n, user_id as ui};
+        let deleted = conn.transaction(|conn| {
+            let id = account::table
+                .filter(ui.eq(user_id))
+                .filter(k.eq(AccountKind::Asset))
+                .filter(n.eq(name))
+                .select(i)
+                .first::<i32>(conn)?;
+            Account::remove(conn, id)
+        });
+        match deleted {
             Ok(acc) => DatabaseResult::Succeful(acc),
             Err(Error::NotFound) => DatabaseResult::NotFound,
             Err(err) => panic!(
@@ -128,40 +213,149 @@ impl Account {
             ),
         }
     }
+
+    /// deletes an account with the transactions on it, run inside a
+    /// database transaction
+    ///
+    /// categories are shared by every transaction of their owner's books
+    /// and entries post to other accounts too, so deleting an account
+    /// either of them still posts to would change those. That's refused
+    /// with Error::NotFound, the entries have to be deleted first
+    pub(super) fn remove(conn: &mut PgConnection, id: i32) -> QueryResult<Account> {
+        use super::schema::account::id as i;
+        let acc = account::table.filter(i.eq(id)).first::<Account>(conn)?;
+        if !acc.kind.is_category() {
+            Transaction::remove_all(conn, id)?;
+        }
+        if Account::has_postings(conn, id)? {
+            return Err(Error::NotFound);
+        }
+        diesel::delete(account::table.filter(i.eq(id))).get_result::<Account>(conn)
+    }
+
+    /// deletes an account with every journal entry that has a posting
+    /// on it, run inside a database transaction, for closing its owner's
+    /// books
+    ///
+    /// transfers go whole, their legs on accounts that stay too
+    pub(super) fn purge(conn: &mut PgConnection, id: i32) -> QueryResult<Account> {
+        use super::schema::account::id as i;
+        Transaction::remove_all(conn, id)?;
+        JournalEntry::remove_touching(conn, id)?;
+        diesel::delete(account::table.filter(i.eq(id))).get_result::<Account>(conn)
+    }
+
+    fn has_postings(conn: &mut PgConnection, id: i32) -> QueryResult<bool> {
+        use super::schema::postings::{self, account_id as ai};
+        diesel::select(diesel::dsl::exists(postings::table.filter(ai.eq(id)))).get_result(conn)
+    }
 }
 
 #[derive(Debug, Insertable)]
 #[diesel(table_name = account)]
 pub struct NewAccount {
-    balance: String,
+    balance: Amount,
     user_id: String,
     name: String,
+    kind: AccountKind,
+    currency: CurrencyCode,
 }
 
-impl<'a> NewAccount {
+impl NewAccount {
     fn new(user_id: String, name: String) -> NewAccount {
         NewAccount {
-            balance: "0".to_string(),
+            balance: Amount::zero(),
             user_id,
             name,
+            kind: AccountKind::Asset,
+            currency: "USD".parse().unwrap(),
         }
     }
-}
 
-use crate::api::account::AccountData;
-impl From<AccountData> for NewAccount {
-    fn from(data: AccountData) -> NewAccount {
-        let AccountData { name, user_id } = data;
-        NewAccount::new(user_id, name)
+    /// sets what kind of account it is, bank accounts are assets
+    pub fn kind(mut self, kind: AccountKind) -> NewAccount {
+        self.kind = kind;
+        self
+    }
+
+    /// sets the currency its books are kept in, USD unless set
+    pub fn currency(mut self, currency: CurrencyCode) -> NewAccount {
+        self.currency = currency;
+        self
     }
 }
 
 impl Default for NewAccount {
     fn default() -> NewAccount {
         NewAccount {
-            balance: "0".to_string(),
+            balance: Amount::zero(),
             user_id: "BerserkerMother".to_string(),
             name: "American Express".to_string(),
+            kind: AccountKind::Asset,
+            currency: "USD".parse().unwrap(),
+        }
+    }
+}
+
+/// Where an account sits in the chart of accounts
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, AsExpression, FromSqlRow, Serialize, Deserialize,
+)]
+#[diesel(sql_type = crate::schema::sql_types::AccountKind)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountKind {
+    /// what the owner has, bank accounts among them
+    #[default]
+    Asset,
+    /// what the owner owes, like a credit card
+    Liability,
+    /// what's left of the assets after the liabilities
+    Equity,
+    /// categories money comes in from
+    Income,
+    /// categories money goes out to
+    Expense,
+}
+
+impl AccountKind {
+    /// whether the account normally has a debit balance, assets and
+    /// expenses do, the others normally have credit balances
+    pub fn is_debit_normal(self) -> bool {
+        matches!(self, AccountKind::Asset | AccountKind::Expense)
+    }
+
+    /// whether transactions are booked against it, income, expenses and
+    /// equity are, see `TransactionKind::category`
+    pub fn is_category(self) -> bool {
+        matches!(
+            self,
+            AccountKind::Income | AccountKind::Expense | AccountKind::Equity
+        )
+    }
+}
+
+impl ToSql<crate::schema::sql_types::AccountKind, Pg> for AccountKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            AccountKind::Asset => out.write_all(b"asset")?,
+            AccountKind::Liability => out.write_all(b"liability")?,
+            AccountKind::Equity => out.write_all(b"equity")?,
+            AccountKind::Income => out.write_all(b"income")?,
+            AccountKind::Expense => out.write_all(b"expense")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<crate::schema::sql_types::AccountKind, Pg> for AccountKind {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"asset" => Ok(AccountKind::Asset),
+            b"liability" => Ok(AccountKind::Liability),
+            b"equity" => Ok(AccountKind::Equity),
+            b"income" => Ok(AccountKind::Income),
+            b"expense" => Ok(AccountKind::Expense),
+            _ => Err("Unrecognized enum variant".into()),
         }
     }
 }
@@ -181,6 +375,7 @@ mod test {
             balance,
             user_id,
             name,
+            ..
         } = &new_account;
 
         let query_result = Account::add(&mut conn, &new_account).unwrap();
@@ -204,6 +399,95 @@ mod test {
         Account::delete_by_name_user(&mut conn, name, user_id).unwrap();
     }
 
+    #[test]
+    fn account_delete_posted() {
+        let mut conn = establish_connection();
+        User::close(&mut conn, "deleting_user");
+        let new_user = NewUser::new(
+            "Deleting".to_string(),
+            "deleting_user".to_string(),
+            "hash".to_string(),
+        );
+        User::add(&mut conn, &new_user).unwrap();
+        let open = |conn: &mut PgConnection, name: &str| {
+            let new_account = Account::new_account(name.to_string(), "deleting_user".to_string());
+            Account::add(conn, &new_account).unwrap()
+        };
+        let checking = open(&mut conn, "Checking");
+        let savings = open(&mut conn, "Savings");
+        let amount = |value: &str| value.parse::<Amount>().unwrap();
+        let usd: CurrencyCode = "USD".parse().unwrap();
+
+        let salary = NewTransaction {
+            value: amount("100"),
+            user_id: "deleting_user".to_string(),
+            bank_account: checking.id,
+            ..Default::default()
+        };
+        let salary = Transaction::add(&mut conn, &salary).unwrap();
+        let order = TransferOrder {
+            user_id: "deleting_user".to_string(),
+            from_account: checking.id,
+            to_account: savings.id,
+            amount: amount("40"),
+            currency: usd.clone(),
+            to_currency: usd.clone(),
+            rate: "1".parse().unwrap(),
+            fee: amount("1"),
+            title: "Savings".to_string(),
+        };
+        let (transfer, _) = Transfer::add(&mut conn, &order).unwrap();
+        let card = NewJournalEntry {
+            user_id: "deleting_user".to_string(),
+            description: Some("Card payment".to_string()),
+            time: salary.time,
+            postings: vec![
+                NewPosting::new(checking.id, amount("-5"), usd.clone()),
+                NewPosting::new(savings.id, amount("5"), usd),
+            ],
+        };
+        let (card, _) = JournalEntry::add(&mut conn, &card).unwrap();
+
+        // categories carry every transaction of the books, they stay
+        let categories = Account::all(&mut conn, "deleting_user".to_string())
+            .unwrap()
+            .into_iter()
+            .filter(|acc| acc.kind.is_category())
+            .collect::<Vec<_>>();
+        assert_eq!(categories.len(), 3);
+        for category in &categories {
+            assert!(matches!(
+                Account::delete_by_id(&mut conn, category.id),
+                DatabaseResult::NotFound
+            ));
+        }
+        assert_eq!(Transaction::get(&mut conn, salary.id).unwrap(), salary);
+
+        // and so do accounts an entry still posts to
+        assert!(matches!(
+            Account::delete_by_id(&mut conn, checking.id),
+            DatabaseResult::NotFound
+        ));
+        Transfer::get(&mut conn, transfer.id).unwrap();
+        JournalEntry::delete(&mut conn, card.id).unwrap();
+
+        // transfers go whole, their legs on the other account too
+        Account::delete_by_id(&mut conn, checking.id).unwrap();
+        assert!(matches!(
+            Transfer::get(&mut conn, transfer.id),
+            DatabaseResult::NotFound
+        ));
+        assert_eq!(
+            Account::get(&mut conn, savings.id).unwrap().balance,
+            Amount::zero()
+        );
+        for category in &categories {
+            Account::delete_by_id(&mut conn, category.id).unwrap();
+        }
+
+        User::close(&mut conn, "deleting_user").unwrap();
+    }
+
     #[test]
     fn account_get() {
         let mut conn = establish_connection();
@@ -213,6 +497,7 @@ mod test {
             name,
             balance,
             user_id,
+            ..
         } = &new_account;
 
         let query_result = Account::add(&mut conn, &new_account).unwrap();
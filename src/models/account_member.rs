@@ -0,0 +1,261 @@
+use super::schema::account_members;
+use super::*;
+use chrono::{NaiveDateTime, Utc};
+use diesel::sql_types::Text;
+
+/// What a member may do with a shared bank account, each role
+/// can do everything the ones before it can
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    AsExpression,
+    FromSqlRow,
+    Serialize,
+    Deserialize,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberRole {
+    /// sees the account and its transactions
+    Viewer,
+    /// adds and deletes transactions and renames the account
+    Editor,
+    /// manages members and deletes the account
+    Owner,
+}
+
+impl ToSql<Text, Pg> for MemberRole {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            MemberRole::Viewer => out.write_all(b"viewer")?,
+            MemberRole::Editor => out.write_all(b"editor")?,
+            MemberRole::Owner => out.write_all(b"owner")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for MemberRole {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"viewer" => Ok(MemberRole::Viewer),
+            b"editor" => Ok(MemberRole::Editor),
+            b"owner" => Ok(MemberRole::Owner),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+/// A user's membership in a bank account
+#[derive(Queryable, Debug, PartialEq, Serialize)]
+pub struct AccountMember {
+    pub account_id: i32,
+    pub user_id: String,
+    pub role: MemberRole,
+    pub added_at: NaiveDateTime,
+}
+
+impl AccountMember {
+    /// adds a member to an account
+    ///
+    /// returns DatabaseResult::AlreadyExists if the user is already a member
+    /// or either of them doesn't exist
+    pub fn add(
+        conn: &mut PgConnection,
+        member: &NewAccountMember,
+    ) -> DatabaseResult<AccountMember> {
+        match diesel::insert_into(account_members::table)
+            .values(member)
+            .get_result::<AccountMember>(conn)
+        {
+            Ok(member) => DatabaseResult::Succeful(member),
+            Err(Error::DatabaseError(_, _)) => DatabaseResult::AlreadyExists,
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets the role a user has in an account
+    pub fn role(
+        conn: &mut PgConnection,
+        account_id: i32,
+        user_id: &str,
+    ) -> DatabaseResult<MemberRole> {
+        use super::schema::account_members::role as r;
+        match account_members::table
+            .find((account_id, user_id))
+            .select(r)
+            .first::<MemberRole>(conn)
+        {
+            Ok(role) => DatabaseResult::Succeful(role),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets all members of an account, owners first
+    pub fn all(conn: &mut PgConnection, account_id: i32) -> DatabaseResult<Vec<AccountMember>> {
+        use super::schema::account_members::{account_id as ai, added_at as aa};
+        match account_members::table
+            .filter(ai.eq(account_id))
+            .order(aa.asc())
+            .load::<AccountMember>(conn)
+        {
+            Ok(mut member_vec) => {
+                member_vec.sort_by_key(|member| std::cmp::Reverse(member.role));
+                DatabaseResult::Succeful(member_vec)
+            }
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        }
+    }
+
+    /// changes a member's role
+    ///
+    /// returns DatabaseResult::NotFound if the user isn't a member or the
+    /// account would be left without an owner
+    pub fn set_role(
+        conn: &mut PgConnection,
+        account_id: i32,
+        user_id: &str,
+        role: MemberRole,
+    ) -> DatabaseResult<AccountMember> {
+        use super::schema::account_members::role as r;
+        AccountMember::keeping_an_owner(conn, account_id, |conn| {
+            diesel::update(account_members::table.find((account_id, user_id)))
+                .set(r.eq(role))
+                .get_result::<AccountMember>(conn)
+        })
+    }
+
+    /// removes a member from an account
+    ///
+    /// returns DatabaseResult::NotFound if the user isn't a member or they're
+    /// the last owner
+    pub fn delete(
+        conn: &mut PgConnection,
+        account_id: i32,
+        user_id: &str,
+    ) -> DatabaseResult<AccountMember> {
+        AccountMember::keeping_an_owner(conn, account_id, |conn| {
+            diesel::delete(account_members::table.find((account_id, user_id)))
+                .get_result::<AccountMember>(conn)
+        })
+    }
+
+    /// runs `change` in a transaction that's rolled back if it leaves
+    /// the account without an owner
+    fn keeping_an_owner(
+        conn: &mut PgConnection,
+        account_id: i32,
+        change: impl FnOnce(&mut PgConnection) -> QueryResult<AccountMember>,
+    ) -> DatabaseResult<AccountMember> {
+        use super::schema::account_members::{account_id as ai, role as r};
+        let changed = conn.transaction::<_, Error, _>(|conn| {
+            let member = change(conn)?;
+            let owners = account_members::table
+                .filter(ai.eq(account_id))
+                .filter(r.eq(MemberRole::Owner))
+                .count()
+                .get_result::<i64>(conn)?;
+            match owners {
+                0 => Err(Error::RollbackTransaction),
+                _ => Ok(member),
+            }
+        });
+        match changed {
+            Ok(member) => DatabaseResult::Succeful(member),
+            Err(Error::NotFound) | Err(Error::RollbackTransaction) => DatabaseResult::NotFound,
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = account_members)]
+pub struct NewAccountMember {
+    account_id: i32,
+    user_id: String,
+    role: MemberRole,
+    added_at: NaiveDateTime,
+}
+
+impl NewAccountMember {
+    pub fn new(account_id: i32, user_id: String, role: MemberRole) -> NewAccountMember {
+        NewAccountMember {
+            account_id,
+            user_id,
+            role,
+            added_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // make sure test users "BerserkerMother" and "test_user" exist in database
+    use super::super::establish_connection;
+    use super::*;
+
+    #[test]
+    fn account_member_roles() {
+        let mut conn = establish_connection();
+        let new_account = Account::new_account("Joint".to_string(), "BerserkerMother".to_string());
+        let added = Account::add(&mut conn, &new_account).unwrap();
+
+        // whoever opens an account owns it
+        assert_eq!(
+            AccountMember::role(&mut conn, added.id, "BerserkerMother").unwrap(),
+            MemberRole::Owner
+        );
+        let member = NewAccountMember::new(added.id, "test_user".to_string(), MemberRole::Viewer);
+        AccountMember::add(&mut conn, &member).unwrap();
+        assert!(matches!(
+            AccountMember::add(&mut conn, &member),
+            DatabaseResult::AlreadyExists
+        ));
+        assert!(Account::all(&mut conn, "test_user".to_string())
+            .unwrap()
+            .contains(&added));
+
+        AccountMember::set_role(&mut conn, added.id, "test_user", MemberRole::Editor).unwrap();
+        let members = AccountMember::all(&mut conn, added.id).unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].user_id, "BerserkerMother");
+        assert_eq!(members[1].role, MemberRole::Editor);
+
+        // cleans up inserted rows, members cascade
+        Account::delete_by_id(&mut conn, added.id);
+    }
+
+    #[test]
+    fn account_member_last_owner() {
+        let mut conn = establish_connection();
+        let new_account = Account::new_account("Solo".to_string(), "BerserkerMother".to_string());
+        let added = Account::add(&mut conn, &new_account).unwrap();
+
+        assert!(matches!(
+            AccountMember::set_role(&mut conn, added.id, "BerserkerMother", MemberRole::Viewer),
+            DatabaseResult::NotFound
+        ));
+        assert!(matches!(
+            AccountMember::delete(&mut conn, added.id, "BerserkerMother"),
+            DatabaseResult::NotFound
+        ));
+        assert_eq!(
+            AccountMember::role(&mut conn, added.id, "BerserkerMother").unwrap(),
+            MemberRole::Owner
+        );
+
+        Account::delete_by_id(&mut conn, added.id);
+    }
+}
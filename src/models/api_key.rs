@@ -0,0 +1,194 @@
+use super::schema::api_keys;
+use super::*;
+use crate::authentication::hasher::token_hash;
+use crate::authentication::random_token;
+use chrono::{NaiveDateTime, Utc};
+
+/// every key starts with this, so they're recognizable in scripts and logs
+pub const KEY_PREFIX: &str = "fm_";
+
+/// A named key a user creates for scripts, limited to some scopes
+/// and optionally to some of their accounts
+///
+/// only the hash of the key is stored, `prefix` identifies it in listings
+#[derive(Queryable, Debug, Clone, PartialEq, Serialize)]
+pub struct ApiKey {
+    pub id: i32,
+    pub user_id: String,
+    pub name: String,
+    pub prefix: String,
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    /// None means every account of the user
+    pub account_ids: Option<Vec<i32>>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+impl ApiKey {
+    /// stores a new key
+    pub fn add(conn: &mut PgConnection, new_key: &NewApiKey) -> DatabaseResult<ApiKey> {
+        match diesel::insert_into(api_keys::table)
+            .values(new_key)
+            .get_result::<ApiKey>(conn)
+        {
+            Ok(key) => DatabaseResult::Succeful(key),
+            Err(Error::DatabaseError(_, _)) => DatabaseResult::AlreadyExists,
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// looks up an unexpired key and marks it as used now
+    pub fn authenticate(conn: &mut PgConnection, key: &str) -> DatabaseResult<ApiKey> {
+        use super::schema::api_keys::{expires_at as ea, key_hash as kh, last_used_at as lu};
+        let now = Utc::now().naive_utc();
+        match diesel::update(
+            api_keys::table
+                .filter(kh.eq(token_hash(key)))
+                .filter(ea.is_null().or(ea.gt(now))),
+        )
+        .set(lu.eq(now))
+        .get_result::<ApiKey>(conn)
+        {
+            Ok(key) => DatabaseResult::Succeful(key),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
+        }
+    }
+
+    /// gets all keys of a user, newest first
+    pub fn all(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<Vec<ApiKey>> {
+        use super::schema::api_keys::{created_at as ca, user_id as ui};
+        match api_keys::table
+            .filter(ui.eq(user_id))
+            .order(ca.desc())
+            .load::<ApiKey>(conn)
+        {
+            Ok(key_vec) => DatabaseResult::Succeful(key_vec),
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        }
+    }
+
+    /// deletes one of a user's keys
+    ///
+    /// returns DatabaseResult::NotFound if the key doesn't
+    /// exist or belongs to someone else
+    pub fn delete(conn: &mut PgConnection, id: i32, user_id: &str) -> DatabaseResult<ApiKey> {
+        use super::schema::api_keys::{id as i, user_id as ui};
+        match diesel::delete(api_keys::table.filter(i.eq(id)).filter(ui.eq(user_id)))
+            .get_result::<ApiKey>(conn)
+        {
+            Ok(key) => DatabaseResult::Succeful(key),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while deleting data, Error message: {}",
+                err
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = api_keys)]
+pub struct NewApiKey {
+    user_id: String,
+    name: String,
+    prefix: String,
+    key_hash: String,
+    scopes: Vec<String>,
+    account_ids: Option<Vec<i32>>,
+    created_at: NaiveDateTime,
+    expires_at: Option<NaiveDateTime>,
+}
+
+impl NewApiKey {
+    /// generates a new key, returns it with the row to store
+    ///
+    /// the key itself is never stored, it has to be shown to the user now
+    pub fn new(
+        user_id: String,
+        name: String,
+        scopes: Vec<String>,
+        account_ids: Option<Vec<i32>>,
+        expires_at: Option<NaiveDateTime>,
+    ) -> (NewApiKey, String) {
+        let key = format!("{}{}", KEY_PREFIX, random_token());
+        let new_key = NewApiKey {
+            user_id,
+            name,
+            prefix: key[..KEY_PREFIX.len() + 6].to_string(),
+            key_hash: token_hash(&key),
+            scopes,
+            account_ids,
+            created_at: Utc::now().naive_utc(),
+            expires_at,
+        };
+        (new_key, key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // make sure a test user with username "BerserkerMother" exist in database
+    use super::super::establish_connection;
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn api_key_authenticate() {
+        let mut conn = establish_connection();
+
+        let (new_key, key) = NewApiKey::new(
+            "BerserkerMother".to_string(),
+            "import script".to_string(),
+            vec!["transactions:read".to_string()],
+            Some(vec![1, 2]),
+            None,
+        );
+        let added = ApiKey::add(&mut conn, &new_key).unwrap();
+        assert!(key.starts_with(&added.prefix));
+        assert_eq!(added.last_used_at, None);
+
+        let query_result = ApiKey::authenticate(&mut conn, &key).unwrap();
+        assert_eq!(query_result.id, added.id);
+        assert_eq!(query_result.account_ids, Some(vec![1, 2]));
+        assert!(query_result.last_used_at.is_some());
+        assert!(matches!(
+            ApiKey::authenticate(&mut conn, "fm_wrong"),
+            DatabaseResult::NotFound
+        ));
+
+        // cleans up inserted row
+        ApiKey::delete(&mut conn, added.id, "BerserkerMother");
+    }
+
+    #[test]
+    fn api_key_expired() {
+        let mut conn = establish_connection();
+
+        let (new_key, key) = NewApiKey::new(
+            "BerserkerMother".to_string(),
+            "old script".to_string(),
+            vec![],
+            None,
+            Some(Utc::now().naive_utc() - Duration::minutes(1)),
+        );
+        let added = ApiKey::add(&mut conn, &new_key).unwrap();
+
+        assert!(matches!(
+            ApiKey::authenticate(&mut conn, &key),
+            DatabaseResult::NotFound
+        ));
+        assert!(matches!(
+            ApiKey::delete(&mut conn, added.id, "someone_else"),
+            DatabaseResult::NotFound
+        ));
+
+        ApiKey::delete(&mut conn, added.id, "BerserkerMother").unwrap();
+    }
+}
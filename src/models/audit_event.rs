@@ -0,0 +1,163 @@
+use super::schema::audit_events;
+use super::*;
+use chrono::{NaiveDateTime, Utc};
+
+/// A record of something a user did, see crate::audit
+///
+/// the table is append-only, a trigger refuses updates and deletes
+#[derive(Queryable, Debug, PartialEq, Serialize)]
+pub struct AuditEvent {
+    pub id: i32,
+    /// who did it, for failed logins the username that was tried
+    pub actor: Option<String>,
+    pub action: String,
+    /// what it was done to, like a username or an account id
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// what exactly was done, like the request an impersonating admin made
+    pub detail: Option<String>,
+}
+
+/// Narrows down AuditEvent::search, unset fields match everything
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub from: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
+
+impl AuditEvent {
+    /// records an event
+    pub fn add(conn: &mut PgConnection, event: &NewAuditEvent) -> DatabaseResult<AuditEvent> {
+        match diesel::insert_into(audit_events::table)
+            .values(event)
+            .get_result::<AuditEvent>(conn)
+        {
+            Ok(event) => DatabaseResult::Succeful(event),
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets the events matching a filter, newest first
+    pub fn search(
+        conn: &mut PgConnection,
+        filter: &AuditFilter,
+    ) -> DatabaseResult<Vec<AuditEvent>> {
+        use super::schema::audit_events::{action as a, actor as ac, created_at as ca, id as i};
+        let mut query = audit_events::table
+            .order((ca.desc(), i.desc()))
+            .into_boxed();
+        if let Some(actor) = &filter.actor {
+            query = query.filter(ac.eq(actor));
+        }
+        if let Some(action) = &filter.action {
+            query = query.filter(a.eq(action));
+        }
+        if let Some(from) = filter.from {
+            query = query.filter(ca.ge(from));
+        }
+        if let Some(until) = filter.until {
+            query = query.filter(ca.lt(until));
+        }
+        match query.load::<AuditEvent>(conn) {
+            Ok(event_vec) => DatabaseResult::Succeful(event_vec),
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = audit_events)]
+pub struct NewAuditEvent {
+    actor: Option<String>,
+    action: String,
+    target: Option<String>,
+    ip: Option<String>,
+    created_at: NaiveDateTime,
+    detail: Option<String>,
+}
+
+impl NewAuditEvent {
+    pub fn new(action: &str, actor: Option<&str>, target: Option<&str>) -> NewAuditEvent {
+        NewAuditEvent {
+            actor: actor.map(String::from),
+            action: action.to_string(),
+            target: target.map(String::from),
+            ip: None,
+            created_at: Utc::now().naive_utc(),
+            detail: None,
+        }
+    }
+
+    pub fn ip(mut self, ip: Option<&str>) -> NewAuditEvent {
+        self.ip = ip.map(String::from);
+        self
+    }
+
+    pub fn detail(mut self, detail: &str) -> NewAuditEvent {
+        self.detail = Some(detail.to_string());
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::establish_connection;
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn audit_event_search() {
+        let mut conn = establish_connection();
+        let start = Utc::now().naive_utc();
+
+        let event = NewAuditEvent::new("test.searched", Some("audit_actor"), Some("42"))
+            .ip(Some("127.0.0.1"))
+            .detail("testing");
+        let added = AuditEvent::add(&mut conn, &event).unwrap();
+        AuditEvent::add(
+            &mut conn,
+            &NewAuditEvent::new("test.other", Some("audit_actor"), None),
+        );
+        assert_eq!(added.target.as_deref(), Some("42"));
+        assert_eq!(added.detail.as_deref(), Some("testing"));
+
+        let filter = AuditFilter {
+            actor: Some("audit_actor".to_string()),
+            action: Some("test.searched".to_string()),
+            from: Some(start),
+            until: None,
+        };
+        let query_result = AuditEvent::search(&mut conn, &filter).unwrap();
+        assert_eq!(query_result.first(), Some(&added));
+        assert!(query_result.iter().all(|e| e.action == "test.searched"));
+
+        let filter = AuditFilter {
+            actor: Some("audit_actor".to_string()),
+            until: Some(start - Duration::seconds(1)),
+            ..Default::default()
+        };
+        let query_result = AuditEvent::search(&mut conn, &filter).unwrap();
+        assert!(!query_result.contains(&added));
+    }
+
+    #[test]
+    fn audit_event_append_only() {
+        use super::super::schema::audit_events::{actor as ac, id as i};
+        let mut conn = establish_connection();
+        let added =
+            AuditEvent::add(&mut conn, &NewAuditEvent::new("test.kept", None, None)).unwrap();
+
+        let updated = diesel::update(audit_events::table.filter(i.eq(added.id)))
+            .set(ac.eq("someone_else"))
+            .execute(&mut conn);
+        assert!(updated.is_err());
+        let deleted = diesel::delete(audit_events::table.filter(i.eq(added.id))).execute(&mut conn);
+        assert!(deleted.is_err());
+    }
+}
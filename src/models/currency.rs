@@ -0,0 +1,252 @@
+use super::money::SCALE;
+use super::schema::currencies;
+use super::*;
+use diesel::sql_types::Text;
+use serde::{de, Deserializer, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// The code a currency goes by, like `USD`, stored as text
+///
+/// ISO 4217 codes are three capital letters, custom units like `BTC` or
+/// `POINTS` can be up to ten capitals and digits, starting with a letter
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+pub struct CurrencyCode(String);
+
+/// Why a string isn't a currency code
+#[derive(Debug, PartialEq, Eq)]
+pub struct CurrencyCodeError;
+
+impl fmt::Display for CurrencyCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected a currency code like \"USD\"")
+    }
+}
+
+impl std::error::Error for CurrencyCodeError {}
+
+impl CurrencyCode {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for CurrencyCode {
+    type Err = CurrencyCodeError;
+
+    fn from_str(s: &str) -> Result<CurrencyCode, CurrencyCodeError> {
+        let mut chars = s.chars();
+        let starts_with_letter = chars.next().is_some_and(|c| c.is_ascii_uppercase());
+        let rest_fits = chars.all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+        match starts_with_letter && rest_fits && (2..=10).contains(&s.len()) {
+            true => Ok(CurrencyCode(s.to_string())),
+            false => Err(CurrencyCodeError),
+        }
+    }
+}
+
+impl fmt::Display for CurrencyCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for CurrencyCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for CurrencyCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<CurrencyCode, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        code.parse().map_err(de::Error::custom)
+    }
+}
+
+impl ToSql<Text, Pg> for CurrencyCode {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(&self.0, out)
+    }
+}
+
+impl FromSql<Text, Pg> for CurrencyCode {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        <String as FromSql<Text, Pg>>::from_sql(bytes).map(CurrencyCode)
+    }
+}
+
+/// A currency of the registry, ISO 4217 ones are there from the start and
+/// admins can add custom units
+#[derive(Queryable, Debug, PartialEq, Serialize)]
+pub struct Currency {
+    pub code: CurrencyCode,
+    pub name: String,
+    /// digits after the point amounts in it are rounded to
+    pub minor_units: i16,
+    pub symbol: String,
+    /// added by an admin rather than taken from ISO 4217
+    pub custom: bool,
+}
+
+impl Currency {
+    /// gets a currency by its code
+    pub fn get(conn: &mut PgConnection, code: &CurrencyCode) -> DatabaseResult<Currency> {
+        match Currency::find(conn, code) {
+            Ok(currency) => DatabaseResult::Succeful(currency),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets every currency of the registry by code
+    pub fn all(conn: &mut PgConnection) -> DatabaseResult<Vec<Currency>> {
+        match currencies::table
+            .order(currencies::code)
+            .load::<Currency>(conn)
+        {
+            Ok(currency_vec) => DatabaseResult::Succeful(currency_vec),
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        }
+    }
+
+    /// adds a custom currency, AlreadyExists if the code is taken
+    pub fn add(conn: &mut PgConnection, new_currency: &NewCurrency) -> DatabaseResult<Currency> {
+        match diesel::insert_into(currencies::table)
+            .values(new_currency)
+            .get_result::<Currency>(conn)
+        {
+            Ok(currency) => DatabaseResult::Succeful(currency),
+            Err(Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _)) => {
+                DatabaseResult::AlreadyExists
+            }
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
+        }
+    }
+
+    /// deletes a custom currency nothing refers to anymore, no amounts,
+    /// rates or users' base currencies
+    ///
+    /// ISO 4217 currencies stay, NotFound for them as for codes in use
+    pub fn delete(conn: &mut PgConnection, code: &CurrencyCode) -> DatabaseResult<Currency> {
+        use diesel::result::DatabaseErrorKind::ForeignKeyViolation;
+        match diesel::delete(
+            currencies::table
+                .find(code)
+                .filter(currencies::custom.eq(true)),
+        )
+        .get_result::<Currency>(conn)
+        {
+            Ok(currency) => DatabaseResult::Succeful(currency),
+            Err(Error::NotFound | Error::DatabaseError(ForeignKeyViolation, _)) => {
+                DatabaseResult::NotFound
+            }
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
+        }
+    }
+
+    pub(super) fn find(conn: &mut PgConnection, code: &CurrencyCode) -> QueryResult<Currency> {
+        currencies::table.find(code).first::<Currency>(conn)
+    }
+
+    /// the amount rounded to the currency's minor units, halves away from zero
+    pub fn round(&self, amount: &Amount) -> Amount {
+        amount.round(self.minor_units.into())
+    }
+}
+
+/// A custom currency for an admin to add
+#[derive(Insertable, Debug)]
+#[diesel(table_name = currencies)]
+pub struct NewCurrency {
+    pub code: CurrencyCode,
+    pub name: String,
+    pub minor_units: i16,
+    pub symbol: String,
+    custom: bool,
+}
+
+impl NewCurrency {
+    pub fn new(code: CurrencyCode, name: String, minor_units: i16, symbol: String) -> NewCurrency {
+        NewCurrency {
+            code,
+            name,
+            minor_units,
+            symbol,
+            custom: true,
+        }
+    }
+
+    /// whether amounts can keep that many minor units, they're stored
+    /// with four digits after the point
+    pub fn is_valid(&self) -> bool {
+        (0..=SCALE).contains(&i64::from(self.minor_units))
+            && !self.name.trim().is_empty()
+            && !self.symbol.trim().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::establish_connection;
+    use super::*;
+
+    fn code(s: &str) -> CurrencyCode {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn currency_code_parse() {
+        assert_eq!(code("USD").to_string(), "USD");
+        assert_eq!(code("POINTS2").as_str(), "POINTS2");
+        for invalid in ["usd", "U", "", "1BTC", "US D", "EURO-", "ABCDEFGHIJK"] {
+            assert_eq!(
+                invalid.parse::<CurrencyCode>(),
+                Err(CurrencyCodeError),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn currency_round() {
+        let mut conn = establish_connection();
+        let amount = |value: &str| value.parse::<Amount>().unwrap();
+        let round = |conn: &mut PgConnection, c: &str, value: &str| {
+            Currency::get(conn, &code(c)).unwrap().round(&amount(value))
+        };
+        assert_eq!(round(&mut conn, "USD", "10.005"), amount("10.01"));
+        assert_eq!(round(&mut conn, "USD", "-10.005"), amount("-10.01"));
+        assert_eq!(round(&mut conn, "JPY", "1500.5"), amount("1501"));
+        assert_eq!(round(&mut conn, "KWD", "1.2345"), amount("1.235"));
+        assert_eq!(round(&mut conn, "CLF", "1.2345"), amount("1.2345"));
+        assert_eq!(Currency::get(&mut conn, &code("EUR")).unwrap().symbol, "€");
+        assert!(matches!(
+            Currency::get(&mut conn, &code("EURO")),
+            DatabaseResult::NotFound
+        ));
+
+        // custom units come and go, ISO 4217 ones stay
+        Currency::delete(&mut conn, &code("TESTPTS"));
+        let points = NewCurrency::new(code("TESTPTS"), "Points".to_string(), 0, "pts".to_string());
+        assert!(points.is_valid());
+        assert!(Currency::add(&mut conn, &points).unwrap().custom);
+        assert!(matches!(
+            Currency::add(&mut conn, &points),
+            DatabaseResult::AlreadyExists
+        ));
+        assert_eq!(round(&mut conn, "TESTPTS", "2.5"), amount("3"));
+        Currency::delete(&mut conn, &code("TESTPTS")).unwrap();
+        assert!(matches!(
+            Currency::delete(&mut conn, &code("USD")),
+            DatabaseResult::NotFound
+        ));
+        let too_fine = NewCurrency::new(code("TESTSAT"), "Sats".to_string(), 8, "sat".to_string());
+        assert!(!too_fine.is_valid());
+    }
+}
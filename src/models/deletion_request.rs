@@ -0,0 +1,133 @@
+use super::schema::deletion_requests;
+use super::*;
+use chrono::{Duration, NaiveDateTime, Utc};
+use std::env;
+
+/// A user's request to have their data deleted once `scheduled_for` passes,
+/// see User::close
+#[derive(Queryable, Debug, PartialEq, Serialize)]
+pub struct DeletionRequest {
+    pub user_id: String,
+    pub requested_at: NaiveDateTime,
+    pub scheduled_for: NaiveDateTime,
+}
+
+impl DeletionRequest {
+    /// schedules a user's deletion
+    ///
+    /// returns DatabaseResult::AlreadyExists if one is already scheduled
+    pub fn add(
+        conn: &mut PgConnection,
+        request: &NewDeletionRequest,
+    ) -> DatabaseResult<DeletionRequest> {
+        match diesel::insert_into(deletion_requests::table)
+            .values(request)
+            .get_result::<DeletionRequest>(conn)
+        {
+            Ok(request) => DatabaseResult::Succeful(request),
+            Err(Error::DatabaseError(_, _)) => DatabaseResult::AlreadyExists,
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets a user's scheduled deletion
+    pub fn get(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<DeletionRequest> {
+        match deletion_requests::table
+            .find(user_id)
+            .first::<DeletionRequest>(conn)
+        {
+            Ok(request) => DatabaseResult::Succeful(request),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets the deletions whose grace period is over
+    pub fn due(conn: &mut PgConnection) -> DatabaseResult<Vec<DeletionRequest>> {
+        use super::schema::deletion_requests::scheduled_for as sf;
+        match deletion_requests::table
+            .filter(sf.le(Utc::now().naive_utc()))
+            .order(sf.asc())
+            .load::<DeletionRequest>(conn)
+        {
+            Ok(request_vec) => DatabaseResult::Succeful(request_vec),
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        }
+    }
+
+    /// cancels a user's scheduled deletion
+    pub fn delete(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<DeletionRequest> {
+        match diesel::delete(deletion_requests::table.find(user_id))
+            .get_result::<DeletionRequest>(conn)
+        {
+            Ok(request) => DatabaseResult::Succeful(request),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while deleting data, Error message: {}",
+                err
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = deletion_requests)]
+pub struct NewDeletionRequest {
+    user_id: String,
+    requested_at: NaiveDateTime,
+    scheduled_for: NaiveDateTime,
+}
+
+impl NewDeletionRequest {
+    /// schedules the deletion after DELETION_GRACE_DAYS, 14 by default
+    pub fn new(user_id: String) -> NewDeletionRequest {
+        let grace = env::var("DELETION_GRACE_DAYS")
+            .ok()
+            .and_then(|days| days.parse().ok())
+            .unwrap_or(14);
+        let now = Utc::now().naive_utc();
+        NewDeletionRequest {
+            user_id,
+            requested_at: now,
+            scheduled_for: now + Duration::days(grace),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // make sure a test user with username "test_user" exist in database
+    use super::super::establish_connection;
+    use super::*;
+
+    #[test]
+    fn deletion_request_schedule() {
+        let mut conn = establish_connection();
+        DeletionRequest::delete(&mut conn, "test_user");
+
+        let new_request = NewDeletionRequest::new("test_user".to_string());
+        let added = DeletionRequest::add(&mut conn, &new_request).unwrap();
+        assert!(added.scheduled_for > added.requested_at);
+        assert!(matches!(
+            DeletionRequest::add(&mut conn, &new_request),
+            DatabaseResult::AlreadyExists
+        ));
+        // still in its grace period
+        assert!(!DeletionRequest::due(&mut conn).unwrap().contains(&added));
+
+        assert_eq!(
+            DeletionRequest::delete(&mut conn, "test_user").unwrap(),
+            added
+        );
+        assert!(matches!(
+            DeletionRequest::get(&mut conn, "test_user"),
+            DatabaseResult::NotFound
+        ));
+    }
+}
@@ -0,0 +1,76 @@
+use super::*;
+use crate::encryption::keyring;
+use diesel::sql_types::{Nullable, Text};
+
+/// A text column that's encrypted at rest, see `crate::encryption`
+///
+/// models keep plain `String`s and go through this with
+/// `#[diesel(serialize_as)]` and `#[diesel(deserialize_as)]`, so values are
+/// sealed on the way in and opened on the way out
+#[derive(Debug, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+pub struct Encrypted<T>(pub T);
+
+impl From<String> for Encrypted<String> {
+    fn from(plaintext: String) -> Encrypted<String> {
+        Encrypted(plaintext)
+    }
+}
+
+impl From<Encrypted<String>> for String {
+    fn from(encrypted: Encrypted<String>) -> String {
+        encrypted.0
+    }
+}
+
+impl From<Option<String>> for Encrypted<Option<String>> {
+    fn from(plaintext: Option<String>) -> Encrypted<Option<String>> {
+        Encrypted(plaintext)
+    }
+}
+
+impl From<Encrypted<Option<String>>> for Option<String> {
+    fn from(encrypted: Encrypted<Option<String>>) -> Option<String> {
+        encrypted.0
+    }
+}
+
+impl ToSql<Text, Pg> for Encrypted<String> {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(keyring().seal(&self.0).as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl ToSql<Text, Pg> for Encrypted<Option<String>> {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match &self.0 {
+            Some(plaintext) => {
+                out.write_all(keyring().seal(plaintext).as_bytes())?;
+                Ok(IsNull::No)
+            }
+            None => Ok(IsNull::Yes),
+        }
+    }
+}
+
+impl FromSql<Text, Pg> for Encrypted<String> {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        let stored = <String as FromSql<Text, Pg>>::from_sql(bytes)?;
+        Ok(Encrypted(keyring().open(&stored)?))
+    }
+}
+
+impl FromSql<Nullable<Text>, Pg> for Encrypted<Option<String>> {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        let Encrypted(plaintext) = <Encrypted<String> as FromSql<Text, Pg>>::from_sql(bytes)?;
+        Ok(Encrypted(Some(plaintext)))
+    }
+
+    fn from_nullable_sql(bytes: Option<PgValue>) -> deserialize::Result<Self> {
+        match bytes {
+            Some(bytes) => Self::from_sql(bytes),
+            None => Ok(Encrypted(None)),
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use super::schema::exchange_rates;
+use super::*;
+
+/// What one unit of `base` bought of `quote` on a day
+#[derive(Queryable, Debug, PartialEq)]
+pub struct ExchangeRate {
+    pub id: i32,
+    pub date: NaiveDate,
+    pub base: CurrencyCode,
+    pub quote: CurrencyCode,
+    pub rate: Rate,
+}
+
+impl ExchangeRate {
+    /// stores rates, replacing the ones already there for the same pair
+    /// and day, returns how many were stored
+    ///
+    /// returns DatabaseResult::NotFound if one of the currencies isn't in
+    /// the registry, nothing is stored then
+    pub fn add_all(conn: &mut PgConnection, rates: &[NewExchangeRate]) -> DatabaseResult<usize> {
+        use super::schema::exchange_rates::{base, date, quote, rate};
+        use diesel::result::DatabaseErrorKind::ForeignKeyViolation;
+        use diesel::upsert::excluded;
+        let added = conn.transaction::<_, Error, _>(|conn| {
+            let mut count = 0;
+            // postgres takes 65535 parameters a statement at most
+            for chunk in rates.chunks(1000) {
+                count += diesel::insert_into(exchange_rates::table)
+                    .values(chunk)
+                    .on_conflict((base, quote, date))
+                    .do_update()
+                    .set(rate.eq(excluded(rate)))
+                    .execute(conn)?;
+            }
+            Ok(count)
+        });
+        match added {
+            Ok(count) => DatabaseResult::Succeful(count),
+            Err(Error::DatabaseError(ForeignKeyViolation, _)) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// what one unit of `from` buys of `to`, at the rate nearest to `on`
+    ///
+    /// pairs stored the other way round are inverted, and pairs that are
+    /// both quoted against another currency, like EUR for the ECB's rates,
+    /// are crossed through it. NotFound if there's no way between them
+    pub fn rate(
+        conn: &mut PgConnection,
+        from: &CurrencyCode,
+        to: &CurrencyCode,
+        on: NaiveDate,
+    ) -> DatabaseResult<Rate> {
+        match ExchangeRate::find_rate(conn, from, to, on) {
+            Ok(Some(rate)) => DatabaseResult::Succeful(rate),
+            Ok(None) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// money in another currency at the rate nearest to `on`, rounded to
+    /// its minor units, see `ExchangeRate::rate`. NotFound if there's no
+    /// rate or the result is too large to store
+    pub fn convert(
+        conn: &mut PgConnection,
+        money: &Money,
+        to: &CurrencyCode,
+        on: NaiveDate,
+    ) -> DatabaseResult<Money> {
+        let converted = ExchangeRate::find_rate(conn, &money.currency, to, on).and_then(|rate| {
+            let currency = Currency::find(conn, to)?;
+            let amount = rate.and_then(|rate| money.amount.convert(&rate));
+            Ok(amount.map(|amount| currency.round(&amount)))
+        });
+        match converted {
+            Ok(Some(amount)) => DatabaseResult::Succeful(Money::new(amount, to.clone())),
+            Ok(None) | Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    pub(super) fn find_rate(
+        conn: &mut PgConnection,
+        from: &CurrencyCode,
+        to: &CurrencyCode,
+        on: NaiveDate,
+    ) -> QueryResult<Option<Rate>> {
+        use super::schema::exchange_rates::{base, quote};
+        if from == to {
+            return Ok(Some(Rate::one()));
+        }
+        if let Some(direct) = ExchangeRate::nearest(conn, from, to, on)? {
+            return Ok(Some(direct.rate));
+        }
+        if let Some(reverse) = ExchangeRate::nearest(conn, to, from, on)? {
+            return Ok(reverse.rate.inverse());
+        }
+        let pivots = exchange_rates::table
+            .filter(quote.eq(from))
+            .select(base)
+            .distinct()
+            .order(base)
+            .load::<CurrencyCode>(conn)?;
+        for pivot in &pivots {
+            let from_rate = ExchangeRate::nearest(conn, pivot, from, on)?;
+            let to_rate = ExchangeRate::nearest(conn, pivot, to, on)?;
+            if let (Some(from_rate), Some(to_rate)) = (from_rate, to_rate) {
+                return Ok(Rate::cross(&from_rate.rate, &to_rate.rate));
+            }
+        }
+        Ok(None)
+    }
+
+    /// the stored rate of a pair closest to `on`, the earlier one on a tie
+    fn nearest(
+        conn: &mut PgConnection,
+        from: &CurrencyCode,
+        to: &CurrencyCode,
+        on: NaiveDate,
+    ) -> QueryResult<Option<ExchangeRate>> {
+        use super::schema::exchange_rates::{base, date, quote};
+        let before = exchange_rates::table
+            .filter(base.eq(from))
+            .filter(quote.eq(to))
+            .filter(date.le(on))
+            .order(date.desc())
+            .first::<ExchangeRate>(conn)
+            .optional()?;
+        let after = exchange_rates::table
+            .filter(base.eq(from))
+            .filter(quote.eq(to))
+            .filter(date.gt(on))
+            .order(date.asc())
+            .first::<ExchangeRate>(conn)
+            .optional()?;
+        Ok(match (before, after) {
+            (Some(before), Some(after)) if after.date - on < on - before.date => Some(after),
+            (Some(before), _) => Some(before),
+            (None, after) => after,
+        })
+    }
+}
+
+/// A rate to store, see `ExchangeRate::add_all`
+#[derive(Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = exchange_rates)]
+pub struct NewExchangeRate {
+    pub date: NaiveDate,
+    pub base: CurrencyCode,
+    pub quote: CurrencyCode,
+    pub rate: Rate,
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::establish_connection;
+    use super::*;
+
+    fn code(s: &str) -> CurrencyCode {
+        s.parse().unwrap()
+    }
+
+    fn day(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn exchange_rate_nearest() {
+        let mut conn = establish_connection();
+        // currencies of its own so other tests' rates don't get in the way
+        for test_code in ["TESTFX", "TESTFY"] {
+            Currency::add(
+                &mut conn,
+                &NewCurrency::new(code(test_code), "Test".to_string(), 2, "T".to_string()),
+            );
+        }
+        let rate = |date, quote: &str, rate: &str| NewExchangeRate {
+            date: day(date),
+            base: code("TESTFX"),
+            quote: code(quote),
+            rate: rate.parse().unwrap(),
+        };
+        let rates = [
+            rate("2022-11-01", "USD", "2"),
+            rate("2022-11-10", "USD", "4"),
+            rate("2022-11-10", "TESTFY", "5"),
+        ];
+        assert_eq!(ExchangeRate::add_all(&mut conn, &rates).unwrap(), 3);
+        // stored again they replace what's there
+        assert_eq!(ExchangeRate::add_all(&mut conn, &rates).unwrap(), 3);
+
+        let usd = |conn: &mut PgConnection, on| {
+            ExchangeRate::rate(conn, &code("TESTFX"), &code("USD"), day(on))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(usd(&mut conn, "2022-10-01"), "2");
+        assert_eq!(usd(&mut conn, "2022-11-05"), "2");
+        assert_eq!(usd(&mut conn, "2022-11-06"), "4");
+        assert_eq!(usd(&mut conn, "2022-12-31"), "4");
+
+        let on = day("2022-11-10");
+        let inverse = ExchangeRate::rate(&mut conn, &code("USD"), &code("TESTFX"), on).unwrap();
+        assert_eq!(inverse.to_string(), "0.25");
+        let crossed = ExchangeRate::rate(&mut conn, &code("USD"), &code("TESTFY"), on).unwrap();
+        assert_eq!(crossed.to_string(), "1.25");
+        assert!(matches!(
+            ExchangeRate::rate(&mut conn, &code("USD"), &code("JPY"), on),
+            DatabaseResult::NotFound
+        ));
+
+        let money = Money::new("10.01".parse().unwrap(), code("USD"));
+        let converted = ExchangeRate::convert(&mut conn, &money, &code("TESTFY"), on).unwrap();
+        assert_eq!(converted.to_string(), "12.51 TESTFY");
+
+        let unknown = [rate("2022-11-10", "NOSUCH", "1")];
+        assert!(matches!(
+            ExchangeRate::add_all(&mut conn, &unknown),
+            DatabaseResult::NotFound
+        ));
+    }
+}
@@ -0,0 +1,434 @@
+use super::schema::{account, journal_entries, postings, transaction};
+use super::*;
+use crate::encryption::keyring;
+use chrono::{NaiveDateTime, Utc};
+
+/// A double-entry journal entry, money moved between accounts
+///
+/// its postings add up to zero in every currency, a transaction is an
+/// entry with two postings, one on its bank account and one against a
+/// category, see `Transaction::add`
+#[derive(Queryable, Debug, PartialEq)]
+pub struct JournalEntry {
+    pub id: i32,
+    /// who made it, None once they deleted their data
+    pub user_id: Option<String>,
+    /// encrypted at rest, transactions keep their own title instead
+    #[diesel(deserialize_as = Encrypted<Option<String>>)]
+    pub description: Option<String>,
+    pub time: NaiveDate,
+    pub created_at: NaiveDateTime,
+}
+
+/// One line of a journal entry, debits are positive and credits negative
+#[derive(Queryable, Debug, PartialEq, Clone)]
+pub struct Posting {
+    pub id: i32,
+    pub entry_id: i32,
+    pub account_id: i32,
+    pub amount: Amount,
+    pub currency: CurrencyCode,
+}
+
+impl JournalEntry {
+    /// posts an entry and books it on the balances of its accounts
+    ///
+    /// returns DatabaseResult::NotFound if one of the accounts or currencies
+    /// doesn't exist, if a posting isn't in its account's currency, or if
+    /// the postings don't balance once rounded to their currencies' minor
+    /// units, see `NewJournalEntry::is_balanced`
+    pub fn add(
+        conn: &mut PgConnection,
+        entry: &NewJournalEntry,
+    ) -> DatabaseResult<(JournalEntry, Vec<Posting>)> {
+        use diesel::result::DatabaseErrorKind::ForeignKeyViolation;
+        let added = conn.transaction(|conn| {
+            let mut entry = entry.clone();
+            for posting in entry.postings.iter_mut() {
+                let currency = account::table
+                    .find(posting.account_id)
+                    .select(account::currency)
+                    .first::<CurrencyCode>(conn)?;
+                if currency != posting.currency {
+                    return Err(Error::NotFound);
+                }
+                posting.amount = Currency::find(conn, &posting.currency)?.round(&posting.amount);
+            }
+            if !entry.is_balanced() {
+                return Err(Error::NotFound);
+            }
+            JournalEntry::post(conn, &entry)
+        });
+        match added {
+            Ok(added) => DatabaseResult::Succeful(added),
+            Err(Error::NotFound | Error::DatabaseError(ForeignKeyViolation, _)) => {
+                DatabaseResult::NotFound
+            }
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets an entry with its postings
+    pub fn get(conn: &mut PgConnection, id: i32) -> DatabaseResult<(JournalEntry, Vec<Posting>)> {
+        let found = journal_entries::table
+            .find(id)
+            .first::<JournalEntry>(conn)
+            .and_then(|entry| Ok((entry, JournalEntry::postings_of(conn, id)?)));
+        match found {
+            Ok(found) => DatabaseResult::Succeful(found),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets the postings of an entry, empty if there's no such entry
+    pub fn postings(conn: &mut PgConnection, id: i32) -> DatabaseResult<Vec<Posting>> {
+        match JournalEntry::postings_of(conn, id) {
+            Ok(posting_vec) => DatabaseResult::Succeful(posting_vec),
+            Err(err) => panic!("Something went wrong, Error message: {}", err),
+        }
+    }
+
+    /// deletes an entry and takes its postings off the balances
+    ///
+    /// entries behind a transaction go with the transaction, see
+    /// `Transaction::delete`, and are reported as DatabaseResult::NotFound
+    pub fn delete(conn: &mut PgConnection, id: i32) -> DatabaseResult<JournalEntry> {
+        use super::schema::transaction::entry_id as ei;
+        let deleted = conn.transaction::<_, Error, _>(|conn| {
+            let behind_transaction =
+                diesel::select(diesel::dsl::exists(transaction::table.filter(ei.eq(id))))
+                    .get_result::<bool>(conn)?;
+            if behind_transaction {
+                return Err(Error::NotFound);
+            }
+            JournalEntry::remove(conn, id)
+        });
+        match deleted {
+            Ok(entry) => DatabaseResult::Succeful(entry),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while deleting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// re-encrypts every description that isn't under the current key,
+    /// see `Transaction::reencrypt`
+    ///
+    /// returns how many entries were rewritten
+    pub fn reencrypt(conn: &mut PgConnection) -> DatabaseResult<usize> {
+        use super::schema::journal_entries::{description as d, id as i};
+        let current = format!("{}%", keyring().current_prefix());
+        let reencrypted = conn.transaction::<_, Error, _>(|conn| {
+            let stale = journal_entries::table
+                .filter(d.not_like(&current))
+                .load::<JournalEntry>(conn)?;
+            for entry in &stale {
+                diesel::update(journal_entries::table.filter(i.eq(entry.id)))
+                    .set(d.eq(Encrypted(entry.description.clone())))
+                    .execute(conn)?;
+            }
+            Ok(stale.len())
+        });
+        match reencrypted {
+            Ok(count) => DatabaseResult::Succeful(count),
+            Err(err) => panic!("Something is wrong, Error message: {}", err),
+        }
+    }
+
+    /// inserts an entry with its postings and books them, run inside
+    /// a database transaction
+    pub(super) fn post(
+        conn: &mut PgConnection,
+        entry: &NewJournalEntry,
+    ) -> QueryResult<(JournalEntry, Vec<Posting>)> {
+        use super::schema::journal_entries::{created_at, description, time, user_id};
+        assert!(entry.is_balanced(), "unbalanced journal entry: {:?}", entry);
+        let added = diesel::insert_into(journal_entries::table)
+            .values((
+                user_id.eq(&entry.user_id),
+                description.eq(Encrypted(entry.description.clone())),
+                time.eq(entry.time),
+                created_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result::<JournalEntry>(conn)?;
+        let posting_vec = JournalEntry::insert_postings(conn, added.id, &entry.postings)?;
+        Ok((added, posting_vec))
+    }
+
+    /// replaces the postings of an entry and books the difference, run
+    /// inside a database transaction
+    pub(super) fn repost(
+        conn: &mut PgConnection,
+        id: i32,
+        new_postings: &[NewPosting],
+    ) -> QueryResult<Vec<Posting>> {
+        use super::schema::postings::entry_id as ei;
+        assert!(NewPosting::balance(new_postings), "unbalanced postings");
+        let old = diesel::delete(postings::table.filter(ei.eq(id))).get_results::<Posting>(conn)?;
+        for posting in &old {
+            JournalEntry::book(conn, posting.account_id, -posting.amount.clone())?;
+        }
+        JournalEntry::insert_postings(conn, id, new_postings)
+    }
+
+    /// deletes an entry and takes its postings off the balances, run
+    /// inside a database transaction
+    ///
+    /// a transaction behind it goes too
+    pub(super) fn remove(conn: &mut PgConnection, id: i32) -> QueryResult<JournalEntry> {
+        use super::schema::journal_entries::id as i;
+        for posting in JournalEntry::postings_of(conn, id)? {
+            JournalEntry::book(conn, posting.account_id, -posting.amount)?;
+        }
+        diesel::delete(journal_entries::table.filter(i.eq(id))).get_result::<JournalEntry>(conn)
+    }
+
+    /// deletes every entry with a posting on the account, run inside
+    /// a database transaction, returns how many
+    pub(super) fn remove_touching(conn: &mut PgConnection, account_id: i32) -> QueryResult<usize> {
+        use super::schema::postings::{account_id as ai, entry_id as ei};
+        let mut entry_ids = postings::table
+            .filter(ai.eq(account_id))
+            .select(ei)
+            .load::<i32>(conn)?;
+        entry_ids.sort_unstable();
+        entry_ids.dedup();
+        for id in &entry_ids {
+            JournalEntry::remove(conn, *id)?;
+        }
+        Ok(entry_ids.len())
+    }
+
+    /// moves the postings `from` has on their own categories in entries on
+    /// `account_id` over to `to`'s categories of the same kind, name and
+    /// currency, opening those as needed, run inside a database transaction
+    ///
+    /// used when an account is handed to `to`, so its entries stay
+    /// within one owner's books. Postings on `from`'s bank accounts stay,
+    /// their entries go when those accounts are deleted
+    pub(super) fn hand_over(
+        conn: &mut PgConnection,
+        account_id: i32,
+        from: &str,
+        to: &str,
+    ) -> QueryResult<usize> {
+        use super::schema::account::{kind as k, name as n, user_id as ui};
+        use super::schema::postings::{account_id as ai, entry_id as ei, id as i};
+        let entry_ids = postings::table
+            .filter(ai.eq(account_id))
+            .select(ei)
+            .load::<i32>(conn)?;
+        let moving = postings::table
+            .inner_join(account::table)
+            .filter(ei.eq_any(entry_ids))
+            .filter(ui.eq(from))
+            .filter(k.eq_any([
+                AccountKind::Income,
+                AccountKind::Expense,
+                AccountKind::Equity,
+            ]))
+            .select((postings::all_columns, k, n))
+            .load::<(Posting, AccountKind, String)>(conn)?;
+        for (posting, kind, name) in &moving {
+            let heirs = Account::category(conn, to, *kind, name, &posting.currency)?;
+            diesel::update(postings::table.filter(i.eq(posting.id)))
+                .set(ai.eq(heirs.id))
+                .execute(conn)?;
+            JournalEntry::book(conn, posting.account_id, -posting.amount.clone())?;
+            JournalEntry::book(conn, heirs.id, posting.amount.clone())?;
+        }
+        Ok(moving.len())
+    }
+
+    fn postings_of(conn: &mut PgConnection, id: i32) -> QueryResult<Vec<Posting>> {
+        use super::schema::postings::{entry_id as ei, id as i};
+        postings::table
+            .filter(ei.eq(id))
+            .order(i.asc())
+            .load::<Posting>(conn)
+    }
+
+    fn insert_postings(
+        conn: &mut PgConnection,
+        id: i32,
+        new_postings: &[NewPosting],
+    ) -> QueryResult<Vec<Posting>> {
+        use super::schema::postings::{account_id, amount, currency, entry_id};
+        let rows = new_postings
+            .iter()
+            .map(|posting| {
+                (
+                    entry_id.eq(id),
+                    account_id.eq(posting.account_id),
+                    amount.eq(&posting.amount),
+                    currency.eq(&posting.currency),
+                )
+            })
+            .collect::<Vec<_>>();
+        let posting_vec = diesel::insert_into(postings::table)
+            .values(&rows)
+            .get_results::<Posting>(conn)?;
+        for posting in &posting_vec {
+            JournalEntry::book(conn, posting.account_id, posting.amount.clone())?;
+        }
+        Ok(posting_vec)
+    }
+
+    /// moves an account's balance by `delta`
+    fn book(conn: &mut PgConnection, account_id: i32, delta: Amount) -> QueryResult<usize> {
+        use super::schema::account::{balance as b, id as i};
+        diesel::update(account::table.filter(i.eq(account_id)))
+            .set(b.eq(b + delta))
+            .execute(conn)
+    }
+}
+
+/// A journal entry to post with `JournalEntry::add`
+#[derive(Debug, Clone)]
+pub struct NewJournalEntry {
+    pub user_id: String,
+    pub description: Option<String>,
+    pub time: NaiveDate,
+    pub postings: Vec<NewPosting>,
+}
+
+impl NewJournalEntry {
+    /// whether there are at least two postings, none of them zero, and
+    /// they add up to zero in every currency
+    pub fn is_balanced(&self) -> bool {
+        NewPosting::balance(&self.postings)
+    }
+}
+
+/// A line of a new journal entry, debits are positive and credits negative
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewPosting {
+    pub account_id: i32,
+    pub amount: Amount,
+    pub currency: CurrencyCode,
+}
+
+impl NewPosting {
+    pub fn new(account_id: i32, amount: Amount, currency: CurrencyCode) -> NewPosting {
+        NewPosting {
+            account_id,
+            amount,
+            currency,
+        }
+    }
+
+    /// see `NewJournalEntry::is_balanced`
+    pub fn balance(postings: &[NewPosting]) -> bool {
+        let mut totals: Vec<(&CurrencyCode, Amount)> = Vec::new();
+        for posting in postings {
+            match totals.iter_mut().find(|(c, _)| **c == posting.currency) {
+                Some((_, total)) => *total = total.clone() + posting.amount.clone(),
+                None => totals.push((&posting.currency, posting.amount.clone())),
+            }
+        }
+        postings.len() >= 2
+            && postings
+                .iter()
+                .all(|posting| posting.amount != Amount::zero())
+            && totals.iter().all(|(_, total)| *total == Amount::zero())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // make sure a test user with username "test_user" exist in database
+    use super::super::establish_connection;
+    use super::*;
+    use chrono::Local;
+
+    fn amount(value: &str) -> Amount {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn journal_entry_balanced() {
+        let usd =
+            |account_id, value| NewPosting::new(account_id, amount(value), "USD".parse().unwrap());
+        let cad =
+            |account_id, value| NewPosting::new(account_id, amount(value), "CAD".parse().unwrap());
+        assert!(NewPosting::balance(&[
+            usd(1, "10"),
+            usd(2, "-4"),
+            usd(3, "-6")
+        ]));
+        assert!(!NewPosting::balance(&[usd(1, "10"), usd(2, "-9")]));
+        assert!(!NewPosting::balance(&[usd(1, "10"), cad(2, "-10")]));
+        assert!(!NewPosting::balance(&[usd(1, "0"), usd(2, "0")]));
+        assert!(!NewPosting::balance(&[]));
+        // each currency balances on its own
+        assert!(NewPosting::balance(&[
+            usd(1, "10"),
+            usd(2, "-10"),
+            cad(3, "13.5"),
+            cad(4, "-13.5"),
+        ]));
+    }
+
+    #[test]
+    fn journal_entry_split() {
+        let mut conn = establish_connection();
+        let open = |conn: &mut PgConnection, name: &str, kind| {
+            let new_account = Account::new_account(name.to_string(), "test_user".to_string());
+            Account::add(conn, &new_account.kind(kind)).unwrap()
+        };
+        let card = open(&mut conn, "Entry Card", AccountKind::Liability);
+        let food = open(&mut conn, "Entry Food", AccountKind::Expense);
+        let home = open(&mut conn, "Entry Home", AccountKind::Expense);
+        let balance = |conn: &mut PgConnection, id| Account::get(conn, id).unwrap().balance;
+
+        // one card payment split across two categories
+        let entry = NewJournalEntry {
+            user_id: "test_user".to_string(),
+            description: Some("Groceries and a lamp".to_string()),
+            time: Local::today().naive_local(),
+            postings: vec![
+                NewPosting::new(card.id, amount("-80"), "USD".parse().unwrap()),
+                NewPosting::new(food.id, amount("50"), "USD".parse().unwrap()),
+                NewPosting::new(home.id, amount("30"), "USD".parse().unwrap()),
+            ],
+        };
+        let (added, posting_vec) = JournalEntry::add(&mut conn, &entry).unwrap();
+        assert_eq!(posting_vec.len(), 3);
+        assert_eq!(balance(&mut conn, card.id), amount("-80"));
+        assert_eq!(balance(&mut conn, food.id), amount("50"));
+        let (found, _) = JournalEntry::get(&mut conn, added.id).unwrap();
+        assert_eq!(found.description.as_deref(), Some("Groceries and a lamp"));
+
+        JournalEntry::delete(&mut conn, added.id).unwrap();
+        assert_eq!(balance(&mut conn, card.id), Amount::zero());
+        assert_eq!(balance(&mut conn, home.id), Amount::zero());
+
+        // entries behind transactions go through them
+        let trans = NewTransaction {
+            bank_account: card.id,
+            ..Default::default()
+        };
+        let trans = Transaction::add(&mut conn, &trans).unwrap();
+        assert!(matches!(
+            JournalEntry::delete(&mut conn, trans.entry_id),
+            DatabaseResult::NotFound
+        ));
+
+        for acc in [card, food, home] {
+            Account::delete_by_id(&mut conn, acc.id);
+        }
+        assert!(matches!(
+            Transaction::get(&mut conn, trans.id),
+            DatabaseResult::NotFound
+        ));
+    }
+}
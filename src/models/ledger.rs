@@ -0,0 +1,327 @@
+use super::*;
+use crate::schema::sql_types::AccountKind as AccountKindType;
+use diesel::sql_types::{Int4, Numeric, Text};
+
+/// What an account adds up to in one currency, a line of the trial balance
+#[derive(QueryableByName, Debug, PartialEq)]
+pub struct AccountBalance {
+    #[diesel(sql_type = Int4)]
+    pub account_id: i32,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = AccountKindType)]
+    pub kind: AccountKind,
+    #[diesel(sql_type = Text)]
+    pub currency: CurrencyCode,
+    /// debits count positive and credits negative
+    #[diesel(sql_type = Numeric)]
+    pub balance: Amount,
+}
+
+impl AccountBalance {
+    /// the balance of every account of the owner's books, the balances add
+    /// up to zero in each currency
+    pub fn trial_balance(
+        conn: &mut PgConnection,
+        owner: &str,
+    ) -> DatabaseResult<Vec<AccountBalance>> {
+        let balances = diesel::sql_query(
+            "SELECT account.id AS account_id, account.name, account.kind,
+                account.currency, SUM(postings.amount) AS balance
+            FROM postings JOIN account ON account.id = postings.account_id
+            WHERE account.user_id = $1
+            GROUP BY account.id
+            ORDER BY account.kind, account.name, account.currency",
+        )
+        .bind::<Text, _>(owner)
+        .load::<AccountBalance>(conn);
+        match balances {
+            Ok(balances) => DatabaseResult::Succeful(balances),
+            Err(err) => panic!("Something went wrong, Error message: {}", err),
+        }
+    }
+
+    /// a trial balance in one currency at the rates nearest to `on`
+    ///
+    /// lines are rounded one by one, so they can add up to a few minor
+    /// units off zero. NotFound if a currency or balance can't be converted
+    pub fn converted(
+        conn: &mut PgConnection,
+        balances: &[AccountBalance],
+        to: &CurrencyCode,
+        on: NaiveDate,
+    ) -> DatabaseResult<Vec<AccountBalance>> {
+        let currencies = balances.iter().map(|line| &line.currency);
+        let conversion = match Conversion::find(conn, currencies, to, on) {
+            DatabaseResult::Succeful(conversion) => conversion,
+            _ => return DatabaseResult::NotFound,
+        };
+        let converted = balances
+            .iter()
+            .map(|line| {
+                Some(AccountBalance {
+                    account_id: line.account_id,
+                    name: line.name.clone(),
+                    kind: line.kind,
+                    currency: to.clone(),
+                    balance: conversion.convert(&line.balance, &line.currency)?,
+                })
+            })
+            .collect();
+        match converted {
+            Some(converted) => DatabaseResult::Succeful(converted),
+            None => DatabaseResult::NotFound,
+        }
+    }
+}
+
+/// The owner's assets against what finances them, in one currency
+///
+/// assets always equal liabilities, equity and net income together
+#[derive(Debug, PartialEq)]
+pub struct BalanceSheet {
+    pub currency: CurrencyCode,
+    pub assets: Amount,
+    pub liabilities: Amount,
+    pub equity: Amount,
+    /// income less expenses, equity that isn't booked as such yet
+    pub net_income: Amount,
+}
+
+impl BalanceSheet {
+    /// sums a trial balance up by currency
+    pub fn from_balances(balances: &[AccountBalance]) -> Vec<BalanceSheet> {
+        let mut sheets: Vec<BalanceSheet> = Vec::new();
+        for line in balances {
+            let sheet = match sheets.iter().position(|s| s.currency == line.currency) {
+                Some(index) => &mut sheets[index],
+                None => {
+                    sheets.push(BalanceSheet::empty(line.currency.clone()));
+                    sheets.last_mut().unwrap()
+                }
+            };
+            // everything but assets is shown the way it normally runs, credits
+            let total = match line.kind {
+                AccountKind::Asset => &mut sheet.assets,
+                AccountKind::Liability => &mut sheet.liabilities,
+                AccountKind::Equity => &mut sheet.equity,
+                AccountKind::Income | AccountKind::Expense => &mut sheet.net_income,
+            };
+            *total = match line.kind {
+                AccountKind::Asset => total.clone() + line.balance.clone(),
+                _ => total.clone() - line.balance.clone(),
+            };
+        }
+        sheets
+    }
+
+    /// adds balance sheets up in one currency at the rates nearest to `on`,
+    /// NotFound if a currency or total can't be converted
+    pub fn converted(
+        conn: &mut PgConnection,
+        sheets: &[BalanceSheet],
+        to: &CurrencyCode,
+        on: NaiveDate,
+    ) -> DatabaseResult<BalanceSheet> {
+        let currencies = sheets.iter().map(|sheet| &sheet.currency);
+        let conversion = match Conversion::find(conn, currencies, to, on) {
+            DatabaseResult::Succeful(conversion) => conversion,
+            _ => return DatabaseResult::NotFound,
+        };
+        let mut total = BalanceSheet::empty(to.clone());
+        for sheet in sheets {
+            let convert = |amount| conversion.convert(amount, &sheet.currency);
+            let converted = (
+                convert(&sheet.assets),
+                convert(&sheet.liabilities),
+                convert(&sheet.equity),
+            );
+            let (assets, liabilities, equity) = match converted {
+                (Some(assets), Some(liabilities), Some(equity)) => (assets, liabilities, equity),
+                _ => return DatabaseResult::NotFound,
+            };
+            total.assets = total.assets + assets;
+            total.liabilities = total.liabilities + liabilities;
+            total.equity = total.equity + equity;
+        }
+        // net income takes up the rounding so the sheet still balances
+        total.net_income = total.assets.clone() - total.liabilities.clone() - total.equity.clone();
+        DatabaseResult::Succeful(total)
+    }
+
+    fn empty(currency: CurrencyCode) -> BalanceSheet {
+        BalanceSheet {
+            currency,
+            assets: Amount::zero(),
+            liabilities: Amount::zero(),
+            equity: Amount::zero(),
+            net_income: Amount::zero(),
+        }
+    }
+}
+
+/// The rates a report's currencies are converted to another one at
+struct Conversion {
+    currency: Currency,
+    rates: Vec<(CurrencyCode, Rate)>,
+}
+
+impl Conversion {
+    /// looks every currency's rate up once, NotFound if one has none
+    fn find<'a>(
+        conn: &mut PgConnection,
+        currencies: impl Iterator<Item = &'a CurrencyCode>,
+        to: &CurrencyCode,
+        on: NaiveDate,
+    ) -> DatabaseResult<Conversion> {
+        let found = conn.transaction::<_, Error, _>(|conn| {
+            let currency = Currency::find(conn, to)?;
+            let mut rates: Vec<(CurrencyCode, Rate)> = Vec::new();
+            for code in currencies {
+                if rates.iter().any(|(from, _)| from == code) {
+                    continue;
+                }
+                match ExchangeRate::find_rate(conn, code, to, on)? {
+                    Some(rate) => rates.push((code.clone(), rate)),
+                    None => return Err(Error::NotFound),
+                }
+            }
+            Ok(Conversion { currency, rates })
+        });
+        match found {
+            Ok(conversion) => DatabaseResult::Succeful(conversion),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// an amount in one of the currencies looked up, rounded to the
+    /// minor units of the one converted to, see `Amount::convert`
+    fn convert(&self, amount: &Amount, from: &CurrencyCode) -> Option<Amount> {
+        let (_, rate) = self
+            .rates
+            .iter()
+            .find(|(code, _)| code == from)
+            .expect("rates are looked up for every currency of the report");
+        Some(self.currency.round(&amount.convert(rate)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::establish_connection;
+    use super::*;
+
+    fn amount(value: &str) -> Amount {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn ledger_reports() {
+        let mut conn = establish_connection();
+        User::close(&mut conn, "ledger_user");
+        let new_user = NewUser::new(
+            "Ledger".to_string(),
+            "ledger_user".to_string(),
+            "hash".to_string(),
+        );
+        User::add(&mut conn, &new_user).unwrap();
+        let checking = Account::new_account("Checking".to_string(), "ledger_user".to_string());
+        let checking = Account::add(&mut conn, &checking).unwrap();
+        for (kind, value) in [
+            (TransactionKind::Income, "100"),
+            (TransactionKind::Expense, "30"),
+        ] {
+            let trans = NewTransaction {
+                kind,
+                value: amount(value),
+                user_id: "ledger_user".to_string(),
+                bank_account: checking.id,
+                ..Default::default()
+            };
+            Transaction::add(&mut conn, &trans).unwrap();
+        }
+
+        let balances = AccountBalance::trial_balance(&mut conn, "ledger_user").unwrap();
+        let kinds = balances.iter().map(|line| line.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                AccountKind::Asset,
+                AccountKind::Income,
+                AccountKind::Expense
+            ]
+        );
+        let total = balances
+            .iter()
+            .fold(Amount::zero(), |total, line| total + line.balance.clone());
+        assert_eq!(total, Amount::zero());
+
+        let sheets = BalanceSheet::from_balances(&balances);
+        assert_eq!(
+            sheets,
+            [BalanceSheet {
+                currency: "USD".parse().unwrap(),
+                assets: amount("70"),
+                liabilities: Amount::zero(),
+                equity: Amount::zero(),
+                net_income: amount("70"),
+            }]
+        );
+
+        // a CAD account next to it, reported in USD
+        let cad: CurrencyCode = "CAD".parse().unwrap();
+        let savings = Account::new_account("Savings".to_string(), "ledger_user".to_string())
+            .currency(cad.clone());
+        let savings = Account::add(&mut conn, &savings).unwrap();
+        let trans = NewTransaction {
+            value: amount("100"),
+            currency: cad,
+            user_id: "ledger_user".to_string(),
+            bank_account: savings.id,
+            ..Default::default()
+        };
+        Transaction::add(&mut conn, &trans).unwrap();
+        let usd: CurrencyCode = "USD".parse().unwrap();
+        let on = "2022-11-10".parse().unwrap();
+        let rate = NewExchangeRate {
+            date: on,
+            base: "CAD".parse().unwrap(),
+            quote: usd.clone(),
+            rate: "0.75".parse().unwrap(),
+        };
+        ExchangeRate::add_all(&mut conn, &[rate]).unwrap();
+
+        let balances = AccountBalance::trial_balance(&mut conn, "ledger_user").unwrap();
+        assert_eq!(balances.len(), 5);
+        let converted = AccountBalance::converted(&mut conn, &balances, &usd, on).unwrap();
+        let lines = converted
+            .iter()
+            .map(|line| (line.name.as_str(), line.balance.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                ("Checking", "70.00".to_string()),
+                ("Savings", "75.00".to_string()),
+                ("Income", "-75.00".to_string()),
+                ("Income", "-100.00".to_string()),
+                ("Expenses", "30.00".to_string())
+            ]
+        );
+        let sheets = BalanceSheet::from_balances(&balances);
+        let sheet = BalanceSheet::converted(&mut conn, &sheets, &usd, on).unwrap();
+        assert_eq!(sheet.assets, amount("145"));
+        assert_eq!(sheet.net_income, amount("145"));
+        let jpy = "JPY".parse().unwrap();
+        assert!(matches!(
+            BalanceSheet::converted(&mut conn, &sheets, &jpy, on),
+            DatabaseResult::NotFound
+        ));
+
+        User::close(&mut conn, "ledger_user").unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+use super::schema::lockouts;
+use super::*;
+use chrono::{NaiveDateTime, Utc};
+
+/// A temporary ban on logging in for a username or an address
+#[derive(Queryable, Debug, PartialEq, Serialize)]
+pub struct Lockout {
+    pub id: i32,
+    /// either "username" or "ip"
+    pub scope: String,
+    pub subject: String,
+    /// failed attempts that triggered the lockout
+    pub failures: i32,
+    pub locked_at: NaiveDateTime,
+    pub locked_until: NaiveDateTime,
+}
+
+impl Lockout {
+    /// records a lockout
+    pub fn add(conn: &mut PgConnection, lockout: &NewLockout) -> DatabaseResult<Lockout> {
+        match diesel::insert_into(lockouts::table)
+            .values(lockout)
+            .get_result::<Lockout>(conn)
+        {
+            Ok(lockout) => DatabaseResult::Succeful(lockout),
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets the lockout on a username or address that lasts the longest,
+    /// DatabaseResult::NotFound if neither is locked
+    pub fn active(
+        conn: &mut PgConnection,
+        username: &str,
+        ip: Option<&str>,
+    ) -> DatabaseResult<Lockout> {
+        use super::schema::lockouts::{locked_until as lu, scope as sc, subject as su};
+        let ip_lock = sc.eq("ip").and(su.nullable().eq(ip));
+        match lockouts::table
+            .filter(sc.eq("username").and(su.eq(username)).or(ip_lock))
+            .filter(lu.gt(Utc::now().naive_utc()))
+            .order(lu.desc())
+            .first::<Lockout>(conn)
+        {
+            Ok(lockout) => DatabaseResult::Succeful(lockout),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets lockouts, newest first, optionally only those still in effect
+    pub fn all(conn: &mut PgConnection, active_only: bool) -> DatabaseResult<Vec<Lockout>> {
+        use super::schema::lockouts::{locked_at as la, locked_until as lu};
+        let mut query = lockouts::table.order(la.desc()).into_boxed();
+        if active_only {
+            query = query.filter(lu.gt(Utc::now().naive_utc()));
+        }
+        match query.load::<Lockout>(conn) {
+            Ok(lockout_vec) => DatabaseResult::Succeful(lockout_vec),
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        }
+    }
+
+    /// deletes a lockout by id
+    pub fn delete_by_id(conn: &mut PgConnection, id: i32) -> DatabaseResult<Lockout> {
+        use super::schema::lockouts::id as i;
+        match diesel::delete(lockouts::table.filter(i.eq(id))).get_result::<Lockout>(conn) {
+            Ok(lockout) => DatabaseResult::Succeful(lockout),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while deleting data, Error message: {}",
+                err
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = lockouts)]
+pub struct NewLockout {
+    scope: String,
+    subject: String,
+    failures: i32,
+    locked_at: NaiveDateTime,
+    locked_until: NaiveDateTime,
+}
+
+impl NewLockout {
+    /// locks a username out until `locked_until`
+    pub fn username(username: &str, failures: i64, locked_until: NaiveDateTime) -> NewLockout {
+        NewLockout::new("username", username, failures, locked_until)
+    }
+
+    /// locks an address out until `locked_until`
+    pub fn ip(ip: &str, failures: i64, locked_until: NaiveDateTime) -> NewLockout {
+        NewLockout::new("ip", ip, failures, locked_until)
+    }
+
+    fn new(scope: &str, subject: &str, failures: i64, locked_until: NaiveDateTime) -> NewLockout {
+        NewLockout {
+            scope: scope.to_string(),
+            subject: subject.to_string(),
+            failures: failures as i32,
+            locked_at: Utc::now().naive_utc(),
+            locked_until,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::establish_connection;
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn lockout_active() {
+        let mut conn = establish_connection();
+        let until = Utc::now().naive_utc() + Duration::minutes(5);
+
+        let by_ip = Lockout::add(&mut conn, &NewLockout::ip("198.51.100.4", 50, until)).unwrap();
+
+        let query_result =
+            Lockout::active(&mut conn, "lockout_user", Some("198.51.100.4")).unwrap();
+        assert_eq!(query_result, by_ip);
+        assert!(matches!(
+            Lockout::active(&mut conn, "lockout_user", None),
+            DatabaseResult::NotFound
+        ));
+
+        // cleans up inserted row
+        Lockout::delete_by_id(&mut conn, by_ip.id);
+    }
+
+    #[test]
+    fn lockout_expired() {
+        let mut conn = establish_connection();
+        let until = Utc::now().naive_utc() - Duration::minutes(5);
+
+        let expired =
+            Lockout::add(&mut conn, &NewLockout::username("lockout_user", 10, until)).unwrap();
+
+        assert!(matches!(
+            Lockout::active(&mut conn, "lockout_user", None),
+            DatabaseResult::NotFound
+        ));
+
+        Lockout::delete_by_id(&mut conn, expired.id);
+    }
+}
@@ -0,0 +1,174 @@
+use super::schema::login_attempts;
+use super::*;
+use chrono::{NaiveDateTime, Utc};
+
+/// A single password check made by routes::login
+#[derive(Queryable, Debug, PartialEq, Serialize)]
+pub struct LoginAttempt {
+    pub id: i32,
+    pub username: String,
+    pub ip: Option<String>,
+    pub succeeded: bool,
+    pub attempted_at: NaiveDateTime,
+}
+
+/// Failed attempts counted against a username or an address
+#[derive(Debug, PartialEq)]
+pub struct Failures {
+    pub count: i64,
+    pub last: Option<NaiveDateTime>,
+}
+
+impl Failures {
+    /// counts the failed attempts `query` selects and finds the latest one
+    fn of<'a>(
+        conn: &mut PgConnection,
+        query: impl Fn() -> login_attempts::BoxedQuery<'a, diesel::pg::Pg>,
+    ) -> DatabaseResult<Failures> {
+        use super::schema::login_attempts::attempted_at as at;
+        let count = match query().count().get_result::<i64>(conn) {
+            Ok(count) => count,
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        };
+        match query()
+            .select(at)
+            .order(at.desc())
+            .first::<NaiveDateTime>(conn)
+            .optional()
+        {
+            Ok(last) => DatabaseResult::Succeful(Failures { count, last }),
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        }
+    }
+}
+
+impl LoginAttempt {
+    /// records a login attempt
+    pub fn add(conn: &mut PgConnection, attempt: &NewLoginAttempt) -> DatabaseResult<LoginAttempt> {
+        match diesel::insert_into(login_attempts::table)
+            .values(attempt)
+            .get_result::<LoginAttempt>(conn)
+        {
+            Ok(attempt) => DatabaseResult::Succeful(attempt),
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// counts failed attempts on a username after `since`
+    ///
+    /// a successful login resets the count
+    pub fn username_failures(
+        conn: &mut PgConnection,
+        username: &str,
+        since: NaiveDateTime,
+    ) -> DatabaseResult<Failures> {
+        use super::schema::login_attempts::{attempted_at as at, succeeded as s, username as u};
+        let last_success = match login_attempts::table
+            .filter(u.eq(username))
+            .filter(s.eq(true))
+            .select(at)
+            .order(at.desc())
+            .first::<NaiveDateTime>(conn)
+            .optional()
+        {
+            Ok(last_success) => last_success,
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        };
+        let since = last_success.map_or(since, |last_success| last_success.max(since));
+
+        Failures::of(conn, || {
+            login_attempts::table
+                .filter(u.eq(username))
+                .filter(s.eq(false))
+                .filter(at.gt(since))
+                .into_boxed()
+        })
+    }
+
+    /// counts failed attempts from an address after `since`
+    ///
+    /// unlike usernames, a success doesn't reset the count, otherwise
+    /// logging in to their own account would let an attacker keep guessing
+    pub fn ip_failures(
+        conn: &mut PgConnection,
+        ip: &str,
+        since: NaiveDateTime,
+    ) -> DatabaseResult<Failures> {
+        use super::schema::login_attempts::{attempted_at as at, ip as i, succeeded as s};
+        Failures::of(conn, || {
+            login_attempts::table
+                .filter(i.eq(ip))
+                .filter(s.eq(false))
+                .filter(at.gt(since))
+                .into_boxed()
+        })
+    }
+
+    /// deletes attempts older than `before`, they no longer count
+    pub fn delete_before(conn: &mut PgConnection, before: NaiveDateTime) -> DatabaseResult<usize> {
+        use super::schema::login_attempts::attempted_at as at;
+        match diesel::delete(login_attempts::table.filter(at.lt(before))).execute(conn) {
+            Ok(count) => DatabaseResult::Succeful(count),
+            Err(err) => panic!(
+                "Something went wrong while deleting data, Error message: {}",
+                err
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = login_attempts)]
+pub struct NewLoginAttempt {
+    username: String,
+    ip: Option<String>,
+    succeeded: bool,
+    attempted_at: NaiveDateTime,
+}
+
+impl NewLoginAttempt {
+    pub fn new(username: String, ip: Option<String>, succeeded: bool) -> NewLoginAttempt {
+        NewLoginAttempt {
+            username,
+            ip,
+            succeeded,
+            attempted_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::establish_connection;
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn login_attempt_failures() {
+        let mut conn = establish_connection();
+        let since = Utc::now().naive_utc() - Duration::minutes(1);
+        let ip = "203.0.113.7".to_string();
+
+        let failed = NewLoginAttempt::new("attempt_user".to_string(), Some(ip.clone()), false);
+        LoginAttempt::add(&mut conn, &failed);
+        LoginAttempt::add(&mut conn, &failed);
+
+        let failures = LoginAttempt::username_failures(&mut conn, "attempt_user", since).unwrap();
+        assert_eq!(failures.count, 2);
+
+        // a success resets the username but not the address
+        let succeeded = NewLoginAttempt::new("attempt_user".to_string(), Some(ip.clone()), true);
+        LoginAttempt::add(&mut conn, &succeeded);
+
+        let failures = LoginAttempt::username_failures(&mut conn, "attempt_user", since).unwrap();
+        assert_eq!(failures.count, 0);
+        let failures = LoginAttempt::ip_failures(&mut conn, &ip, since).unwrap();
+        assert_eq!(failures.count, 2);
+
+        // cleans up inserted rows
+        LoginAttempt::delete_before(&mut conn, Utc::now().naive_utc());
+    }
+}
@@ -1,7 +1,28 @@
 mod account;
+mod account_member;
+mod api_key;
+mod audit_event;
+mod currency;
+mod deletion_request;
+mod encrypted;
+mod exchange_rate;
+mod journal_entry;
+mod ledger;
+mod lockout;
+mod login_attempt;
+mod money;
+mod password_reset;
+mod recovery_code;
+mod refresh_token;
+mod role;
+mod session;
+mod totp_secret;
 mod transaction;
+mod transfer;
 mod user;
+mod user_export;
 
+#[cfg(test)]
 use super::establish_connection;
 use super::schema;
 use chrono::NaiveDate;
@@ -14,9 +35,29 @@ use diesel::serialize::{self, IsNull, Output, ToSql};
 use serde::{Deserialize, Serialize};
 use std::io::Write;
 
-pub use account::{Account, NewAccount};
-pub use transaction::{CurrencyType, NewTransaction, Transaction};
-pub use user::{NewUser, User};
+pub use account::{Account, AccountKind, NewAccount};
+pub use account_member::{AccountMember, MemberRole, NewAccountMember};
+pub use api_key::{ApiKey, NewApiKey, KEY_PREFIX as API_KEY_PREFIX};
+pub use audit_event::{AuditEvent, AuditFilter, NewAuditEvent};
+pub use currency::{Currency, CurrencyCode, CurrencyCodeError, NewCurrency};
+pub use deletion_request::{DeletionRequest, NewDeletionRequest};
+pub use encrypted::Encrypted;
+pub use exchange_rate::{ExchangeRate, NewExchangeRate};
+pub use journal_entry::{JournalEntry, NewJournalEntry, NewPosting, Posting};
+pub use ledger::{AccountBalance, BalanceSheet};
+pub use lockout::{Lockout, NewLockout};
+pub use login_attempt::{Failures, LoginAttempt, NewLoginAttempt};
+pub use money::{Amount, AmountError, Money, Rate};
+pub use password_reset::{NewPasswordReset, PasswordReset};
+pub use recovery_code::RecoveryCode;
+pub use refresh_token::RefreshToken;
+pub use role::Role;
+pub use session::{NewSession, Session};
+pub use totp_secret::{NewTotpSecret, TotpSecret};
+pub use transaction::{NewTransaction, Transaction, TransactionKind};
+pub use transfer::{Transfer, TransferChanges, TransferLegs, TransferOrder};
+pub use user::{NewUser, User, UserChanges};
+pub use user_export::{AccountExport, UserExport};
 
 pub mod result_variant {
     /// Enum to represent state of database calls
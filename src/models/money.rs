@@ -0,0 +1,406 @@
+use super::*;
+use bigdecimal::{BigDecimal, Signed, Zero};
+use diesel::sql_types::Numeric;
+use serde::{de, Deserializer, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// digits a stored amount keeps after the point, matches NUMERIC(19, 4)
+pub const SCALE: i64 = 4;
+/// digits a stored amount can have before the point
+const INTEGER_DIGITS: usize = 15;
+/// digits a stored rate keeps after the point, matches NUMERIC(24, 10)
+pub const RATE_SCALE: i64 = 10;
+/// digits a stored rate can have before the point
+const RATE_INTEGER_DIGITS: usize = 14;
+
+/// A decimal amount of money, stored as NUMERIC
+///
+/// only plain decimals like `-12.5` parse, at most 15 digits before the
+/// point and 4 after it, so nothing gets rounded or cut off on the way in
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Numeric)]
+pub struct Amount(BigDecimal);
+
+/// Why a string isn't an amount or a rate
+#[derive(Debug, PartialEq, Eq)]
+pub enum AmountError {
+    /// not a plain decimal number
+    Invalid,
+    /// more digits after the point than are stored
+    TooPrecise,
+    /// more digits before the point than are stored
+    TooLarge,
+    /// rates have to be positive
+    NotPositive,
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmountError::Invalid => write!(f, "expected a decimal number like \"12.50\""),
+            AmountError::TooPrecise => write!(f, "too many digits after the point"),
+            AmountError::TooLarge => write!(f, "too many digits before the point"),
+            AmountError::NotPositive => write!(f, "has to be positive"),
+        }
+    }
+}
+
+/// parses a plain decimal like `-12.5` with at most `integer_digits`
+/// before the point and `scale` after it
+fn parse_decimal(s: &str, integer_digits: usize, scale: i64) -> Result<BigDecimal, AmountError> {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if integer.is_empty() || !is_digits(integer) || !is_digits(fraction) {
+        return Err(AmountError::Invalid);
+    }
+    if digits.ends_with('.') {
+        return Err(AmountError::Invalid);
+    }
+    if fraction.len() > scale as usize {
+        return Err(AmountError::TooPrecise);
+    }
+    if integer.trim_start_matches('0').len() > integer_digits {
+        return Err(AmountError::TooLarge);
+    }
+    BigDecimal::from_str(s).map_err(|_| AmountError::Invalid)
+}
+
+/// deserializes anything that parses from a string, JSON floats are
+/// refused since they'd already have lost precision
+fn deserialize_decimal<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: FromStr<Err = AmountError>,
+    D: Deserializer<'de>,
+{
+    struct DecimalVisitor<T>(std::marker::PhantomData<T>);
+
+    impl<'de, T: FromStr<Err = AmountError>> de::Visitor<'de> for DecimalVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a decimal number as a string, like \"12.50\"")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<T, E> {
+            value.parse().map_err(E::custom)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<T, E> {
+            self.visit_str(&value.to_string())
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<T, E> {
+            self.visit_str(&value.to_string())
+        }
+    }
+
+    deserializer.deserialize_any(DecimalVisitor(std::marker::PhantomData))
+}
+
+impl std::error::Error for AmountError {}
+
+impl Amount {
+    pub fn zero() -> Amount {
+        Amount(BigDecimal::zero())
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.0.is_positive()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0.is_negative()
+    }
+
+    pub fn as_decimal(&self) -> &BigDecimal {
+        &self.0
+    }
+
+    /// the amount in another currency, rounded to what's stored, None if
+    /// it has more digits before the point than are stored
+    ///
+    /// whole units are compared so rounding it to a currency later can't
+    /// carry it over either
+    pub fn convert(&self, rate: &Rate) -> Option<Amount> {
+        let amount = (&self.0 * &rate.0).round(SCALE);
+        let limit = BigDecimal::from(10u64.pow(INTEGER_DIGITS as u32));
+        (amount.round(0).abs() < limit).then_some(Amount(amount))
+    }
+
+    /// the amount with `digits` after the point, halves away from zero
+    pub fn round(&self, digits: i64) -> Amount {
+        Amount(self.0.round(digits))
+    }
+}
+
+impl Default for Amount {
+    fn default() -> Amount {
+        Amount::zero()
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Amount, AmountError> {
+        parse_decimal(s, INTEGER_DIGITS, SCALE).map(Amount)
+    }
+}
+
+impl fmt::Display for Amount {
+    /// always shows cents, and more decimals only when they're not zero
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let full = self.0.with_scale(SCALE).to_string();
+        let (integer, fraction) = full.split_once('.').unwrap_or((&full, ""));
+        let mut fraction = fraction.trim_end_matches('0').to_string();
+        while fraction.len() < 2 {
+            fraction.push('0');
+        }
+        write!(f, "{}.{}", integer, fraction)
+    }
+}
+
+impl std::ops::Add for Amount {
+    type Output = Amount;
+
+    fn add(self, other: Amount) -> Amount {
+        Amount(self.0 + other.0)
+    }
+}
+
+impl std::ops::Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, other: Amount) -> Amount {
+        Amount(self.0 - other.0)
+    }
+}
+
+impl std::ops::Neg for Amount {
+    type Output = Amount;
+
+    fn neg(self) -> Amount {
+        Amount(-self.0)
+    }
+}
+
+impl Serialize for Amount {
+    /// as a string, JSON numbers are floats and would lose cents
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+        deserialize_decimal(deserializer)
+    }
+}
+
+impl ToSql<Numeric, Pg> for Amount {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <BigDecimal as ToSql<Numeric, Pg>>::to_sql(&self.0, out)
+    }
+}
+
+impl FromSql<Numeric, Pg> for Amount {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        <BigDecimal as FromSql<Numeric, Pg>>::from_sql(bytes).map(Amount)
+    }
+}
+
+/// An exchange rate, how much of the other currency one unit buys,
+/// stored as NUMERIC
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Numeric)]
+pub struct Rate(BigDecimal);
+
+impl Rate {
+    /// the rate between a currency and itself
+    pub fn one() -> Rate {
+        Rate(BigDecimal::from(1))
+    }
+
+    pub fn as_decimal(&self) -> &BigDecimal {
+        &self.0
+    }
+
+    /// the rate the other way round, None if it's too small to keep
+    pub fn inverse(&self) -> Option<Rate> {
+        Rate::cross(self, &Rate::one())
+    }
+
+    /// the rate between two currencies quoted against the same base,
+    /// None if it's too small to keep
+    pub fn cross(from: &Rate, to: &Rate) -> Option<Rate> {
+        let rate = (&to.0 / &from.0).round(RATE_SCALE);
+        rate.is_positive().then_some(Rate(rate))
+    }
+}
+
+impl FromStr for Rate {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Rate, AmountError> {
+        let rate = parse_decimal(s, RATE_INTEGER_DIGITS, RATE_SCALE)?;
+        match rate.is_positive() {
+            true => Ok(Rate(rate)),
+            false => Err(AmountError::NotPositive),
+        }
+    }
+}
+
+impl fmt::Display for Rate {
+    /// without trailing zeros
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let full = self.0.with_scale(RATE_SCALE).to_string();
+        let trimmed = full.trim_end_matches('0').trim_end_matches('.');
+        write!(f, "{}", trimmed)
+    }
+}
+
+impl Serialize for Rate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Rate, D::Error> {
+        deserialize_decimal(deserializer)
+    }
+}
+
+impl ToSql<Numeric, Pg> for Rate {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <BigDecimal as ToSql<Numeric, Pg>>::to_sql(&self.0, out)
+    }
+}
+
+impl FromSql<Numeric, Pg> for Rate {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        <BigDecimal as FromSql<Numeric, Pg>>::from_sql(bytes).map(Rate)
+    }
+}
+
+/// An amount in a currency
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Money {
+    pub amount: Amount,
+    pub currency: CurrencyCode,
+}
+
+impl Money {
+    pub fn new(amount: Amount, currency: CurrencyCode) -> Money {
+        Money { amount, currency }
+    }
+
+    /// adds two sums of the same currency, None if they differ
+    pub fn checked_add(&self, other: &Money) -> Option<Money> {
+        if self.currency != other.currency {
+            return None;
+        }
+        let amount = self.amount.clone() + other.amount.clone();
+        Some(Money::new(amount, self.currency.clone()))
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.currency)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocket::serde::json::{from_str, to_string};
+
+    #[test]
+    fn amount_parse() {
+        assert_eq!("12.5".parse::<Amount>().unwrap().to_string(), "12.50");
+        assert_eq!("-0.0001".parse::<Amount>().unwrap().to_string(), "-0.0001");
+        assert_eq!("100".parse::<Amount>().unwrap().to_string(), "100.00");
+        assert_eq!("007.10".parse::<Amount>().unwrap().to_string(), "7.10");
+        for invalid in [
+            "abc", "", "-", ".5", "5.", "1e3", "1,000", " 1", "+1", "NaN",
+        ] {
+            assert_eq!(
+                invalid.parse::<Amount>(),
+                Err(AmountError::Invalid),
+                "{}",
+                invalid
+            );
+        }
+        assert_eq!("0.00001".parse::<Amount>(), Err(AmountError::TooPrecise));
+        assert_eq!(
+            "1234567890123456".parse::<Amount>(),
+            Err(AmountError::TooLarge)
+        );
+        assert_eq!(
+            "12.5".parse::<Amount>().unwrap(),
+            "12.5000".parse::<Amount>().unwrap()
+        );
+    }
+
+    #[test]
+    fn rate_convert() {
+        let rate: Rate = "0.0000238095".parse().unwrap();
+        assert_eq!(rate.to_string(), "0.0000238095");
+        assert_eq!(Rate::one().to_string(), "1");
+        let rials: Amount = "4200000".parse().unwrap();
+        assert_eq!(rials.convert(&rate).unwrap().to_string(), "99.9999");
+        let dollars: Amount = "10.05".parse().unwrap();
+        assert_eq!(
+            dollars
+                .convert(&"1.35".parse().unwrap())
+                .unwrap()
+                .to_string(),
+            "13.5675"
+        );
+        assert_eq!(dollars.convert(&Rate::one()), Some(dollars));
+        // NUMERIC(19, 4) can't hold more than 15 digits before the point
+        let most: Amount = "999999999999999".parse().unwrap();
+        assert_eq!(most.convert(&Rate::one()), Some(most.clone()));
+        assert_eq!(most.convert(&"1.01".parse().unwrap()), None);
+        assert_eq!((-most).convert(&"1.01".parse().unwrap()), None);
+        let almost: Amount = "999999999999999.9".parse().unwrap();
+        assert_eq!(almost.convert(&Rate::one()), None);
+
+        let usd: Rate = "1.04".parse().unwrap();
+        let cad: Rate = "1.3832".parse().unwrap();
+        assert_eq!(usd.inverse().unwrap().to_string(), "0.9615384615");
+        assert_eq!(Rate::cross(&usd, &cad).unwrap().to_string(), "1.33");
+        let huge: Rate = "99999999999999".parse().unwrap();
+        assert_eq!(huge.inverse(), None);
+
+        assert_eq!("0".parse::<Rate>(), Err(AmountError::NotPositive));
+        assert_eq!("-1.2".parse::<Rate>(), Err(AmountError::NotPositive));
+        assert_eq!(
+            "0.00000000001".parse::<Rate>(),
+            Err(AmountError::TooPrecise)
+        );
+        assert!(from_str::<Rate>("1.5").is_err());
+        assert_eq!(from_str::<Rate>(r#""1.50""#).unwrap().to_string(), "1.5");
+    }
+
+    #[test]
+    fn money_serde() {
+        let money: Money = from_str(r#"{"amount": "19.99", "currency": "USD"}"#).unwrap();
+        assert_eq!(
+            to_string(&money).unwrap(),
+            r#"{"amount":"19.99","currency":"USD"}"#
+        );
+        assert!(from_str::<Money>(r#"{"amount": "abc", "currency": "USD"}"#).is_err());
+        // floats would already have lost precision
+        assert!(from_str::<Money>(r#"{"amount": 19.99, "currency": "USD"}"#).is_err());
+        assert_eq!(from_str::<Amount>("20").unwrap().to_string(), "20.00");
+
+        let cad = Money::new("1".parse().unwrap(), "CAD".parse().unwrap());
+        assert_eq!(money.checked_add(&cad), None);
+        let sum = money.checked_add(&money).unwrap();
+        assert_eq!(sum.to_string(), "39.98 USD");
+    }
+}
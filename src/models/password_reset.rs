@@ -0,0 +1,216 @@
+use super::schema::{api_keys, password_resets, sessions, users};
+use super::*;
+use crate::authentication::hasher::token_hash;
+use crate::authentication::random_token;
+use chrono::{Duration, NaiveDateTime, Utc};
+use std::env;
+
+/// A single use token that lets a user pick a new password,
+/// only its hash is stored
+#[derive(Queryable, Debug, PartialEq)]
+pub struct PasswordReset {
+    pub id: i32,
+    pub user_id: String,
+    pub token_hash: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+impl PasswordReset {
+    /// stores a reset token, earlier unused tokens of the user stop working
+    pub fn add(
+        conn: &mut PgConnection,
+        new_reset: &NewPasswordReset,
+    ) -> DatabaseResult<PasswordReset> {
+        use super::schema::password_resets::{used_at as ua, user_id as ui};
+        let added = conn.transaction::<_, Error, _>(|conn| {
+            diesel::delete(
+                password_resets::table
+                    .filter(ui.eq(&new_reset.user_id))
+                    .filter(ua.is_null()),
+            )
+            .execute(conn)?;
+            diesel::insert_into(password_resets::table)
+                .values(new_reset)
+                .get_result::<PasswordReset>(conn)
+        });
+        match added {
+            Ok(reset) => DatabaseResult::Succeful(reset),
+            Err(Error::DatabaseError(_, _)) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets the latest reset token requested for a user
+    pub fn latest(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<PasswordReset> {
+        use super::schema::password_resets::{created_at as ca, user_id as ui};
+        match password_resets::table
+            .filter(ui.eq(user_id))
+            .order(ca.desc())
+            .first::<PasswordReset>(conn)
+        {
+            Ok(reset) => DatabaseResult::Succeful(reset),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// uses up a reset token and sets the user's new password hash
+    ///
+    /// all of the user's sessions (and so their refresh tokens) and API
+    /// keys are deleted and tokens minted until now are refused, see
+    /// `User::tokens_valid`, returns the username. Returns DatabaseResult::NotFound
+    /// if the token is unknown, used or expired
+    pub fn redeem(
+        conn: &mut PgConnection,
+        token: &str,
+        password_hash: &str,
+    ) -> DatabaseResult<String> {
+        use super::schema::password_resets::{expires_at as ea, token_hash as th, used_at as ua};
+        let now = Utc::now().naive_utc();
+        let redeemed = conn.transaction::<_, Error, _>(|conn| {
+            let reset = match diesel::update(
+                password_resets::table
+                    .filter(th.eq(token_hash(token)))
+                    .filter(ua.is_null())
+                    .filter(ea.gt(now)),
+            )
+            .set(ua.eq(now))
+            .get_result::<PasswordReset>(conn)
+            .optional()?
+            {
+                Some(reset) => reset,
+                None => return Ok(None),
+            };
+
+            let user_id = reset.user_id;
+            diesel::update(users::table.find(&user_id))
+                .set((
+                    users::password.eq(password_hash),
+                    users::tokens_valid_after.eq(now),
+                ))
+                .execute(conn)?;
+            diesel::delete(sessions::table.filter(sessions::user_id.eq(&user_id))).execute(conn)?;
+            diesel::delete(api_keys::table.filter(api_keys::user_id.eq(&user_id))).execute(conn)?;
+            Ok(Some(user_id))
+        });
+
+        match redeemed {
+            Ok(Some(user_id)) => DatabaseResult::Succeful(user_id),
+            Ok(None) => DatabaseResult::NotFound,
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = password_resets)]
+pub struct NewPasswordReset {
+    user_id: String,
+    token_hash: String,
+    created_at: NaiveDateTime,
+    expires_at: NaiveDateTime,
+}
+
+impl NewPasswordReset {
+    /// generates a reset token for a user, returns it with the row to store
+    ///
+    /// tokens last PASSWORD_RESET_TTL_MINUTES, an hour by default
+    pub fn new(user_id: String) -> (NewPasswordReset, String) {
+        let ttl = env::var("PASSWORD_RESET_TTL_MINUTES")
+            .ok()
+            .and_then(|minutes| minutes.parse().ok())
+            .unwrap_or(60);
+        let token = random_token();
+        let now = Utc::now().naive_utc();
+        let new_reset = NewPasswordReset {
+            user_id,
+            token_hash: token_hash(&token),
+            created_at: now,
+            expires_at: now + Duration::minutes(ttl),
+        };
+        (new_reset, token)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::establish_connection;
+    use super::*;
+
+    fn add_user(conn: &mut PgConnection, username: &str) {
+        User::delete_by_username(conn, username);
+        let new_user = NewUser::new(
+            "Reset".to_string(),
+            username.to_string(),
+            "old hash".to_string(),
+        );
+        User::add(conn, &new_user).unwrap();
+    }
+
+    #[test]
+    fn password_reset_redeem() {
+        let mut conn = establish_connection();
+        add_user(&mut conn, "reset_user");
+        let session = Session::add(
+            &mut conn,
+            &NewSession::new("reset_user".to_string(), None, None),
+        )
+        .unwrap();
+
+        let (new_reset, token) = NewPasswordReset::new("reset_user".to_string());
+        PasswordReset::add(&mut conn, &new_reset).unwrap();
+
+        let query_result = PasswordReset::redeem(&mut conn, &token, "new hash").unwrap();
+        assert_eq!(query_result, "reset_user");
+        assert_eq!(
+            User::get(&mut conn, "reset_user").unwrap().password,
+            "new hash"
+        );
+        assert!(matches!(
+            Session::get(&mut conn, session.id),
+            DatabaseResult::NotFound
+        ));
+        // whatever was minted before the reset is refused
+        let user = User::get(&mut conn, "reset_user").unwrap();
+        assert!(!user.tokens_valid(session.created_at));
+        assert!(user.tokens_valid(Utc::now().naive_utc()));
+        // tokens only work once
+        assert!(matches!(
+            PasswordReset::redeem(&mut conn, &token, "newer hash"),
+            DatabaseResult::NotFound
+        ));
+
+        // cleans up the user, resets cascade
+        User::delete_by_username(&mut conn, "reset_user");
+    }
+
+    #[test]
+    fn password_reset_replaces_earlier_token() {
+        let mut conn = establish_connection();
+        add_user(&mut conn, "reset_user2");
+
+        let (first, first_token) = NewPasswordReset::new("reset_user2".to_string());
+        PasswordReset::add(&mut conn, &first).unwrap();
+        let (second, _) = NewPasswordReset::new("reset_user2".to_string());
+        let added = PasswordReset::add(&mut conn, &second).unwrap();
+
+        assert!(matches!(
+            PasswordReset::redeem(&mut conn, &first_token, "new hash"),
+            DatabaseResult::NotFound
+        ));
+        assert_eq!(
+            PasswordReset::latest(&mut conn, "reset_user2").unwrap(),
+            added
+        );
+
+        User::delete_by_username(&mut conn, "reset_user2");
+    }
+}
@@ -0,0 +1,164 @@
+use super::schema::recovery_codes;
+use super::*;
+use crate::authentication::hasher::token_hash;
+use chrono::{NaiveDateTime, Utc};
+use rand::{thread_rng, Rng};
+
+/// codes handed out each time they're generated
+const CODE_COUNT: usize = 10;
+/// no 0/o or 1/l/i, codes get read off paper
+const CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// A one time code that stands in for a TOTP code when the
+/// authenticator app is lost, only its hash is stored
+#[derive(Queryable, Debug, PartialEq)]
+pub struct RecoveryCode {
+    pub id: i32,
+    pub user_id: String,
+    pub code_hash: String,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+impl RecoveryCode {
+    /// replaces a user's recovery codes with new ones and returns them
+    pub fn replace(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<Vec<String>> {
+        use super::schema::recovery_codes::user_id as ui;
+        let codes: Vec<String> = (0..CODE_COUNT).map(|_| random_code()).collect();
+        let new_codes: Vec<NewRecoveryCode> = codes
+            .iter()
+            .map(|code| NewRecoveryCode::new(user_id.to_string(), code))
+            .collect();
+        let replaced = conn.transaction::<_, Error, _>(|conn| {
+            diesel::delete(recovery_codes::table.filter(ui.eq(user_id))).execute(conn)?;
+            diesel::insert_into(recovery_codes::table)
+                .values(&new_codes)
+                .execute(conn)
+        });
+        match replaced {
+            Ok(_) => DatabaseResult::Succeful(codes),
+            Err(Error::DatabaseError(_, _)) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// uses up one of a user's recovery codes
+    ///
+    /// returns DatabaseResult::NotFound if the code is wrong or was used
+    pub fn redeem(
+        conn: &mut PgConnection,
+        user_id: &str,
+        code: &str,
+    ) -> DatabaseResult<RecoveryCode> {
+        use super::schema::recovery_codes::{code_hash as ch, used_at as ua, user_id as ui};
+        match diesel::update(
+            recovery_codes::table
+                .filter(ui.eq(user_id))
+                .filter(ch.eq(token_hash(&normalize(code))))
+                .filter(ua.is_null()),
+        )
+        .set(ua.eq(Utc::now().naive_utc()))
+        .get_result::<RecoveryCode>(conn)
+        {
+            Ok(code) => DatabaseResult::Succeful(code),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
+        }
+    }
+
+    /// counts a user's unused recovery codes
+    pub fn remaining(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<i64> {
+        use super::schema::recovery_codes::{used_at as ua, user_id as ui};
+        match recovery_codes::table
+            .filter(ui.eq(user_id))
+            .filter(ua.is_null())
+            .count()
+            .get_result::<i64>(conn)
+        {
+            Ok(count) => DatabaseResult::Succeful(count),
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        }
+    }
+
+    /// deletes all of a user's recovery codes
+    pub fn delete_all(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<usize> {
+        use super::schema::recovery_codes::user_id as ui;
+        match diesel::delete(recovery_codes::table.filter(ui.eq(user_id))).execute(conn) {
+            Ok(count) => DatabaseResult::Succeful(count),
+            Err(err) => panic!(
+                "Something went wrong while deleting data, Error message: {}",
+                err
+            ),
+        }
+    }
+}
+
+/// a code like `k7wq3-xm2fp`
+fn random_code() -> String {
+    let mut rng = thread_rng();
+    let mut code: String = (0..10)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect();
+    code.insert(5, '-');
+    code
+}
+
+/// lets codes be typed without the dash and in any case
+fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = recovery_codes)]
+pub struct NewRecoveryCode {
+    user_id: String,
+    code_hash: String,
+}
+
+impl NewRecoveryCode {
+    fn new(user_id: String, code: &str) -> NewRecoveryCode {
+        NewRecoveryCode {
+            user_id,
+            code_hash: token_hash(&normalize(code)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // make sure a test user with username "BerserkerMother" exist in database
+    use super::super::establish_connection;
+    use super::*;
+
+    #[test]
+    fn recovery_code_redeem() {
+        let mut conn = establish_connection();
+
+        let codes = RecoveryCode::replace(&mut conn, "BerserkerMother").unwrap();
+        assert_eq!(codes.len(), CODE_COUNT);
+
+        let typed = codes[0].replace('-', "").to_uppercase();
+        RecoveryCode::redeem(&mut conn, "BerserkerMother", &typed).unwrap();
+        // every code works once
+        assert!(matches!(
+            RecoveryCode::redeem(&mut conn, "BerserkerMother", &codes[0]),
+            DatabaseResult::NotFound
+        ));
+        assert!(matches!(
+            RecoveryCode::redeem(&mut conn, "someone_else", &codes[1]),
+            DatabaseResult::NotFound
+        ));
+        assert_eq!(
+            RecoveryCode::remaining(&mut conn, "BerserkerMother").unwrap(),
+            CODE_COUNT as i64 - 1
+        );
+
+        // cleans up inserted rows
+        RecoveryCode::delete_all(&mut conn, "BerserkerMother");
+    }
+}
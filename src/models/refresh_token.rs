@@ -0,0 +1,160 @@
+use super::schema::{refresh_tokens, sessions};
+use super::*;
+use crate::authentication::hasher::token_hash;
+use crate::authentication::random_token;
+use chrono::{NaiveDateTime, Utc};
+
+/// A single use token that trades in for a new access token
+///
+/// only the hash of the token is stored, tokens minted for the
+/// same session form a family
+#[derive(Queryable, Debug, PartialEq)]
+pub struct RefreshToken {
+    pub id: i32,
+    pub session_id: i32,
+    pub token_hash: String,
+    pub issued_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+impl RefreshToken {
+    /// mints a new refresh token for a session and returns it
+    pub fn issue(conn: &mut PgConnection, session_id: i32) -> DatabaseResult<String> {
+        let token = random_token();
+        match diesel::insert_into(refresh_tokens::table)
+            .values(&NewRefreshToken::new(session_id, &token))
+            .execute(conn)
+        {
+            Ok(_) => DatabaseResult::Succeful(token),
+            Err(Error::DatabaseError(_, _)) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// exchanges a refresh token for a new one of the same family
+    ///
+    /// each token can be exchanged once. Presenting a used token again
+    /// means it leaked, so the whole family is revoked by deleting its
+    /// session and DatabaseResult::NotFound is returned
+    pub fn rotate(conn: &mut PgConnection, token: &str) -> DatabaseResult<(Session, String)> {
+        use super::schema::refresh_tokens::{id as i, token_hash as th, used_at as ua};
+        let rotated = conn.transaction::<_, Error, _>(|conn| {
+            let presented = match refresh_tokens::table
+                .filter(th.eq(token_hash(token)))
+                .for_update()
+                .first::<RefreshToken>(conn)
+                .optional()?
+            {
+                Some(presented) => presented,
+                None => return Ok(None),
+            };
+
+            if presented.used_at.is_some() {
+                diesel::delete(sessions::table.find(presented.session_id)).execute(conn)?;
+                return Ok(None);
+            }
+
+            let session = match Session::touch(conn, presented.session_id) {
+                DatabaseResult::Succeful(session)
+                    if session.expires_at > Utc::now().naive_utc() =>
+                {
+                    session
+                }
+                _ => return Ok(None),
+            };
+
+            diesel::update(refresh_tokens::table.filter(i.eq(presented.id)))
+                .set(ua.eq(Utc::now().naive_utc()))
+                .execute(conn)?;
+
+            let token = random_token();
+            diesel::insert_into(refresh_tokens::table)
+                .values(&NewRefreshToken::new(session.id, &token))
+                .execute(conn)?;
+            Ok(Some((session, token)))
+        });
+
+        match rotated {
+            Ok(Some(rotated)) => DatabaseResult::Succeful(rotated),
+            Ok(None) => DatabaseResult::NotFound,
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = refresh_tokens)]
+pub struct NewRefreshToken {
+    session_id: i32,
+    token_hash: String,
+    issued_at: NaiveDateTime,
+}
+
+impl NewRefreshToken {
+    fn new(session_id: i32, token: &str) -> NewRefreshToken {
+        NewRefreshToken {
+            session_id,
+            token_hash: token_hash(token),
+            issued_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // make sure a test user with username "BerserkerMother" exist in database
+    use super::super::establish_connection;
+    use super::*;
+
+    #[test]
+    fn refresh_token_rotate() {
+        let mut conn = establish_connection();
+
+        let session = Session::add(
+            &mut conn,
+            &NewSession::new("BerserkerMother".to_string(), None, None),
+        )
+        .unwrap();
+        let first = RefreshToken::issue(&mut conn, session.id).unwrap();
+
+        let (rotated_session, second) = RefreshToken::rotate(&mut conn, &first).unwrap();
+
+        assert_eq!(rotated_session.id, session.id);
+        assert_ne!(first, second);
+
+        // cleans up inserted rows
+        Session::delete(&mut conn, session.id, "BerserkerMother");
+    }
+
+    #[test]
+    fn refresh_token_reuse_revokes_family() {
+        let mut conn = establish_connection();
+
+        let session = Session::add(
+            &mut conn,
+            &NewSession::new("BerserkerMother".to_string(), None, None),
+        )
+        .unwrap();
+        let first = RefreshToken::issue(&mut conn, session.id).unwrap();
+        let (_, second) = RefreshToken::rotate(&mut conn, &first).unwrap();
+
+        // the stolen first token is replayed
+        assert!(matches!(
+            RefreshToken::rotate(&mut conn, &first),
+            DatabaseResult::NotFound
+        ));
+
+        // the legitimate holder is logged out as well
+        assert!(matches!(
+            RefreshToken::rotate(&mut conn, &second),
+            DatabaseResult::NotFound
+        ));
+        assert!(matches!(
+            Session::get(&mut conn, session.id),
+            DatabaseResult::NotFound
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+use super::schema::{role_permissions, roles};
+use super::*;
+
+/// A named set of permissions every user is assigned one of
+#[derive(Queryable, Debug, PartialEq, Serialize)]
+pub struct Role {
+    pub name: String,
+    pub description: String,
+    /// members can only use their permissions after logging in with 2FA
+    pub require_2fa: bool,
+}
+
+impl Role {
+    /// gets a role by name
+    pub fn get(conn: &mut PgConnection, name: &str) -> DatabaseResult<Role> {
+        match roles::table.find(name).first::<Role>(conn) {
+            Ok(role) => DatabaseResult::Succeful(role),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// sets whether members have to log in with 2FA to use their permissions
+    pub fn set_require_2fa(
+        conn: &mut PgConnection,
+        name: &str,
+        require_2fa: bool,
+    ) -> DatabaseResult<Role> {
+        use super::schema::roles::require_2fa as r2;
+        match diesel::update(roles::table.find(name))
+            .set(r2.eq(require_2fa))
+            .get_result::<Role>(conn)
+        {
+            Ok(role) => DatabaseResult::Succeful(role),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
+        }
+    }
+
+    /// gets the names of all permissions granted to a role
+    pub fn permissions(conn: &mut PgConnection, role: &str) -> DatabaseResult<Vec<String>> {
+        use super::schema::role_permissions::{permission as p, role as r};
+        match role_permissions::table
+            .filter(r.eq(role))
+            .select(p)
+            .order(p)
+            .load::<String>(conn)
+        {
+            Ok(permission_vec) => DatabaseResult::Succeful(permission_vec),
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        }
+    }
+
+    /// checks whether a role is granted a permission
+    pub fn has_permission(
+        conn: &mut PgConnection,
+        role: &str,
+        permission: &str,
+    ) -> DatabaseResult<bool> {
+        match role_permissions::table
+            .find((role, permission))
+            .first::<(String, String)>(conn)
+        {
+            Ok(_) => DatabaseResult::Succeful(true),
+            Err(Error::NotFound) => DatabaseResult::Succeful(false),
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // relies on the roles seeded by the roles_permissions migration
+    use super::super::establish_connection;
+    use super::*;
+
+    #[test]
+    fn role_permissions() {
+        let mut conn = establish_connection();
+
+        let admin = Role::permissions(&mut conn, "admin").unwrap();
+        let support = Role::permissions(&mut conn, "support").unwrap();
+        let user = Role::permissions(&mut conn, "user").unwrap();
+
+        assert!(admin.contains(&"users:delete".to_string()));
+        assert_eq!(support, vec!["users:read".to_string()]);
+        assert!(user.is_empty());
+    }
+
+    #[test]
+    fn role_has_permission() {
+        let mut conn = establish_connection();
+
+        assert!(Role::has_permission(&mut conn, "support", "users:read").unwrap());
+        assert!(!Role::has_permission(&mut conn, "support", "users:write").unwrap());
+        assert!(!Role::has_permission(&mut conn, "missing", "users:read").unwrap());
+    }
+
+    #[test]
+    fn role_set_require_2fa() {
+        let mut conn = establish_connection();
+
+        let role = Role::set_require_2fa(&mut conn, "support", true).unwrap();
+        assert!(role.require_2fa);
+        assert!(Role::get(&mut conn, "support").unwrap().require_2fa);
+        assert!(matches!(
+            Role::set_require_2fa(&mut conn, "missing", true),
+            DatabaseResult::NotFound
+        ));
+
+        // puts the seeded role back
+        Role::set_require_2fa(&mut conn, "support", false);
+    }
+}
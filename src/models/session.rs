@@ -0,0 +1,224 @@
+use super::schema::sessions;
+use super::*;
+use chrono::{Duration, NaiveDateTime, Utc};
+use std::env;
+
+/// A logged in device
+///
+/// the session is the family its refresh tokens belong to,
+/// deleting it revokes all of them
+#[derive(Queryable, Debug, PartialEq, Serialize)]
+pub struct Session {
+    pub id: i32,
+    pub user_id: String,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// whether the login that started it passed a second factor
+    pub two_factor: bool,
+}
+
+impl Session {
+    /// starts a new session
+    pub fn add(conn: &mut PgConnection, new_session: &NewSession) -> DatabaseResult<Session> {
+        match diesel::insert_into(sessions::table)
+            .values(new_session)
+            .get_result::<Session>(conn)
+        {
+            Ok(session) => DatabaseResult::Succeful(session),
+            Err(Error::DatabaseError(_, _)) => DatabaseResult::AlreadyExists,
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets an unexpired session by id
+    pub fn get(conn: &mut PgConnection, id: i32) -> DatabaseResult<Session> {
+        use super::schema::sessions::{expires_at as ea, id as i};
+        match sessions::table
+            .filter(i.eq(id))
+            .filter(ea.gt(Utc::now().naive_utc()))
+            .first::<Session>(conn)
+        {
+            Ok(session) => DatabaseResult::Succeful(session),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// marks a session as used now
+    pub fn touch(conn: &mut PgConnection, id: i32) -> DatabaseResult<Session> {
+        use super::schema::sessions::{id as i, last_used_at as lu};
+        match diesel::update(sessions::table.filter(i.eq(id)))
+            .set(lu.eq(Utc::now().naive_utc()))
+            .get_result::<Session>(conn)
+        {
+            Ok(session) => DatabaseResult::Succeful(session),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
+        }
+    }
+
+    /// gets all unexpired sessions of a user, most recently used first
+    pub fn all(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<Vec<Session>> {
+        use super::schema::sessions::{expires_at as ea, last_used_at as lu, user_id as ui};
+        match sessions::table
+            .filter(ui.eq(user_id))
+            .filter(ea.gt(Utc::now().naive_utc()))
+            .order(lu.desc())
+            .load::<Session>(conn)
+        {
+            Ok(session_vec) => DatabaseResult::Succeful(session_vec),
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        }
+    }
+
+    /// deletes one of a user's sessions
+    ///
+    /// returns DatabaseResult::NotFound if the session doesn't
+    /// exist or belongs to someone else
+    pub fn delete(conn: &mut PgConnection, id: i32, user_id: &str) -> DatabaseResult<Session> {
+        use super::schema::sessions::{id as i, user_id as ui};
+        match diesel::delete(sessions::table.filter(i.eq(id)).filter(ui.eq(user_id)))
+            .get_result::<Session>(conn)
+        {
+            Ok(session) => DatabaseResult::Succeful(session),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while deleting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// deletes every session of a user, logging them out everywhere
+    pub fn delete_all(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<Vec<Session>> {
+        use super::schema::sessions::user_id as ui;
+        match diesel::delete(sessions::table.filter(ui.eq(user_id))).get_results::<Session>(conn) {
+            Ok(session_vec) => DatabaseResult::Succeful(session_vec),
+            Err(err) => panic!(
+                "Something went wrong while deleting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// deletes a user's expired sessions
+    pub fn delete_expired(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<usize> {
+        use super::schema::sessions::{expires_at as ea, user_id as ui};
+        match diesel::delete(
+            sessions::table
+                .filter(ui.eq(user_id))
+                .filter(ea.le(Utc::now().naive_utc())),
+        )
+        .execute(conn)
+        {
+            Ok(count) => DatabaseResult::Succeful(count),
+            Err(err) => panic!(
+                "Something went wrong while deleting data, Error message: {}",
+                err
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = sessions)]
+pub struct NewSession {
+    user_id: String,
+    created_at: NaiveDateTime,
+    last_used_at: NaiveDateTime,
+    expires_at: NaiveDateTime,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    two_factor: bool,
+}
+
+impl NewSession {
+    /// creates a session for a user
+    ///
+    /// sessions live for SESSION_TTL_HOURS, two weeks by default,
+    /// no matter how often their refresh token is rotated
+    pub fn new(user_id: String, user_agent: Option<String>, ip: Option<String>) -> NewSession {
+        let ttl = env::var("SESSION_TTL_HOURS")
+            .ok()
+            .and_then(|hours| hours.parse().ok())
+            .unwrap_or(14 * 24);
+        let now = Utc::now().naive_utc();
+        NewSession {
+            user_id,
+            created_at: now,
+            last_used_at: now,
+            expires_at: now + Duration::hours(ttl),
+            user_agent,
+            ip,
+            two_factor: false,
+        }
+    }
+
+    /// marks the session as started with a second factor
+    pub fn two_factor(mut self, two_factor: bool) -> NewSession {
+        self.two_factor = two_factor;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // make sure a test user with username "BerserkerMother" exist in database
+    use super::super::establish_connection;
+    use super::*;
+
+    #[test]
+    fn session_get() {
+        let mut conn = establish_connection();
+
+        let new_session = NewSession::new("BerserkerMother".to_string(), None, None);
+        let added = Session::add(&mut conn, &new_session).unwrap();
+
+        let query_result = Session::get(&mut conn, added.id).unwrap();
+
+        assert_eq!(query_result, added);
+
+        // cleans up inserted row
+        Session::delete(&mut conn, added.id, "BerserkerMother");
+    }
+
+    #[test]
+    fn session_expired() {
+        let mut conn = establish_connection();
+
+        let mut new_session = NewSession::new("BerserkerMother".to_string(), None, None);
+        new_session.expires_at = new_session.created_at - Duration::hours(1);
+        let added = Session::add(&mut conn, &new_session).unwrap();
+
+        assert!(matches!(
+            Session::get(&mut conn, added.id),
+            DatabaseResult::NotFound
+        ));
+
+        Session::delete_expired(&mut conn, "BerserkerMother").unwrap();
+    }
+
+    #[test]
+    fn session_delete_other_user() {
+        let mut conn = establish_connection();
+
+        let new_session = NewSession::new("BerserkerMother".to_string(), None, None);
+        let added = Session::add(&mut conn, &new_session).unwrap();
+
+        assert!(matches!(
+            Session::delete(&mut conn, added.id, "someone_else"),
+            DatabaseResult::NotFound
+        ));
+
+        Session::delete(&mut conn, added.id, "BerserkerMother").unwrap();
+    }
+}
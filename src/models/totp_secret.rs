@@ -0,0 +1,201 @@
+use super::schema::totp_secrets;
+use super::*;
+use chrono::{NaiveDateTime, Utc};
+
+/// The shared secret behind a user's authenticator app
+///
+/// 2FA is only on once the secret is confirmed with a code from the app
+#[derive(Queryable, Debug, PartialEq)]
+pub struct TotpSecret {
+    pub user_id: String,
+    pub secret: Vec<u8>,
+    pub created_at: NaiveDateTime,
+    pub confirmed_at: Option<NaiveDateTime>,
+    /// time step of the last accepted code, older codes are rejected
+    pub last_used_step: Option<i64>,
+}
+
+impl TotpSecret {
+    /// gets a user's secret, confirmed or not
+    pub fn get(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<TotpSecret> {
+        match totp_secrets::table.find(user_id).first::<TotpSecret>(conn) {
+            Ok(secret) => DatabaseResult::Succeful(secret),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets a user's secret if they have 2FA on
+    pub fn get_confirmed(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<TotpSecret> {
+        match TotpSecret::get(conn, user_id) {
+            DatabaseResult::Succeful(secret) if secret.confirmed_at.is_some() => {
+                DatabaseResult::Succeful(secret)
+            }
+            _ => DatabaseResult::NotFound,
+        }
+    }
+
+    /// stores a new unconfirmed secret, replacing an earlier unconfirmed one
+    ///
+    /// returns DatabaseResult::AlreadyExists if the user already has 2FA on
+    pub fn enroll(
+        conn: &mut PgConnection,
+        new_secret: &NewTotpSecret,
+    ) -> DatabaseResult<TotpSecret> {
+        use super::schema::totp_secrets::{confirmed_at as ca, user_id as ui};
+        let enrolled = conn.transaction::<_, Error, _>(|conn| {
+            diesel::delete(
+                totp_secrets::table
+                    .filter(ui.eq(&new_secret.user_id))
+                    .filter(ca.is_null()),
+            )
+            .execute(conn)?;
+            diesel::insert_into(totp_secrets::table)
+                .values(new_secret)
+                .get_result::<TotpSecret>(conn)
+        });
+        match enrolled {
+            Ok(secret) => DatabaseResult::Succeful(secret),
+            Err(Error::DatabaseError(_, _)) => DatabaseResult::AlreadyExists,
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// turns 2FA on, `step` is the time step of the code that confirmed it
+    pub fn confirm(
+        conn: &mut PgConnection,
+        user_id: &str,
+        step: i64,
+    ) -> DatabaseResult<TotpSecret> {
+        use super::schema::totp_secrets::{
+            confirmed_at as ca, last_used_step as ls, user_id as ui,
+        };
+        match diesel::update(
+            totp_secrets::table
+                .filter(ui.eq(user_id))
+                .filter(ca.is_null()),
+        )
+        .set((ca.eq(Utc::now().naive_utc()), ls.eq(step)))
+        .get_result::<TotpSecret>(conn)
+        {
+            Ok(secret) => DatabaseResult::Succeful(secret),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
+        }
+    }
+
+    /// records that the code for `step` was used
+    ///
+    /// returns DatabaseResult::NotFound if that step or a later one was
+    /// already used, so two requests can't both use the same code
+    pub fn use_step(
+        conn: &mut PgConnection,
+        user_id: &str,
+        step: i64,
+    ) -> DatabaseResult<TotpSecret> {
+        use super::schema::totp_secrets::{
+            confirmed_at as ca, last_used_step as ls, user_id as ui,
+        };
+        match diesel::update(
+            totp_secrets::table
+                .filter(ui.eq(user_id))
+                .filter(ca.is_not_null())
+                .filter(ls.is_null().or(ls.lt(step))),
+        )
+        .set(ls.eq(step))
+        .get_result::<TotpSecret>(conn)
+        {
+            Ok(secret) => DatabaseResult::Succeful(secret),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
+        }
+    }
+
+    /// deletes a user's secret, turning 2FA off
+    pub fn delete(conn: &mut PgConnection, user_id: &str) -> DatabaseResult<TotpSecret> {
+        match diesel::delete(totp_secrets::table.find(user_id)).get_result::<TotpSecret>(conn) {
+            Ok(secret) => DatabaseResult::Succeful(secret),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while deleting data, Error message: {}",
+                err
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = totp_secrets)]
+pub struct NewTotpSecret {
+    user_id: String,
+    secret: Vec<u8>,
+    created_at: NaiveDateTime,
+}
+
+impl NewTotpSecret {
+    pub fn new(user_id: String, secret: Vec<u8>) -> NewTotpSecret {
+        NewTotpSecret {
+            user_id,
+            secret,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // make sure a test user with username "BerserkerMother" exist in database
+    use super::super::establish_connection;
+    use super::*;
+
+    #[test]
+    fn totp_secret_enroll() {
+        let mut conn = establish_connection();
+        TotpSecret::delete(&mut conn, "BerserkerMother");
+
+        let new_secret = NewTotpSecret::new("BerserkerMother".to_string(), vec![1; 20]);
+        TotpSecret::enroll(&mut conn, &new_secret).unwrap();
+        // enrolling again replaces the unconfirmed secret
+        let new_secret = NewTotpSecret::new("BerserkerMother".to_string(), vec![2; 20]);
+        let enrolled = TotpSecret::enroll(&mut conn, &new_secret).unwrap();
+
+        assert_eq!(enrolled.secret, vec![2; 20]);
+        assert!(matches!(
+            TotpSecret::get_confirmed(&mut conn, "BerserkerMother"),
+            DatabaseResult::NotFound
+        ));
+
+        TotpSecret::confirm(&mut conn, "BerserkerMother", 10).unwrap();
+        assert!(matches!(
+            TotpSecret::enroll(&mut conn, &new_secret),
+            DatabaseResult::AlreadyExists
+        ));
+
+        TotpSecret::delete(&mut conn, "BerserkerMother").unwrap();
+    }
+
+    #[test]
+    fn totp_secret_use_step() {
+        let mut conn = establish_connection();
+        TotpSecret::delete(&mut conn, "BerserkerMother");
+
+        let new_secret = NewTotpSecret::new("BerserkerMother".to_string(), vec![1; 20]);
+        TotpSecret::enroll(&mut conn, &new_secret).unwrap();
+        TotpSecret::confirm(&mut conn, "BerserkerMother", 10).unwrap();
+
+        assert!(matches!(
+            TotpSecret::use_step(&mut conn, "BerserkerMother", 10),
+            DatabaseResult::NotFound
+        ));
+        let used = TotpSecret::use_step(&mut conn, "BerserkerMother", 11).unwrap();
+        assert_eq!(used.last_used_step, Some(11));
+
+        TotpSecret::delete(&mut conn, "BerserkerMother").unwrap();
+    }
+}
@@ -1,31 +1,47 @@
 use super::schema::transaction;
 use super::*;
+use crate::encryption::keyring;
 use crate::models::result_variant::DatabaseResult;
 use chrono::offset::Local;
 use serde::{Deserialize, Serialize};
 
-#[derive(Queryable, Debug, PartialEq, Serialize, Deserialize)]
+/// A transaction, `title` and `notes` are encrypted at rest
+///
+/// each one is a journal entry moving its value between its bank account
+/// and a category, see `TransactionKind::category`
+#[derive(Queryable, Debug, PartialEq)]
 pub struct Transaction {
-    pub kind: bool,
+    pub kind: TransactionKind,
+    #[diesel(deserialize_as = Encrypted<String>)]
     pub title: String,
-    pub value: String,
-    pub currency: CurrencyType,
+    pub value: Amount,
+    pub currency: CurrencyCode,
     pub time: NaiveDate,
-    pub user_id: String,
+    /// who added it, None once they deleted their data
+    pub user_id: Option<String>,
     pub id: i32,
     pub bank_account: i32,
+    #[diesel(deserialize_as = Encrypted<Option<String>>)]
+    pub notes: Option<String>,
+    /// the transfer this is a leg of, see `Transfer`
+    pub transfer_id: Option<i32>,
+    /// the journal entry behind it
+    pub entry_id: i32,
 }
 
 impl Transaction {
+    #[cfg(test)]
+    #[allow(clippy::too_many_arguments)]
     fn new(
-        kind: bool,
+        kind: TransactionKind,
         title: String,
-        value: String,
-        currency: CurrencyType,
+        value: Amount,
+        currency: CurrencyCode,
         time: NaiveDate,
         user_id: String,
         id: i32,
         bank_account: i32,
+        entry_id: i32,
     ) -> Transaction {
         Transaction {
             kind,
@@ -33,19 +49,24 @@ impl Transaction {
             value,
             currency,
             time,
-            user_id,
+            user_id: Some(user_id),
             id,
             bank_account,
+            notes: None,
+            transfer_id: None,
+            entry_id,
         }
     }
 
-    /// adds a new transaction
+    /// adds a new transaction and books it on its account's balance
+    ///
+    /// the value is rounded to the currency's minor units, returns
+    /// DatabaseResult::NotFound for unknown currencies, values that round
+    /// to zero and currencies other than the account's
     pub fn add(conn: &mut PgConnection, trans: &NewTransaction) -> DatabaseResult<Transaction> {
-        match diesel::insert_into(transaction::table)
-            .values(trans)
-            .get_result::<Transaction>(conn)
-        {
+        match conn.transaction(|conn| Transaction::insert(conn, trans)) {
             Ok(trans) => DatabaseResult::Succeful(trans),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
             Err(err) => panic!("Something went wrong, Error message: {}", err),
         }
     }
@@ -69,72 +90,258 @@ impl Transaction {
             .filter(i.eq(id))
             .load::<Transaction>(conn)
         {
-            Ok(mut trans_vec) if !trans_vec.is_empty() => {
-                DatabaseResult::Succeful(trans_vec.pop().unwrap())
-            }
+            Ok(trans_vec) if trans_vec.is_empty() => DatabaseResult::NotFound,
+            Ok(mut trans_vec) => DatabaseResult::Succeful(trans_vec.pop().unwrap()),
             Err(err) => panic!("Something is wrong: Error message {}", err),
-            _ => panic!("Something is wrong, needs maintence!"),
         }
     }
 
-    /// deletes a transaction
+    /// deletes a transaction and takes it off its account's balance
+    ///
+    /// deleting a leg of a transfer deletes the whole transfer
     pub fn delete(conn: &mut PgConnection, id: i32) -> DatabaseResult<Transaction> {
         use super::schema::transaction::id as i;
-        match diesel::delete(transaction::table.filter(i.eq(id))).get_result::<Transaction>(conn) {
+        let deleted = conn.transaction::<_, Error, _>(|conn| {
+            let trans = transaction::table
+                .filter(i.eq(id))
+                .first::<Transaction>(conn)?;
+            match trans.transfer_id {
+                Some(transfer_id) => {
+                    Transfer::remove(conn, transfer_id)?;
+                }
+                None => {
+                    Transaction::remove(conn, &trans)?;
+                }
+            }
+            Ok(trans)
+        });
+        match deleted {
             Ok(trans) => DatabaseResult::Succeful(trans),
-            Err(Error::DatabaseError(_, _)) => DatabaseResult::NotFound,
+            Err(Error::NotFound) => DatabaseResult::NotFound,
             Err(err) => panic!("Something is wrong, Error message: {}", err),
         }
     }
 
-    /// deletes a user account all transaction
+    /// deletes a user account all transaction and takes them off its balance
     pub fn delete_all(
         conn: &mut PgConnection,
         account_id: i32,
     ) -> DatabaseResult<Vec<Transaction>> {
-        use super::schema::transaction::bank_account as ba;
-        match diesel::delete(transaction::table.filter(ba.eq(account_id)))
-            .get_results::<Transaction>(conn)
-        {
+        match conn.transaction(|conn| Transaction::remove_all(conn, account_id)) {
             Ok(trans_vec) => DatabaseResult::Succeful(trans_vec),
             Err(err) => panic!("Something is wrong, Error message: {}", err),
         }
     }
+
+    /// the value as it counts towards the balance, see `TransactionKind::signed`
+    pub fn signed_value(&self) -> Amount {
+        self.kind.signed(self.value.clone())
+    }
+
+    /// inserts a transaction and posts its entry against the category
+    /// of the bank account's owner, run inside a database transaction
+    ///
+    /// the value is rounded to its currency first, see `Transaction::add`
+    pub(super) fn insert(
+        conn: &mut PgConnection,
+        trans: &NewTransaction,
+    ) -> QueryResult<Transaction> {
+        use super::schema::account::{self, currency as c, id as i, user_id as ui};
+        use super::schema::transaction::entry_id;
+        let trans = &NewTransaction {
+            value: Transaction::rounded(conn, &trans.currency, &trans.value)?,
+            ..trans.clone()
+        };
+        let (owner, currency) = account::table
+            .filter(i.eq(trans.bank_account))
+            .select((ui, c))
+            .first::<(String, CurrencyCode)>(conn)?;
+        if currency != trans.currency {
+            return Err(Error::NotFound);
+        }
+        let (kind, name) = trans.kind.category();
+        let category = Account::category(conn, &owner, kind, name, &trans.currency)?;
+        let signed = trans.kind.signed(trans.value.clone());
+        let entry = NewJournalEntry {
+            user_id: trans.user_id.clone(),
+            description: None,
+            time: trans.time,
+            postings: entry_postings(trans.bank_account, category.id, signed, &trans.currency),
+        };
+        let (entry, _) = JournalEntry::post(conn, &entry)?;
+        diesel::insert_into(transaction::table)
+            .values((trans.clone(), entry_id.eq(entry.id)))
+            .get_result::<Transaction>(conn)
+    }
+
+    /// deletes every transaction on an account with their entries, run
+    /// inside a database transaction
+    ///
+    /// transfers go as a whole, with their legs on other accounts
+    pub(super) fn remove_all(
+        conn: &mut PgConnection,
+        account_id: i32,
+    ) -> QueryResult<Vec<Transaction>> {
+        use super::schema::transaction::bank_account as ba;
+        let trans_vec = transaction::table
+            .filter(ba.eq(account_id))
+            .load::<Transaction>(conn)?;
+        for trans in &trans_vec {
+            match trans.transfer_id {
+                // the other legs go too, an earlier leg may have taken this one already
+                Some(transfer_id) => match Transfer::remove(conn, transfer_id) {
+                    Ok(_) | Err(Error::NotFound) => (),
+                    Err(err) => return Err(err),
+                },
+                None => {
+                    Transaction::remove(conn, trans)?;
+                }
+            }
+        }
+        Ok(trans_vec)
+    }
+
+    /// deletes a transaction with its entry and takes it off the balances,
+    /// run inside a database transaction
+    pub(super) fn remove(conn: &mut PgConnection, trans: &Transaction) -> QueryResult<Transaction> {
+        use super::schema::transaction::id as i;
+        let trans = diesel::delete(transaction::table.filter(i.eq(trans.id)))
+            .get_result::<Transaction>(conn)?;
+        JournalEntry::remove(conn, trans.entry_id)?;
+        Ok(trans)
+    }
+
+    /// changes a transaction's value and reposts its entry, run inside
+    /// a database transaction
+    pub(super) fn revalue(
+        conn: &mut PgConnection,
+        trans: &Transaction,
+        value: Amount,
+    ) -> QueryResult<Transaction> {
+        use super::schema::postings::{self, account_id as ai, entry_id as ei};
+        use super::schema::transaction::{id as i, value as v};
+        let value = Transaction::rounded(conn, &trans.currency, &value)?;
+        let revalued = diesel::update(transaction::table.filter(i.eq(trans.id)))
+            .set(v.eq(value))
+            .get_result::<Transaction>(conn)?;
+        let category = postings::table
+            .filter(ei.eq(trans.entry_id))
+            .filter(ai.ne(trans.bank_account))
+            .select(ai)
+            .first::<i32>(conn)?;
+        let new_postings = entry_postings(
+            revalued.bank_account,
+            category,
+            revalued.signed_value(),
+            &revalued.currency,
+        );
+        JournalEntry::repost(conn, trans.entry_id, &new_postings)?;
+        Ok(revalued)
+    }
+
+    /// the value rounded to the currency's minor units, NotFound when
+    /// nothing is left to book
+    fn rounded(
+        conn: &mut PgConnection,
+        code: &CurrencyCode,
+        value: &Amount,
+    ) -> QueryResult<Amount> {
+        let value = Currency::find(conn, code)?.round(value);
+        match value == Amount::zero() {
+            true => Err(Error::NotFound),
+            false => Ok(value),
+        }
+    }
+
+    /// the transaction's value in its currency
+    pub fn money(&self) -> Money {
+        Money::new(self.value.clone(), self.currency.clone())
+    }
+
+    /// re-encrypts every title and note that isn't under the current key,
+    /// run after rotating ENCRYPTION_KEYS and before dropping the old key
+    ///
+    /// returns how many transactions were rewritten
+    pub fn reencrypt(conn: &mut PgConnection) -> DatabaseResult<usize> {
+        use super::schema::transaction::{id as i, notes as n, title as t};
+        let current = format!("{}%", keyring().current_prefix());
+        let reencrypted = conn.transaction::<_, Error, _>(|conn| {
+            let stale = transaction::table
+                .filter(t.not_like(&current).or(n.not_like(&current)))
+                .load::<Transaction>(conn)?;
+            for trans in &stale {
+                diesel::update(transaction::table.filter(i.eq(trans.id)))
+                    .set((
+                        t.eq(Encrypted(trans.title.clone())),
+                        n.eq(Encrypted(trans.notes.clone())),
+                    ))
+                    .execute(conn)?;
+            }
+            Ok(stale.len())
+        });
+        match reencrypted {
+            Ok(count) => DatabaseResult::Succeful(count),
+            Err(err) => panic!("Something is wrong, Error message: {}", err),
+        }
+    }
 }
 
 #[derive(Debug, Insertable, Clone)]
-#[table_name = "transaction"]
+#[diesel(table_name = transaction)]
 pub struct NewTransaction {
-    pub kind: bool,
+    pub kind: TransactionKind,
+    #[diesel(serialize_as = Encrypted<String>)]
     pub title: String,
-    pub value: String,
-    pub currency: CurrencyType,
+    pub value: Amount,
+    pub currency: CurrencyCode,
     pub time: NaiveDate,
     pub user_id: String,
     pub bank_account: i32,
+    #[diesel(serialize_as = Encrypted<Option<String>>)]
+    pub notes: Option<String>,
+    pub transfer_id: Option<i32>,
+}
+
+/// the postings of a transaction's entry, `signed` on the bank account
+/// and the other way round on the category
+fn entry_postings(
+    bank_account: i32,
+    category: i32,
+    signed: Amount,
+    currency: &CurrencyCode,
+) -> Vec<NewPosting> {
+    vec![
+        NewPosting::new(bank_account, signed.clone(), currency.clone()),
+        NewPosting::new(category, -signed, currency.clone()),
+    ]
 }
 
 use crate::api::transaction::TransactionData;
-impl From<TransactionData> for NewTransaction {
-    fn from(data: TransactionData) -> NewTransaction {
+impl NewTransaction {
+    /// creates a NewTransaction owned by user_id from request data
+    pub fn from_data(data: TransactionData, user_id: String) -> NewTransaction {
         let TransactionData {
             kind,
             title,
             value,
             currency,
-            user_id,
             bank_account,
+            notes,
         } = data;
-        NewTransaction::new(kind, title, value, currency, user_id, bank_account)
+        NewTransaction::new(kind, title, value, currency, user_id, bank_account).notes(notes)
+    }
+
+    /// sets the transaction's free form notes
+    pub fn notes(mut self, notes: Option<String>) -> NewTransaction {
+        self.notes = notes;
+        self
     }
-}
 
-impl<'a> NewTransaction {
     fn new(
-        kind: bool,
+        kind: TransactionKind,
         title: String,
-        value: String,
-        currency: CurrencyType,
+        value: Amount,
+        currency: CurrencyCode,
         user_id: String,
         bank_account: i32,
     ) -> NewTransaction {
@@ -148,6 +355,8 @@ impl<'a> NewTransaction {
             time,
             user_id,
             bank_account,
+            notes: None,
+            transfer_id: None,
         }
     }
 }
@@ -155,50 +364,106 @@ impl<'a> NewTransaction {
 impl Default for NewTransaction {
     fn default() -> NewTransaction {
         NewTransaction {
-            kind: true,
+            kind: TransactionKind::Income,
             title: "Huh".to_string(),
-            value: "344134000".to_string(),
-            currency: CurrencyType::USD,
+            value: "344134000".parse().unwrap(),
+            currency: "USD".parse().unwrap(),
             time: Local::today().naive_local(),
             user_id: "test_user".to_string(),
             bank_account: 1,
+            notes: None,
+            transfer_id: None,
         }
     }
 }
 
-#[derive(Debug, AsExpression, FromSqlRow, PartialEq, Deserialize, Serialize, Clone)]
-#[diesel(sql_type = crate::schema::sql_types::CurrencyType)]
-/// Enum representing currency_type for postgres database
-pub enum CurrencyType {
-    USD,
-    IRR,
-    CAD,
-    Euruo,
+/// What a transaction is, which decides how it moves the balance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Serialize, Deserialize)]
+#[diesel(sql_type = crate::schema::sql_types::TransactionKind)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionKind {
+    /// money coming in
+    Income,
+    /// money going out
+    Expense,
+    /// money moved between accounts, negative on the side it left
+    Transfer,
+    /// a correction, negative when it lowers the balance
+    Adjustment,
+    /// money coming back for an earlier expense
+    Refund,
+}
+
+impl TransactionKind {
+    /// whether the value carries its own sign, the other kinds only
+    /// take positive values
+    pub fn is_signed(self) -> bool {
+        matches!(
+            self,
+            TransactionKind::Transfer | TransactionKind::Adjustment
+        )
+    }
+
+    /// whether a transaction of this kind can have `value`
+    pub fn accepts(self, value: &Amount) -> bool {
+        match self.is_signed() {
+            true => *value != Amount::zero(),
+            false => value.is_positive(),
+        }
+    }
+
+    /// how much a value of this kind moves the balance
+    pub fn signed(self, value: Amount) -> Amount {
+        match self {
+            TransactionKind::Income | TransactionKind::Refund => value,
+            TransactionKind::Expense => -value,
+            TransactionKind::Transfer | TransactionKind::Adjustment => value,
+        }
+    }
+
+    /// kind and name of the account a transaction of this kind is booked
+    /// against, opened for the bank account's owner when first needed
+    ///
+    /// transfer legs meet in a clearing account that evens out once both
+    /// legs are in, up to the exchange rate
+    pub fn category(self) -> (AccountKind, &'static str) {
+        match self {
+            TransactionKind::Income => (AccountKind::Income, "Income"),
+            TransactionKind::Expense | TransactionKind::Refund => {
+                (AccountKind::Expense, "Expenses")
+            }
+            TransactionKind::Adjustment => (AccountKind::Equity, "Adjustments"),
+            TransactionKind::Transfer => (AccountKind::Equity, "Transfers"),
+        }
+    }
 }
 
-impl ToSql<crate::schema::sql_types::CurrencyType, Pg> for CurrencyType {
+impl ToSql<crate::schema::sql_types::TransactionKind, Pg> for TransactionKind {
     fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
         match *self {
-            CurrencyType::USD => out.write_all(b"USD")?,
-            CurrencyType::IRR => out.write_all(b"IRR")?,
-            CurrencyType::CAD => out.write_all(b"CAD")?,
-            CurrencyType::Euruo => out.write_all(b"Euruo")?,
+            TransactionKind::Income => out.write_all(b"income")?,
+            TransactionKind::Expense => out.write_all(b"expense")?,
+            TransactionKind::Transfer => out.write_all(b"transfer")?,
+            TransactionKind::Adjustment => out.write_all(b"adjustment")?,
+            TransactionKind::Refund => out.write_all(b"refund")?,
         }
         Ok(IsNull::No)
     }
 }
 
-impl FromSql<crate::schema::sql_types::CurrencyType, Pg> for CurrencyType {
+impl FromSql<crate::schema::sql_types::TransactionKind, Pg> for TransactionKind {
     fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
         match bytes.as_bytes() {
-            b"USD" => Ok(CurrencyType::USD),
-            b"IRR" => Ok(CurrencyType::IRR),
-            b"CAD" => Ok(CurrencyType::CAD),
-            b"Euruo" => Ok(CurrencyType::Euruo),
+            b"income" => Ok(TransactionKind::Income),
+            b"expense" => Ok(TransactionKind::Expense),
+            b"transfer" => Ok(TransactionKind::Transfer),
+            b"adjustment" => Ok(TransactionKind::Adjustment),
+            b"refund" => Ok(TransactionKind::Refund),
             _ => Err("Unrecognized enum variant".into()),
         }
     }
 }
+
 // make sure a test user with username "BerserkerMother" exist in database
 
 #[cfg(test)]
@@ -219,6 +484,7 @@ mod test {
             time,
             user_id,
             bank_account,
+            ..
         } = new_trans.clone();
 
         let query_result = Transaction::add(&mut conn, &new_trans).unwrap();
@@ -232,6 +498,7 @@ mod test {
             user_id,
             query_result.id,
             bank_account,
+            query_result.entry_id,
         );
 
         assert_eq!(query_result, should_match);
@@ -253,6 +520,7 @@ mod test {
             time,
             user_id,
             bank_account,
+            ..
         } = new_trans.clone();
 
         let query_result = Transaction::add(&mut conn, &new_trans).unwrap();
@@ -267,6 +535,7 @@ mod test {
             user_id,
             query_result.id,
             bank_account,
+            query_result.entry_id,
         );
 
         assert_eq!(should_match, query_result);
@@ -274,6 +543,99 @@ mod test {
         Transaction::delete(&mut conn, query_result.id);
     }
 
+    #[test]
+    fn transaction_encrypted() {
+        use crate::schema::transaction::{id as i, notes as n, title as t};
+        let mut conn = establish_connection();
+
+        let new_trans = NewTransaction::default().notes(Some("Dr. Smith".to_string()));
+        let added = Transaction::add(&mut conn, &new_trans).unwrap();
+        assert_eq!(added.title, new_trans.title);
+        assert_eq!(added.notes.as_deref(), Some("Dr. Smith"));
+
+        let (title, notes) = transaction::table
+            .filter(i.eq(added.id))
+            .select((t, n))
+            .first::<(String, Option<String>)>(&mut conn)
+            .unwrap();
+        assert!(keyring().is_current(&title));
+        assert!(!notes.unwrap().contains("Smith"));
+
+        // rows from before encryption was turned on get sealed
+        diesel::update(transaction::table.filter(i.eq(added.id)))
+            .set(t.eq("plain"))
+            .execute(&mut conn)
+            .unwrap();
+        assert_eq!(
+            Transaction::get(&mut conn, added.id).unwrap().title,
+            "plain"
+        );
+        assert!(Transaction::reencrypt(&mut conn).unwrap() >= 1);
+        let title = transaction::table
+            .filter(i.eq(added.id))
+            .select(t)
+            .first::<String>(&mut conn)
+            .unwrap();
+        assert!(keyring().is_current(&title));
+        assert_eq!(
+            Transaction::get(&mut conn, added.id).unwrap().title,
+            "plain"
+        );
+
+        Transaction::delete(&mut conn, added.id);
+    }
+
+    #[test]
+    fn transaction_balance() {
+        use crate::schema::account::{self, balance as b, id as i};
+        let mut conn = establish_connection();
+        let new_account = Account::new_account("Checking".to_string(), "test_user".to_string());
+        let acc = Account::add(&mut conn, &new_account).unwrap();
+        let balance = |conn: &mut PgConnection| Account::get(conn, acc.id).unwrap().balance;
+        let amount = |value: &str| value.parse::<Amount>().unwrap();
+
+        let salary = NewTransaction {
+            value: amount("100"),
+            bank_account: acc.id,
+            ..Default::default()
+        };
+        let rent = NewTransaction {
+            kind: TransactionKind::Expense,
+            value: amount("30.5"),
+            ..salary.clone()
+        };
+        Transaction::add(&mut conn, &salary).unwrap();
+        let rent = Transaction::add(&mut conn, &rent).unwrap();
+        assert_eq!(balance(&mut conn), amount("69.5"));
+
+        // the account is kept in USD only
+        let in_cad = NewTransaction {
+            currency: "CAD".parse().unwrap(),
+            ..salary.clone()
+        };
+        assert!(matches!(
+            Transaction::add(&mut conn, &in_cad),
+            DatabaseResult::NotFound
+        ));
+        assert_eq!(balance(&mut conn), amount("69.5"));
+
+        Transaction::delete(&mut conn, rent.id).unwrap();
+        assert_eq!(balance(&mut conn), amount("100"));
+
+        // a balance that drifted is rebuilt from the transactions
+        diesel::update(account::table.filter(i.eq(acc.id)))
+            .set(b.eq(amount("-1")))
+            .execute(&mut conn)
+            .unwrap();
+        Account::recompute_balances(&mut conn).unwrap();
+        assert_eq!(balance(&mut conn), amount("100"));
+
+        Transaction::delete_all(&mut conn, acc.id).unwrap();
+        assert_eq!(balance(&mut conn), Amount::zero());
+
+        Account::delete_by_id(&mut conn, acc.id);
+    }
+
     #[test]
     fn transaction_delete() {
         let mut conn = establish_connection();
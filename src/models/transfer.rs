@@ -0,0 +1,391 @@
+use super::schema::{transaction, transfers};
+use super::*;
+use chrono::{Local, NaiveDateTime, Utc};
+
+/// Money moved from one account to another
+///
+/// the money itself is in its legs, transactions of kind Transfer on both
+/// accounts and an Expense for the fee, all pointing back at it. They're
+/// added, changed and deleted together so the two sides can't drift apart
+#[derive(Queryable, Debug, PartialEq)]
+pub struct Transfer {
+    pub id: i32,
+    /// who made it, None once they deleted their data
+    pub user_id: Option<String>,
+    /// what one unit of the sent currency bought of the received one
+    pub rate: Rate,
+    /// charged on the sending account in the sent currency
+    pub fee: Amount,
+    pub created_at: NaiveDateTime,
+}
+
+/// The legs of a transfer, the fee leg is there when there's a fee
+#[derive(Debug, PartialEq)]
+pub struct TransferLegs {
+    pub sent: Transaction,
+    pub received: Transaction,
+    pub fee: Option<Transaction>,
+}
+
+impl TransferLegs {
+    /// every leg, sent first
+    pub fn into_vec(self) -> Vec<Transaction> {
+        let mut legs = vec![self.sent, self.received];
+        legs.extend(self.fee);
+        legs
+    }
+}
+
+impl Transfer {
+    /// moves money between two accounts, adding the legs and booking
+    /// them on both balances in one database transaction
+    ///
+    /// amounts are rounded to their currencies and have to be in their
+    /// accounts' ones, see `Transaction::add`. NotFound if the received
+    /// amount is too large to store
+    pub fn add(
+        conn: &mut PgConnection,
+        order: &TransferOrder,
+    ) -> DatabaseResult<(Transfer, TransferLegs)> {
+        use diesel::result::DatabaseErrorKind::ForeignKeyViolation;
+        let added = conn.transaction::<_, Error, _>(|conn| {
+            let received_value = order.amount.convert(&order.rate).ok_or(Error::NotFound)?;
+            let fee = Currency::find(conn, &order.currency)?.round(&order.fee);
+            let transfer = diesel::insert_into(transfers::table)
+                .values(&NewTransfer {
+                    user_id: order.user_id.clone(),
+                    rate: order.rate.clone(),
+                    fee: fee.clone(),
+                    created_at: Utc::now().naive_utc(),
+                })
+                .get_result::<Transfer>(conn)?;
+            let leg = |kind, value, currency, bank_account, title| NewTransaction {
+                kind,
+                title,
+                value,
+                currency,
+                time: Local::today().naive_local(),
+                user_id: order.user_id.clone(),
+                bank_account,
+                notes: None,
+                transfer_id: Some(transfer.id),
+            };
+            let sent = leg(
+                TransactionKind::Transfer,
+                -order.amount.clone(),
+                order.currency.clone(),
+                order.from_account,
+                order.title.clone(),
+            );
+            let received = leg(
+                TransactionKind::Transfer,
+                received_value,
+                order.to_currency.clone(),
+                order.to_account,
+                order.title.clone(),
+            );
+            let legs = TransferLegs {
+                sent: Transaction::insert(conn, &sent)?,
+                received: Transaction::insert(conn, &received)?,
+                fee: match fee.is_positive() {
+                    true => {
+                        let fee = leg(
+                            TransactionKind::Expense,
+                            fee,
+                            order.currency.clone(),
+                            order.from_account,
+                            format!("{} fee", order.title),
+                        );
+                        Some(Transaction::insert(conn, &fee)?)
+                    }
+                    false => None,
+                },
+            };
+            Ok((transfer, legs))
+        });
+        match added {
+            Ok(added) => DatabaseResult::Succeful(added),
+            Err(Error::NotFound | Error::DatabaseError(ForeignKeyViolation, _)) => {
+                DatabaseResult::NotFound
+            }
+            Err(err) => panic!(
+                "Something went wrong while inserting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets a transfer with its legs
+    pub fn get(conn: &mut PgConnection, id: i32) -> DatabaseResult<(Transfer, TransferLegs)> {
+        match Transfer::find(conn, id) {
+            Ok(found) => DatabaseResult::Succeful(found),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// gets every leg of a transfer, empty if there's no such transfer
+    pub fn legs(conn: &mut PgConnection, id: i32) -> DatabaseResult<Vec<Transaction>> {
+        use super::schema::transaction::{id as leg_id, transfer_id as ti};
+        match transaction::table
+            .filter(ti.eq(id))
+            .order(leg_id.asc())
+            .load::<Transaction>(conn)
+        {
+            Ok(legs) => DatabaseResult::Succeful(legs),
+            Err(err) => panic!("Something went wrong, Error message: {}", err),
+        }
+    }
+
+    /// changes what a transfer moved, the legs and both balances follow
+    ///
+    /// a fee leg is added or deleted as the fee stops or starts being zero,
+    /// NotFound if the received amount would be too large to store
+    pub fn update(
+        conn: &mut PgConnection,
+        id: i32,
+        changes: &TransferChanges,
+    ) -> DatabaseResult<(Transfer, TransferLegs)> {
+        use super::schema::transfers::{fee as f, id as i, rate as r};
+        let updated = conn.transaction::<_, Error, _>(|conn| {
+            let (transfer, legs) = Transfer::find(conn, id)?;
+            let amount = match &changes.amount {
+                Some(amount) => amount.clone(),
+                None => -legs.sent.value.clone(),
+            };
+            let rate = changes.rate.clone().unwrap_or(transfer.rate);
+            let received_value = amount.convert(&rate).ok_or(Error::NotFound)?;
+            let fee = match &changes.fee {
+                Some(fee) => Currency::find(conn, &legs.sent.currency)?.round(fee),
+                None => transfer.fee,
+            };
+            let transfer = diesel::update(transfers::table.filter(i.eq(id)))
+                .set((r.eq(&rate), f.eq(&fee)))
+                .get_result::<Transfer>(conn)?;
+
+            let fee_leg = match (legs.fee, fee.is_positive()) {
+                (Some(leg), true) => Some(Transaction::revalue(conn, &leg, fee.clone())?),
+                (Some(leg), false) => {
+                    Transaction::remove(conn, &leg)?;
+                    None
+                }
+                (None, true) => {
+                    let leg = NewTransaction {
+                        kind: TransactionKind::Expense,
+                        title: format!("{} fee", legs.sent.title),
+                        value: fee.clone(),
+                        currency: legs.sent.currency.clone(),
+                        time: legs.sent.time,
+                        user_id: transfer.user_id.clone().unwrap_or_default(),
+                        bank_account: legs.sent.bank_account,
+                        notes: None,
+                        transfer_id: Some(id),
+                    };
+                    Some(Transaction::insert(conn, &leg)?)
+                }
+                (None, false) => None,
+            };
+            let legs = TransferLegs {
+                sent: Transaction::revalue(conn, &legs.sent, -amount)?,
+                received: Transaction::revalue(conn, &legs.received, received_value)?,
+                fee: fee_leg,
+            };
+            Ok((transfer, legs))
+        });
+        match updated {
+            Ok(updated) => DatabaseResult::Succeful(updated),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
+        }
+    }
+
+    /// deletes a transfer with all its legs and takes them off both balances
+    pub fn delete(conn: &mut PgConnection, id: i32) -> DatabaseResult<Transfer> {
+        match conn.transaction(|conn| Transfer::remove(conn, id)) {
+            Ok(transfer) => DatabaseResult::Succeful(transfer),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while deleting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// deletes a transfer and its legs, run inside a database transaction
+    pub(super) fn remove(conn: &mut PgConnection, id: i32) -> QueryResult<Transfer> {
+        use super::schema::transaction::transfer_id as ti;
+        use super::schema::transfers::id as i;
+        let legs = transaction::table
+            .filter(ti.eq(id))
+            .load::<Transaction>(conn)?;
+        for leg in &legs {
+            Transaction::remove(conn, leg)?;
+        }
+        diesel::delete(transfers::table.filter(i.eq(id))).get_result::<Transfer>(conn)
+    }
+
+    /// loads a transfer and sorts out its legs
+    fn find(conn: &mut PgConnection, id: i32) -> QueryResult<(Transfer, TransferLegs)> {
+        use super::schema::transaction::{id as leg_id, transfer_id as ti};
+        let transfer = transfers::table.find(id).first::<Transfer>(conn)?;
+        let legs = transaction::table
+            .filter(ti.eq(id))
+            .order(leg_id.asc())
+            .load::<Transaction>(conn)?;
+        let mut sent = None;
+        let mut received = None;
+        let mut fee = None;
+        for leg in legs {
+            match leg.kind {
+                TransactionKind::Expense => fee = Some(leg),
+                _ if leg.value.is_negative() => sent = Some(leg),
+                _ => received = Some(leg),
+            }
+        }
+        // a side can be missing when its account went with a deleted user
+        match (sent, received) {
+            (Some(sent), Some(received)) => Ok((
+                transfer,
+                TransferLegs {
+                    sent,
+                    received,
+                    fee,
+                },
+            )),
+            _ => Err(Error::NotFound),
+        }
+    }
+}
+
+/// What to move with `Transfer::add`
+#[derive(Debug, Clone)]
+pub struct TransferOrder {
+    pub user_id: String,
+    pub from_account: i32,
+    pub to_account: i32,
+    /// what leaves `from_account`
+    pub amount: Amount,
+    pub currency: CurrencyCode,
+    /// what arrives on `to_account`, `amount` converted at `rate`
+    pub to_currency: CurrencyCode,
+    pub rate: Rate,
+    /// charged on `from_account` on top of `amount`
+    pub fee: Amount,
+    pub title: String,
+}
+
+/// What `Transfer::update` changes, None keeps it as it is
+#[derive(Debug, Default)]
+pub struct TransferChanges {
+    pub amount: Option<Amount>,
+    pub rate: Option<Rate>,
+    pub fee: Option<Amount>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = transfers)]
+struct NewTransfer {
+    user_id: String,
+    rate: Rate,
+    fee: Amount,
+    created_at: NaiveDateTime,
+}
+
+#[cfg(test)]
+mod test {
+    // make sure a test user with username "test_user" exist in database
+    use super::super::establish_connection;
+    use super::*;
+
+    fn amount(value: &str) -> Amount {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn transfer_legs() {
+        let mut conn = establish_connection();
+        let checking = Account::new_account("Checking".to_string(), "test_user".to_string());
+        let checking = Account::add(&mut conn, &checking).unwrap();
+        let savings = Account::new_account("Savings".to_string(), "test_user".to_string())
+            .currency("CAD".parse().unwrap());
+        let savings = Account::add(&mut conn, &savings).unwrap();
+        let balance = |conn: &mut PgConnection, id| Account::get(conn, id).unwrap().balance;
+
+        let order = TransferOrder {
+            user_id: "test_user".to_string(),
+            from_account: checking.id,
+            to_account: savings.id,
+            amount: amount("100"),
+            currency: "USD".parse().unwrap(),
+            to_currency: "CAD".parse().unwrap(),
+            rate: "1.35".parse().unwrap(),
+            fee: amount("2.5"),
+            title: "Savings".to_string(),
+        };
+        // savings are kept in CAD only
+        let in_usd = TransferOrder {
+            to_currency: "USD".parse().unwrap(),
+            rate: "1".parse().unwrap(),
+            ..order.clone()
+        };
+        assert!(matches!(
+            Transfer::add(&mut conn, &in_usd),
+            DatabaseResult::NotFound
+        ));
+        assert_eq!(balance(&mut conn, checking.id), Amount::zero());
+        // nor more than NUMERIC(19, 4) holds
+        let too_much = TransferOrder {
+            amount: amount("999999999999999"),
+            ..order.clone()
+        };
+        assert!(matches!(
+            Transfer::add(&mut conn, &too_much),
+            DatabaseResult::NotFound
+        ));
+
+        let (transfer, legs) = Transfer::add(&mut conn, &order).unwrap();
+        assert_eq!(legs.received.value, amount("135"));
+        assert_eq!(legs.fee.as_ref().unwrap().title, "Savings fee");
+        assert_eq!(balance(&mut conn, checking.id), amount("-102.5"));
+        assert_eq!(balance(&mut conn, savings.id), amount("135"));
+        let too_much = TransferChanges {
+            rate: Some("99999999999999".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Transfer::update(&mut conn, transfer.id, &too_much),
+            DatabaseResult::NotFound
+        ));
+        assert_eq!(balance(&mut conn, savings.id), amount("135"));
+
+        // both sides follow a change, the fee leg goes with the fee
+        let changes = TransferChanges {
+            amount: Some(amount("10")),
+            fee: Some(Amount::zero()),
+            ..Default::default()
+        };
+        let (_, legs) = Transfer::update(&mut conn, transfer.id, &changes).unwrap();
+        assert_eq!(legs.fee, None);
+        assert_eq!(balance(&mut conn, checking.id), amount("-10"));
+        assert_eq!(balance(&mut conn, savings.id), amount("13.5"));
+
+        // deleting one leg deletes the other
+        Transaction::delete(&mut conn, legs.received.id).unwrap();
+        assert!(matches!(
+            Transaction::get(&mut conn, legs.sent.id),
+            DatabaseResult::NotFound
+        ));
+        assert!(matches!(
+            Transfer::get(&mut conn, transfer.id),
+            DatabaseResult::NotFound
+        ));
+        assert_eq!(balance(&mut conn, checking.id), Amount::zero());
+        assert_eq!(balance(&mut conn, savings.id), Amount::zero());
+
+        Account::delete_by_id(&mut conn, checking.id);
+        Account::delete_by_id(&mut conn, savings.id);
+    }
+}
@@ -1,33 +1,55 @@
 use super::schema::users;
 use super::*;
+use chrono::NaiveDateTime;
 
-#[derive(Queryable, Debug, PartialEq, Serialize, Deserialize, AsChangeset)]
+/// a user row, never serialized as is, see `api::user::UserView`
+#[derive(Queryable, Debug, PartialEq)]
 #[diesel(table_name = users)]
-#[primary_key(username)]
+#[diesel(primary_key(username))]
 pub struct User {
     pub name: String,
     pub username: String,
     pub password: String,
-    pub api_token: String,
-    pub role: bool,
+    pub role: String,
+    /// where password reset tokens get mailed to
+    pub email: Option<String>,
+    /// the currency their reports can be converted to
+    pub base_currency: CurrencyCode,
+    /// tokens minted before this are refused, set when their password is reset
+    pub tokens_valid_after: Option<NaiveDateTime>,
 }
 
 // TODO: Update NewUser to match User!!!
 impl User {
     /// constructor for User
+    #[cfg(test)]
     fn new(username: &str, password: &str, name: &str) -> User {
         User {
             username: String::from(username),
             password: String::from(password),
             name: String::from(name),
-            api_token: String::from("f"),
-            role: false,
+            role: String::from("user"),
+            email: None,
+            base_currency: "USD".parse().unwrap(),
+            tokens_valid_after: None,
         }
     }
 
     /// creates a NewUser
-    pub fn new_user(username: String, password: String, name: String) -> NewUser {
-        NewUser::new(name, username, password)
+    pub fn new_user(
+        username: String,
+        password: String,
+        name: String,
+        email: Option<String>,
+    ) -> NewUser {
+        NewUser::new(name, username, password).email(email)
+    }
+
+    /// whether something minted for them at `issued_at`, like a session
+    /// or a login challenge, still counts
+    pub fn tokens_valid(&self, issued_at: NaiveDateTime) -> bool {
+        self.tokens_valid_after
+            .is_none_or(|valid_after| issued_at > valid_after)
     }
 
     /// gets a user from database with id
@@ -52,6 +74,19 @@ impl User {
         }
     }
 
+    /// gets a user by their email address
+    pub fn get_by_email(conn: &mut PgConnection, email: &str) -> DatabaseResult<User> {
+        use super::schema::users::email as e;
+        match users::table.filter(e.eq(email)).first::<User>(conn) {
+            Ok(user) => DatabaseResult::Succeful(user),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while getting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
     /// gets all users
     pub fn all(conn: &mut PgConnection) -> DatabaseResult<Vec<User>> {
         let user_vec = users::table.load::<User>(conn);
@@ -61,36 +96,19 @@ impl User {
         }
     }
 
-    /// gets a user by its bearer token
-    pub fn get_by_token(conn: &mut PgConnection, token: &str) -> DatabaseResult<User> {
-        use super::schema::users::api_token as ap;
-        let user_vec = users::table.filter(ap.eq(token)).load::<User>(conn);
-        match user_vec {
-            Ok(mut user_vec) => {
-                if user_vec.is_empty() {
-                    DatabaseResult::NotFound
-                } else {
-                    DatabaseResult::Succeful(user_vec.pop().unwrap())
-                }
-            }
-            Err(err) => panic!(
-                "Something went wrong while getting data, Error message: {}",
-                err
-            ),
-        }
-    }
-
-    /// refreshes Bearer token
-    pub fn refresh_bearer(conn: &mut PgConnection, username: &str) -> DatabaseResult<User> {
-        use super::schema::users::{api_token as at, username as un};
-        use crate::authentication::random_token;
-        let new_token = random_token();
+    /// replaces a user's stored password hash
+    pub fn set_password(
+        conn: &mut PgConnection,
+        username: &str,
+        password_hash: &str,
+    ) -> DatabaseResult<User> {
+        use super::schema::users::{password as p, username as un};
         match diesel::update(users::table.filter(un.eq(username)))
-            .set(at.eq(new_token))
+            .set(p.eq(password_hash))
             .get_result::<User>(conn)
         {
             Ok(user) => DatabaseResult::Succeful(user),
-            Err(Error::DatabaseError(_, _)) => DatabaseResult::NotFound,
+            Err(Error::NotFound) => DatabaseResult::NotFound,
             Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
         }
     }
@@ -130,16 +148,124 @@ impl User {
         }
     }
 
-    /// update a user account
-    pub fn update(conn: &mut PgConnection, user: &User) -> DatabaseResult<User> {
+    /// deletes a user along with everything that's only theirs, in one
+    /// database transaction, and returns them
+    ///
+    /// accounts nobody else is a member of are deleted with their
+    /// transactions. Shared accounts stay with their other members, the
+    /// earliest of them becomes owner if the user was the only one, and
+    /// the user's transactions on them are anonymized. The entries of a
+    /// handed over account move to the new owner's categories, those that
+    /// also post to the user's deleted accounts go with them
+    pub fn close(conn: &mut PgConnection, username: &str) -> DatabaseResult<User> {
+        use super::schema::account::{id as i, user_id as opener};
+        use super::schema::account_members::{
+            account_id as ai, added_at as aa, role as r, user_id as ui,
+        };
+        use super::schema::{account, account_members};
+
+        let closed = conn.transaction::<_, Error, _>(|conn| {
+            // accounts they opened but left are handed over too
+            let mut account_ids = account_members::table
+                .filter(ui.eq(username))
+                .select(ai)
+                .load::<i32>(conn)?;
+            account_ids.extend(
+                account::table
+                    .filter(opener.eq(username))
+                    .select(i)
+                    .load::<i32>(conn)?,
+            );
+            account_ids.sort_unstable();
+            account_ids.dedup();
+            let mut handed_over = Vec::new();
+            let mut orphaned = Vec::new();
+            for account_id in account_ids {
+                let others = account_members::table
+                    .filter(ai.eq(account_id))
+                    .filter(ui.ne(username))
+                    .order(aa.asc())
+                    .load::<AccountMember>(conn)?;
+                let heir = match others.iter().find(|m| m.role == MemberRole::Owner) {
+                    Some(owner) => owner,
+                    None => match others.first() {
+                        Some(member) => member,
+                        None => {
+                            orphaned.push(account_id);
+                            continue;
+                        }
+                    },
+                };
+                diesel::update(account_members::table.find((account_id, &heir.user_id)))
+                    .set(r.eq(MemberRole::Owner))
+                    .execute(conn)?;
+                let reopened = diesel::update(
+                    account::table
+                        .filter(i.eq(account_id))
+                        .filter(opener.eq(username)),
+                )
+                .set(opener.eq(&heir.user_id))
+                .execute(conn)?;
+                if reopened > 0 {
+                    handed_over.push((account_id, heir.user_id.clone()));
+                }
+            }
+            // entries are moved off their categories before those go
+            for (account_id, heir) in &handed_over {
+                JournalEntry::hand_over(conn, *account_id, username, heir)?;
+            }
+            // bank accounts before categories, so their transactions go whole
+            let kinds = account::table
+                .filter(i.eq_any(&orphaned))
+                .select((i, account::kind))
+                .load::<(i32, AccountKind)>(conn)?;
+            orphaned.sort_by_key(|id| {
+                kinds
+                    .iter()
+                    .any(|(account_id, kind)| account_id == id && kind.is_category())
+            });
+            for account_id in orphaned {
+                Account::purge(conn, account_id)?;
+            }
+            // memberships cascade and transactions are anonymized by their foreign keys
+            diesel::delete(users::table.find(username)).get_result::<User>(conn)
+        });
+        match closed {
+            Ok(user) => DatabaseResult::Succeful(user),
+            Err(Error::NotFound) => DatabaseResult::NotFound,
+            Err(err) => panic!(
+                "Something went wrong while deleting data, Error message: {}",
+                err
+            ),
+        }
+    }
+
+    /// updates the columns `changes` sets and leaves the others as they
+    /// are, so changes made to them meanwhile aren't written over
+    ///
+    /// returns DatabaseResult::NotFound if there's no such user, role or
+    /// base currency, DatabaseResult::AlreadyExists if the email is taken
+    pub fn update(
+        conn: &mut PgConnection,
+        username: &str,
+        changes: &UserChanges,
+    ) -> DatabaseResult<User> {
         use super::schema::users::username as un;
-        match diesel::update(users::table.filter(un.eq(&user.username)))
-            .set(user)
+        use diesel::result::DatabaseErrorKind::{ForeignKeyViolation, UniqueViolation};
+        // diesel refuses an update without columns
+        if *changes == UserChanges::default() {
+            return User::get(conn, username);
+        }
+        match diesel::update(users::table.filter(un.eq(username)))
+            .set(changes)
             .get_result::<User>(conn)
         {
             Ok(user) => DatabaseResult::Succeful(user),
-            Err(Error::DatabaseError(_, _)) => DatabaseResult::NotFound,
-            _ => panic!("Something went wrong"),
+            Err(Error::NotFound | Error::DatabaseError(ForeignKeyViolation, _)) => {
+                DatabaseResult::NotFound
+            }
+            Err(Error::DatabaseError(UniqueViolation, _)) => DatabaseResult::AlreadyExists,
+            Err(err) => panic!("Something went terribly wrong, Error message: {}", err),
         }
     }
 }
@@ -150,20 +276,37 @@ pub struct NewUser {
     name: String,
     username: String,
     password: String,
-    api_token: String,
+    email: Option<String>,
 }
 
 impl NewUser {
     pub fn new(name: String, username: String, password: String) -> NewUser {
-        use crate::authentication::random_token;
-        let api_token = random_token();
         NewUser {
             name,
             username,
             password,
-            api_token,
+            email: None,
         }
     }
+
+    /// sets the address password reset tokens get mailed to
+    pub fn email(mut self, email: Option<String>) -> NewUser {
+        self.email = email;
+        self
+    }
+}
+
+/// What `User::update` changes, None keeps it as it is
+#[derive(Debug, Default, PartialEq, AsChangeset)]
+#[diesel(table_name = users)]
+pub struct UserChanges {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    /// the new password's hash
+    pub password: Option<String>,
+    pub base_currency: Option<CurrencyCode>,
+    /// has to be one of the roles table, only admins change it
+    pub role: Option<String>,
 }
 
 use super::super::api::user::UserData;
@@ -174,18 +317,19 @@ impl From<UserData> for NewUser {
             name,
             username,
             password,
+            email,
         } = user;
-        User::new_user(username, password.hash(), name)
+        User::new_user(username, password.hash(), name, email)
     }
 }
 
-impl<'a> Default for NewUser {
+impl Default for NewUser {
     fn default() -> NewUser {
         NewUser {
             name: String::from("Kimia"),
             username: String::from("absolute_trash"),
             password: String::from("huh"),
-            api_token: "f".to_string(),
+            email: None,
         }
     }
 }
@@ -207,7 +351,7 @@ mod test {
         } = &new_user;
 
         // makes sure the user doesn't exits
-        User::delete_by_username(&mut conn, &username);
+        User::delete_by_username(&mut conn, username);
 
         let query_result = User::add(&mut conn, &new_user).unwrap();
 
@@ -216,7 +360,7 @@ mod test {
         assert_eq!(query_result, should_match);
 
         // cleans up inserted row
-        User::delete_by_username(&mut conn, &username);
+        User::delete_by_username(&mut conn, username);
     }
 
     #[test]
@@ -254,24 +398,128 @@ mod test {
         // cleans up the added user
         User::delete_by_username(&mut conn, &new_user.username);
     }
+    #[test]
+    fn user_close() {
+        // make sure a test user with username "test_user" exist in database
+        let mut conn = establish_connection();
+        User::close(&mut conn, "closing_user");
+        let new_user = NewUser::new(
+            "Closing".to_string(),
+            "closing_user".to_string(),
+            "hash".to_string(),
+        );
+        User::add(&mut conn, &new_user).unwrap();
+
+        let solo = Account::new_account("Solo".to_string(), "closing_user".to_string());
+        let solo = Account::add(&mut conn, &solo).unwrap();
+        let shared = Account::new_account("Shared".to_string(), "closing_user".to_string());
+        let shared = Account::add(&mut conn, &shared).unwrap();
+        let member = NewAccountMember::new(shared.id, "test_user".to_string(), MemberRole::Viewer);
+        AccountMember::add(&mut conn, &member).unwrap();
+        let trans = NewTransaction {
+            user_id: "closing_user".to_string(),
+            bank_account: shared.id,
+            ..Default::default()
+        };
+        let trans = Transaction::add(&mut conn, &trans).unwrap();
+        let usd: CurrencyCode = "USD".parse().unwrap();
+        let moved = NewJournalEntry {
+            user_id: "closing_user".to_string(),
+            description: None,
+            time: trans.time,
+            postings: vec![
+                NewPosting::new(solo.id, "-50".parse().unwrap(), usd.clone()),
+                NewPosting::new(shared.id, "50".parse().unwrap(), usd),
+            ],
+        };
+        let (moved, _) = JournalEntry::add(&mut conn, &moved).unwrap();
+
+        User::close(&mut conn, "closing_user").unwrap();
+
+        assert!(matches!(
+            Account::get(&mut conn, solo.id),
+            DatabaseResult::NotFound
+        ));
+        assert_eq!(
+            Account::get(&mut conn, shared.id).unwrap().user_id,
+            "test_user"
+        );
+        assert_eq!(
+            AccountMember::role(&mut conn, shared.id, "test_user").unwrap(),
+            MemberRole::Owner
+        );
+        assert_eq!(Transaction::get(&mut conn, trans.id).unwrap().user_id, None);
+        // its entry now posts against the new owner's income
+        let (_, posting_vec) = JournalEntry::get(&mut conn, trans.entry_id).unwrap();
+        let category = Account::get(&mut conn, posting_vec[1].account_id).unwrap();
+        assert_eq!(
+            (category.user_id.as_str(), category.kind),
+            ("test_user", AccountKind::Income)
+        );
+        // money moved from their own accounts goes with those
+        assert!(matches!(
+            JournalEntry::get(&mut conn, moved.id),
+            DatabaseResult::NotFound
+        ));
+        assert_eq!(
+            Account::get(&mut conn, shared.id).unwrap().balance,
+            trans.value
+        );
+        let heirs = Account::all(&mut conn, "test_user".to_string()).unwrap();
+        assert!(heirs.iter().all(|acc| acc.name != "Solo"));
+
+        // cleans up the shared account
+        Transaction::delete_all(&mut conn, shared.id);
+        Account::delete_by_id(&mut conn, shared.id);
+    }
+
     #[test]
     fn user_update() {
         let mut conn = establish_connection();
 
         let new_user = NewUser::default();
         User::delete_by_username(&mut conn, &new_user.username);
-        if let DatabaseResult::Succeful(mut user) = User::add(&mut conn, &new_user) {
-            user.name = String::from("Changed");
-            println!("{:?}", user);
-            let updated = match User::update(&mut conn, &user) {
-                DatabaseResult::Succeful(user) => user,
-                _ => panic!("WTF"),
-            };
-        }
-        let query_result = User::get(&mut conn, &new_user.username).unwrap().name;
+        User::add(&mut conn, &new_user).unwrap();
+        // changed after the caller loaded the user, like by a password reset
+        User::set_password(&mut conn, &new_user.username, "reset");
+
+        let changes = UserChanges {
+            name: Some(String::from("Changed")),
+            ..Default::default()
+        };
+        let updated = User::update(&mut conn, &new_user.username, &changes).unwrap();
+        assert_eq!(updated.name, "Changed");
+        assert_eq!(updated.password, "reset");
+
+        let unknown = UserChanges {
+            base_currency: Some("NOSUCH".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            User::update(&mut conn, &new_user.username, &unknown),
+            DatabaseResult::NotFound
+        ));
+        assert_eq!(
+            User::update(&mut conn, &new_user.username, &UserChanges::default()).unwrap(),
+            updated
+        );
+
+        // roles have to exist
+        let promoted = UserChanges {
+            role: Some(String::from("support")),
+            ..Default::default()
+        };
+        let updated = User::update(&mut conn, &new_user.username, &promoted).unwrap();
+        assert_eq!(updated.role, "support");
+        let unknown = UserChanges {
+            role: Some(String::from("nosuch")),
+            ..Default::default()
+        };
+        assert!(matches!(
+            User::update(&mut conn, &new_user.username, &unknown),
+            DatabaseResult::NotFound
+        ));
 
-        let should_match = String::from("Changed");
-
-        assert_eq!(should_match, query_result);
+        User::delete_by_username(&mut conn, &new_user.username);
     }
 }
@@ -0,0 +1,76 @@
+use super::schema::{account, account_members, transaction};
+use super::*;
+use crate::api::account::AccountView;
+use crate::api::transaction::TransactionView;
+use chrono::{NaiveDateTime, Utc};
+
+/// Everything stored about a user, handed out before their data is deleted
+#[derive(Debug, Serialize)]
+pub struct UserExport {
+    pub exported_at: NaiveDateTime,
+    pub name: String,
+    pub username: String,
+    pub email: Option<String>,
+    pub role: String,
+    pub base_currency: CurrencyCode,
+    pub accounts: Vec<AccountExport>,
+}
+
+/// A bank account the user is a member of, with all its transactions
+#[derive(Debug, Serialize)]
+pub struct AccountExport {
+    pub account: AccountView,
+    pub member_role: MemberRole,
+    pub transactions: Vec<TransactionView>,
+}
+
+impl UserExport {
+    /// collects a user's profile and the accounts they're a member of
+    pub fn of(conn: &mut PgConnection, username: &str) -> DatabaseResult<UserExport> {
+        use super::schema::account_members::{role as r, user_id as ui};
+        use super::schema::transaction::{bank_account as ba, id as ti};
+        let user = match User::get(conn, username) {
+            DatabaseResult::Succeful(user) => user,
+            _ => return DatabaseResult::NotFound,
+        };
+        let memberships = match account::table
+            .inner_join(account_members::table)
+            .filter(ui.eq(username))
+            .select((account::all_columns, r))
+            .order(account::id.asc())
+            .load::<(Account, MemberRole)>(conn)
+        {
+            Ok(memberships) => memberships,
+            Err(err) => panic!("Something is Wrong: Error message: {}", err),
+        };
+
+        let mut accounts = Vec::new();
+        for (account, member_role) in memberships {
+            let transactions = match transaction::table
+                .filter(ba.eq(account.id))
+                .order(ti.asc())
+                .load::<Transaction>(conn)
+            {
+                Ok(trans_vec) => trans_vec,
+                Err(err) => panic!("Something is Wrong: Error message: {}", err),
+            };
+            accounts.push(AccountExport {
+                account: account.into(),
+                member_role,
+                transactions: transactions
+                    .into_iter()
+                    .map(TransactionView::from)
+                    .collect(),
+            });
+        }
+        DatabaseResult::Succeful(UserExport {
+            exported_at: Utc::now().naive_utc(),
+            name: user.name,
+            username: user.username,
+            email: user.email,
+            role: user.role,
+            base_currency: user.base_currency,
+            accounts,
+        })
+    }
+}
@@ -0,0 +1,165 @@
+use super::ImportError;
+use crate::models::{CurrencyCode, NewExchangeRate, Rate};
+use chrono::NaiveDate;
+
+/// parses the ECB's euro foreign exchange reference rates, every rate is
+/// what one EUR bought
+///
+/// takes the CSV files, where the daily one writes dates like
+/// `17 November 2022` and the historical one `2022-11-17` with `N/A`
+/// for currencies that weren't quoted yet, and the XML files with
+/// `<Cube time='...'>` days holding `<Cube currency='...' rate='...'/>`
+pub fn parse(contents: &str) -> Result<Vec<NewExchangeRate>, ImportError> {
+    let rates = match contents.trim_start().starts_with('<') {
+        true => parse_xml(contents)?,
+        false => parse_csv(contents)?,
+    };
+    match rates.is_empty() {
+        true => Err(malformed("no rates in the file")),
+        false => Ok(rates),
+    }
+}
+
+fn parse_csv(contents: &str) -> Result<Vec<NewExchangeRate>, ImportError> {
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header = lines.next().unwrap_or_default();
+    let columns = header.split(',').map(str::trim).collect::<Vec<_>>();
+    if columns.first() != Some(&"Date") {
+        return Err(malformed("expected a Date column first"));
+    }
+    let mut rates = Vec::new();
+    for line in lines {
+        let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+        let date = parse_date(fields[0])?;
+        for (currency, rate) in columns.iter().zip(&fields).skip(1) {
+            // rows end in a comma, and the history has gaps
+            if currency.is_empty() || rate.is_empty() || *rate == "N/A" {
+                continue;
+            }
+            rates.push(new_rate(date, currency, rate)?);
+        }
+    }
+    Ok(rates)
+}
+
+fn parse_xml(contents: &str) -> Result<Vec<NewExchangeRate>, ImportError> {
+    let mut rates = Vec::new();
+    let mut date = None;
+    for tag in contents.split("<Cube").skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        if let Some(time) = attribute(tag, "time") {
+            date = Some(parse_date(time)?);
+        }
+        if let (Some(currency), Some(rate)) = (attribute(tag, "currency"), attribute(tag, "rate")) {
+            let date = date.ok_or_else(|| malformed("a rate outside of a day"))?;
+            rates.push(new_rate(date, currency, rate)?);
+        }
+    }
+    Ok(rates)
+}
+
+/// the value of an attribute like `rate='1.0377'` in a tag
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    tag.split_whitespace().find_map(|token| {
+        let value = token.strip_prefix(name)?.strip_prefix('=')?;
+        Some(
+            value
+                .trim_end_matches('/')
+                .trim_matches(|c| c == '\'' || c == '"'),
+        )
+    })
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, ImportError> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%d %B %Y"))
+        .map_err(|_| malformed(&format!("{:?} isn't a date", date)))
+}
+
+fn new_rate(date: NaiveDate, currency: &str, rate: &str) -> Result<NewExchangeRate, ImportError> {
+    let quote = currency
+        .parse::<CurrencyCode>()
+        .map_err(|err| malformed(&format!("{:?}: {}", currency, err)))?;
+    let rate = rate
+        .parse::<Rate>()
+        .map_err(|err| malformed(&format!("{} rate {:?}: {}", currency, rate, err)))?;
+    Ok(NewExchangeRate {
+        date,
+        base: "EUR".parse().unwrap(),
+        quote,
+        rate,
+    })
+}
+
+fn malformed(reason: &str) -> ImportError {
+    ImportError::Malformed(reason.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn summary(rates: &[NewExchangeRate]) -> Vec<String> {
+        rates
+            .iter()
+            .map(|r| format!("{} {}/{} {}", r.date, r.base, r.quote, r.rate))
+            .collect()
+    }
+
+    #[test]
+    fn ecb_formats() {
+        let daily = "Date, USD, JPY, \n17 November 2022, 1.0377, 145.12, \n";
+        assert_eq!(
+            summary(&parse(daily).unwrap()),
+            ["2022-11-17 EUR/USD 1.0377", "2022-11-17 EUR/JPY 145.12"]
+        );
+
+        let history = "Date,USD,CYP,\n2022-11-17,1.0377,N/A,\n2007-12-31,1.4721,0.585274,\n";
+        assert_eq!(
+            summary(&parse(history).unwrap()),
+            [
+                "2022-11-17 EUR/USD 1.0377",
+                "2007-12-31 EUR/USD 1.4721",
+                "2007-12-31 EUR/CYP 0.585274"
+            ]
+        );
+
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01">
+	<gesmes:subject>Reference rates</gesmes:subject>
+	<Cube>
+		<Cube time='2022-11-17'>
+			<Cube currency='USD' rate='1.0377'/>
+			<Cube currency='JPY' rate='145.12'/>
+		</Cube>
+		<Cube time="2022-11-16">
+			<Cube currency="USD" rate="1.0412"/>
+		</Cube>
+	</Cube>
+</gesmes:Envelope>"#;
+        assert_eq!(
+            summary(&parse(xml).unwrap()),
+            [
+                "2022-11-17 EUR/USD 1.0377",
+                "2022-11-17 EUR/JPY 145.12",
+                "2022-11-16 EUR/USD 1.0412"
+            ]
+        );
+
+        for invalid in [
+            "",
+            "USD,JPY\n1.0377,145.12\n",
+            "Date,USD\nyesterday,1.0377\n",
+            "Date,USD\n2022-11-17,-1\n",
+            "Date,usd\n2022-11-17,1.0377\n",
+            "<Cube currency='USD' rate='1.0377'/>",
+            "<Cube></Cube>",
+        ] {
+            assert!(
+                matches!(parse(invalid), Err(ImportError::Malformed(_))),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
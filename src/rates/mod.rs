@@ -0,0 +1,46 @@
+mod ecb;
+pub use ecb::parse as parse_ecb;
+
+use crate::models::result_variant::DatabaseResult;
+use crate::models::{Currency, CurrencyCode, ExchangeRate};
+use diesel::PgConnection;
+use std::path::Path;
+use std::{env, fs};
+
+#[derive(Debug)]
+pub enum ImportError {
+    /// ECB_RATES_FILE isn't set
+    NotConfigured,
+    /// the file couldn't be read
+    Io(std::io::Error),
+    /// the file isn't one the ECB publishes, says what's wrong with it
+    Malformed(String),
+}
+
+/// imports the ECB's euro reference rates from a file on disk, the daily
+/// or the historical one, as CSV or XML
+///
+/// rates of currencies that aren't in the registry are left out, returns
+/// how many rates were stored
+pub fn import_file(conn: &mut PgConnection, path: &Path) -> Result<usize, ImportError> {
+    let contents = fs::read_to_string(path).map_err(ImportError::Io)?;
+    let mut rates = ecb::parse(&contents)?;
+    let known = match Currency::all(conn) {
+        DatabaseResult::Succeful(currency_vec) => currency_vec,
+        _ => Vec::new(),
+    };
+    let is_known = |code: &CurrencyCode| known.iter().any(|currency| currency.code == *code);
+    rates.retain(|rate| is_known(&rate.base) && is_known(&rate.quote));
+    match ExchangeRate::add_all(conn, &rates) {
+        DatabaseResult::Succeful(count) => Ok(count),
+        _ => Err(ImportError::Malformed(
+            "rates refer to unknown currencies".to_string(),
+        )),
+    }
+}
+
+/// imports the file ECB_RATES_FILE points at, see `import_file`
+pub fn import_configured(conn: &mut PgConnection) -> Result<usize, ImportError> {
+    let path = env::var("ECB_RATES_FILE").map_err(|_| ImportError::NotConfigured)?;
+    import_file(conn, Path::new(&path))
+}
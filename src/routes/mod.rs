@@ -1,11 +1,17 @@
-use crate::authentication::hasher::Hash;
-use crate::establish_connection;
+pub mod password_reset;
+
+use crate::audit::{self, Action};
+use crate::authentication::hasher::{self, Hash, Verification};
+use crate::authentication::{cookie, gaurd, throttle, totp, ClientInfo, TokenIssuer};
 use crate::models::result_variant::DatabaseResult;
-use crate::models::User;
+use crate::models::{NewSession, RefreshToken, Session, TotpSecret, User};
 use crate::DbConn;
+use chrono::NaiveDateTime;
+use rocket::http::{CookieJar, Header, Status};
 use rocket::response::Redirect;
 use rocket::serde::json::Json;
-use serde::Deserialize;
+use rocket::State;
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
 pub struct Credential {
@@ -13,27 +19,332 @@ pub struct Credential {
     password: String,
 }
 
+/// Second login step, a TOTP or recovery code for a challenge
+#[derive(Deserialize)]
+pub struct TwoFactorCredential {
+    challenge: String,
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    refresh_token: String,
+}
+
+/// Tokens handed out on login and refresh
+///
+/// the access token goes in `Authorization: Bearer` until it expires,
+/// then the refresh token trades in for a new pair
+#[derive(Serialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_at: NaiveDateTime,
+    pub refresh_token: String,
+    pub session_id: i32,
+}
+
+impl TokenPair {
+    fn new(
+        issuer: &TokenIssuer,
+        user: &User,
+        session: &Session,
+        refresh_token: String,
+    ) -> TokenPair {
+        let (access_token, expires_at) =
+            issuer.issue(&user.username, &user.role, session.id, session.two_factor);
+        TokenPair {
+            access_token,
+            token_type: "Bearer",
+            expires_at,
+            refresh_token,
+            session_id: session.id,
+        }
+    }
+}
+
+/// Handed out instead of tokens when the user has 2FA on, traded in
+/// with a TOTP or recovery code at /login/totp before it expires
+#[derive(Serialize)]
+pub struct TwoFactorChallenge {
+    pub challenge: String,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Handed out instead of tokens when logging in with `?cookie=true`,
+/// the session then lives in an encrypted HttpOnly cookie
+///
+/// state changing requests have to send `csrf_token` in X-CSRF-Token,
+/// it's also readable from the csrf_token cookie
+#[derive(Serialize)]
+pub struct CookieSession {
+    pub session_id: i32,
+    pub expires_at: NaiveDateTime,
+    pub csrf_token: String,
+}
+
+#[derive(Responder)]
+pub enum LoginResponse {
+    Tokens(Json<TokenPair>),
+    Cookie(Json<CookieSession>),
+    #[response(status = 202)]
+    TwoFactorRequired(Json<TwoFactorChallenge>),
+}
+
+/// Why a login was refused
+#[derive(Responder, Debug)]
+pub enum LoginError {
+    #[response(status = 401)]
+    InvalidCredentials(&'static str),
+    /// too many failed attempts, carries a Retry-After header
+    #[response(status = 429)]
+    TooManyAttempts(&'static str, Header<'static>),
+    #[response(status = 503)]
+    Unavailable(&'static str),
+}
+
+impl LoginError {
+    fn invalid() -> LoginError {
+        LoginError::InvalidCredentials("invalid username or password")
+    }
+
+    fn retry_after(seconds: i64) -> LoginError {
+        LoginError::TooManyAttempts(
+            "too many failed login attempts, try again later",
+            Header::new("Retry-After", seconds.to_string()),
+        )
+    }
+}
+
 #[get("/")]
 pub fn to_loging() -> Redirect {
-    Redirect::to(uri!(login))
+    Redirect::to(uri!(login(_)))
 }
 
-#[get("/login", format = "application/json", data = "<credential>")]
-pub fn login(credential: Json<Credential>, mut conn: DbConn) -> Option<Json<User>> {
+/// checks a password and starts a new session
+///
+/// `?cookie=true` starts a cookie session for browsers instead of
+/// handing out tokens. Users with 2FA on get a challenge instead, see `login_totp`.
+/// repeated failures for a username or from an address have to wait
+/// longer and longer before the next try and eventually get locked out
+#[post("/login?<cookie>", format = "application/json", data = "<credential>")]
+pub fn login(
+    credential: Json<Credential>,
+    cookie: Option<bool>,
+    cookies: &CookieJar<'_>,
+    client: ClientInfo,
+    issuer: &State<TokenIssuer>,
+    mut conn: DbConn,
+) -> Result<LoginResponse, LoginError> {
     let credential = credential.0;
+    let ip = client.ip.as_deref();
+    if let Some(seconds) = throttle::retry_after(&mut conn, &credential.username, ip) {
+        return Err(LoginError::retry_after(seconds));
+    }
+
     let user = match User::get(&mut conn, &credential.username) {
         DatabaseResult::Succeful(user) => user,
-        _ => return None,
+        _ => {
+            throttle::record(&mut conn, &credential.username, ip, false);
+            audit::record(
+                &mut conn,
+                Action::LoginFailed,
+                Some(&credential.username),
+                None,
+                ip,
+            );
+            return Err(LoginError::invalid());
+        }
+    };
+    match hasher::verify(&credential.password, &user.password) {
+        Verification::Valid => (),
+        // upgrades legacy and outdated hashes now that we know the password
+        Verification::NeedsRehash => {
+            User::set_password(&mut conn, &user.username, &credential.password.hash());
+        }
+        Verification::Invalid => {
+            throttle::record(&mut conn, &credential.username, ip, false);
+            audit::record(
+                &mut conn,
+                Action::LoginFailed,
+                Some(&user.username),
+                None,
+                ip,
+            );
+            return Err(LoginError::invalid());
+        }
+    }
+
+    // the success is only recorded once the code checks out too,
+    // otherwise knowing the password would reset the count for guessing codes
+    if let DatabaseResult::Succeful(_) = TotpSecret::get_confirmed(&mut conn, &user.username) {
+        let (challenge, expires_at) = issuer.issue_challenge(&user.username);
+        return Ok(LoginResponse::TwoFactorRequired(Json(TwoFactorChallenge {
+            challenge,
+            expires_at,
+        })));
+    }
+    throttle::record(&mut conn, &user.username, ip, true);
+    let cookies = cookie.unwrap_or(false).then_some(cookies);
+    start_session(&mut conn, issuer, &user, client, false, cookies)
+}
+
+/// second login step for users with 2FA on, checks the code for a
+/// challenge from `login` and starts a new session
+///
+/// a recovery code works in place of a TOTP code, once. Wrong codes
+/// count as failed logins
+#[post(
+    "/login/totp?<cookie>",
+    format = "application/json",
+    data = "<credential>"
+)]
+pub fn login_totp(
+    credential: Json<TwoFactorCredential>,
+    cookie: Option<bool>,
+    cookies: &CookieJar<'_>,
+    client: ClientInfo,
+    issuer: &State<TokenIssuer>,
+    mut conn: DbConn,
+) -> Result<LoginResponse, LoginError> {
+    let (username, issued_at) = match issuer.verify_challenge(&credential.challenge) {
+        Some(challenge) => challenge,
+        None => {
+            return Err(LoginError::InvalidCredentials(
+                "invalid or expired challenge",
+            ))
+        }
+    };
+    let ip = client.ip.as_deref();
+    if let Some(seconds) = throttle::retry_after(&mut conn, &username, ip) {
+        return Err(LoginError::retry_after(seconds));
+    }
+
+    let (user, secret) = match (
+        User::get(&mut conn, &username),
+        TotpSecret::get_confirmed(&mut conn, &username),
+    ) {
+        // challenges from before a password reset are refused
+        (DatabaseResult::Succeful(user), DatabaseResult::Succeful(secret))
+            if user.tokens_valid(issued_at) =>
+        {
+            (user, secret)
+        }
+        _ => {
+            return Err(LoginError::InvalidCredentials(
+                "invalid or expired challenge",
+            ))
+        }
     };
-    if credential.password.hash().eq(&user.password) {
-        let user = User::refresh_bearer(&mut conn, &credential.username).unwrap();
-        Some(Json(user))
-    } else {
-        None
+    if !totp::check_code(&mut conn, &secret, &credential.code) {
+        throttle::record(&mut conn, &username, ip, false);
+        audit::record(&mut conn, Action::LoginFailed, Some(&username), None, ip);
+        return Err(LoginError::InvalidCredentials("invalid code"));
+    }
+    throttle::record(&mut conn, &username, ip, true);
+    let cookies = cookie.unwrap_or(false).then_some(cookies);
+    start_session(&mut conn, issuer, &user, client, true, cookies)
+}
+
+/// starts a session for a user who just logged in and mints its tokens,
+/// or sets its cookie if `cookies` is given
+fn start_session(
+    conn: &mut DbConn,
+    issuer: &TokenIssuer,
+    user: &User,
+    client: ClientInfo,
+    two_factor: bool,
+    cookies: Option<&CookieJar<'_>>,
+) -> Result<LoginResponse, LoginError> {
+    audit::record(
+        conn,
+        Action::LoginSucceeded,
+        Some(&user.username),
+        None,
+        client.ip.as_deref(),
+    );
+    // every login gets its own session so other devices stay logged in
+    Session::delete_expired(conn, &user.username);
+    let new_session =
+        NewSession::new(user.username.clone(), client.user_agent, client.ip).two_factor(two_factor);
+    let session = match Session::add(conn, &new_session) {
+        DatabaseResult::Succeful(session) => session,
+        _ => return Err(LoginError::Unavailable("couldn't start a session")),
+    };
+    if let Some(cookies) = cookies {
+        let csrf_token = cookie::start(cookies, &session);
+        return Ok(LoginResponse::Cookie(Json(CookieSession {
+            session_id: session.id,
+            expires_at: session.expires_at,
+            csrf_token,
+        })));
+    }
+    match RefreshToken::issue(conn, session.id) {
+        DatabaseResult::Succeful(refresh_token) => Ok(LoginResponse::Tokens(Json(TokenPair::new(
+            issuer,
+            user,
+            &session,
+            refresh_token,
+        )))),
+        _ => Err(LoginError::Unavailable("couldn't start a session")),
+    }
+}
+
+/// trades a refresh token in for a new token pair
+///
+/// replaying an already used refresh token revokes its whole session
+#[post("/refresh", format = "application/json", data = "<request>")]
+pub fn refresh(
+    request: Json<RefreshRequest>,
+    client: ClientInfo,
+    issuer: &State<TokenIssuer>,
+    mut conn: DbConn,
+) -> Result<Json<TokenPair>, Status> {
+    let (session, refresh_token) = match RefreshToken::rotate(&mut conn, &request.refresh_token) {
+        DatabaseResult::Succeful(rotated) => rotated,
+        _ => return Err(Status::Unauthorized),
+    };
+    match User::get(&mut conn, &session.user_id) {
+        DatabaseResult::Succeful(user) => {
+            let ip = client.ip.as_deref();
+            audit::record(
+                &mut conn,
+                Action::TokenRefreshed,
+                Some(&user.username),
+                Some(&session.id.to_string()),
+                ip,
+            );
+            Ok(Json(TokenPair::new(issuer, &user, &session, refresh_token)))
+        }
+        _ => Err(Status::Unauthorized),
+    }
+}
+
+/// ends the session the request was authenticated with and removes
+/// its cookies, if any
+#[post("/logout")]
+pub fn logout(
+    user: gaurd::UserGaurd,
+    cookies: &CookieJar<'_>,
+    mut conn: DbConn,
+) -> Option<Json<Session>> {
+    cookie::end(cookies);
+    match Session::delete(&mut conn, user.session_id, &user.username) {
+        DatabaseResult::Succeful(session) => Some(Json(session)),
+        _ => None,
     }
 }
 
+use password_reset::*;
 use rocket::Route;
 pub fn stage() -> Vec<Route> {
-    routes![to_loging, login]
+    routes![
+        to_loging,
+        login,
+        login_totp,
+        refresh,
+        logout,
+        request_password_reset,
+        confirm_password_reset
+    ]
 }
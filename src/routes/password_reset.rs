@@ -0,0 +1,104 @@
+use crate::audit::{self, Action};
+use crate::authentication::hasher::Hash;
+use crate::authentication::ClientInfo;
+use crate::mailer::{Mail, Mailer};
+use crate::models::result_variant::DatabaseResult;
+use crate::models::{NewPasswordReset, PasswordReset, User};
+use crate::DbConn;
+use chrono::{Duration, Utc};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::Deserialize;
+use std::env;
+
+/// a user can only ask for a new reset token this often
+const REQUEST_INTERVAL_SECONDS: i64 = 60;
+
+#[derive(Deserialize)]
+pub struct ResetRequest {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetConfirmation {
+    token: String,
+    password: String,
+}
+
+/// the mail a reset token is sent in
+///
+/// links to PASSWORD_RESET_URL with the token when it's set
+fn reset_mail(to: String, token: &str) -> Mail {
+    let link = match env::var("PASSWORD_RESET_URL") {
+        Ok(url) => format!("\n\n{}?token={}", url, token),
+        Err(_) => String::new(),
+    };
+    Mail {
+        to,
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Someone asked to reset your password. If it was you, use this token \
+             to pick a new one, it only works once:\n\n{}{}\n\nOtherwise you can ignore this mail.",
+            token, link
+        ),
+    }
+}
+
+/// mails a password reset token to the user with this email
+///
+/// always answers 202 Accepted so it can't be used to find out which
+/// addresses have an account
+#[post("/password_reset", format = "application/json", data = "<request>")]
+pub fn request_password_reset(
+    request: Json<ResetRequest>,
+    mailer: &State<Box<dyn Mailer>>,
+    mut conn: DbConn,
+) -> Status {
+    let user = match User::get_by_email(&mut conn, &request.email) {
+        DatabaseResult::Succeful(user) => user,
+        _ => return Status::Accepted,
+    };
+    let recently = Utc::now().naive_utc() - Duration::seconds(REQUEST_INTERVAL_SECONDS);
+    if let DatabaseResult::Succeful(latest) = PasswordReset::latest(&mut conn, &user.username) {
+        if latest.created_at > recently {
+            return Status::Accepted;
+        }
+    }
+
+    let (new_reset, token) = NewPasswordReset::new(user.username);
+    if let DatabaseResult::Succeful(_) = PasswordReset::add(&mut conn, &new_reset) {
+        if let Err(err) = mailer.send(&reset_mail(request.0.email, &token)) {
+            error!("couldn't send a password reset mail: {:?}", err);
+        }
+    }
+    Status::Accepted
+}
+
+/// sets a new password with a token from `request_password_reset`
+///
+/// logs the user out everywhere and revokes their API keys
+#[post(
+    "/password_reset/confirm",
+    format = "application/json",
+    data = "<confirmation>"
+)]
+pub fn confirm_password_reset(
+    confirmation: Json<ResetConfirmation>,
+    client: ClientInfo,
+    mut conn: DbConn,
+) -> Status {
+    let confirmation = confirmation.0;
+    if confirmation.password.is_empty() {
+        return Status::BadRequest;
+    }
+    let password_hash = confirmation.password.hash();
+    match PasswordReset::redeem(&mut conn, &confirmation.token, &password_hash) {
+        DatabaseResult::Succeful(username) => {
+            let ip = client.ip.as_deref();
+            audit::record(&mut conn, Action::PasswordReset, Some(&username), None, ip);
+            Status::NoContent
+        }
+        _ => Status::BadRequest,
+    }
+}
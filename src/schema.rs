@@ -1,24 +1,221 @@
 table! {
+    use super::sql_types::AccountKind;
+    use diesel::sql_types::*;
     account (id) {
-        balance -> Text,
+        balance -> Numeric,
+        user_id -> Text,
+        id -> Int4,
+        name -> Text,
+        kind -> AccountKind,
+        currency -> Text,
+    }
+}
+
+table! {
+    account_members (account_id, user_id) {
+        account_id -> Int4,
+        user_id -> Text,
+        role -> Text,
+        added_at -> Timestamp,
+    }
+}
+
+table! {
+    api_keys (id) {
+        id -> Int4,
+        user_id -> Text,
+        name -> Text,
+        prefix -> Text,
+        key_hash -> Text,
+        scopes -> Array<Text>,
+        account_ids -> Nullable<Array<Int4>>,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    audit_events (id) {
+        id -> Int4,
+        actor -> Nullable<Text>,
+        action -> Text,
+        target -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        created_at -> Timestamp,
+        detail -> Nullable<Text>,
+    }
+}
+
+table! {
+    currencies (code) {
+        code -> Text,
+        name -> Text,
+        minor_units -> Int2,
+        symbol -> Text,
+        custom -> Bool,
+    }
+}
+
+table! {
+    deletion_requests (user_id) {
+        user_id -> Text,
+        requested_at -> Timestamp,
+        scheduled_for -> Timestamp,
+    }
+}
+
+table! {
+    lockouts (id) {
+        id -> Int4,
+        scope -> Text,
+        subject -> Text,
+        failures -> Int4,
+        locked_at -> Timestamp,
+        locked_until -> Timestamp,
+    }
+}
+
+table! {
+    exchange_rates (id) {
+        id -> Int4,
+        date -> Date,
+        base -> Text,
+        quote -> Text,
+        rate -> Numeric,
+    }
+}
+
+table! {
+    journal_entries (id) {
+        id -> Int4,
+        user_id -> Nullable<Text>,
+        description -> Nullable<Text>,
+        time -> Date,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    login_attempts (id) {
+        id -> Int4,
+        username -> Text,
+        ip -> Nullable<Text>,
+        succeeded -> Bool,
+        attempted_at -> Timestamp,
+    }
+}
+
+table! {
+    password_resets (id) {
+        id -> Int4,
+        user_id -> Text,
+        token_hash -> Text,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    permissions (name) {
+        name -> Text,
+        description -> Text,
+    }
+}
+
+table! {
+    postings (id) {
+        id -> Int4,
+        entry_id -> Int4,
+        account_id -> Int4,
+        amount -> Numeric,
+        currency -> Text,
+    }
+}
+
+table! {
+    recovery_codes (id) {
+        id -> Int4,
         user_id -> Text,
+        code_hash -> Text,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    refresh_tokens (id) {
         id -> Int4,
+        session_id -> Int4,
+        token_hash -> Text,
+        issued_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    role_permissions (role, permission) {
+        role -> Text,
+        permission -> Text,
+    }
+}
+
+table! {
+    roles (name) {
         name -> Text,
+        description -> Text,
+        require_2fa -> Bool,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Int4,
+        user_id -> Text,
+        created_at -> Timestamp,
+        last_used_at -> Timestamp,
+        expires_at -> Timestamp,
+        user_agent -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        two_factor -> Bool,
     }
 }
 
 table! {
-    use super::sql_types::CurrencyType;
+    totp_secrets (user_id) {
+        user_id -> Text,
+        secret -> Bytea,
+        created_at -> Timestamp,
+        confirmed_at -> Nullable<Timestamp>,
+        last_used_step -> Nullable<Int8>,
+    }
+}
+
+table! {
+    use super::sql_types::TransactionKind;
     use diesel::sql_types::*;
     transaction (id) {
-        kind -> Bool,
+        kind -> TransactionKind,
         title -> Text,
-        value -> Text,
-        currency -> CurrencyType,
+        value -> Numeric,
+        currency -> Text,
         time -> Date,
-        user_id -> Text,
+        user_id -> Nullable<Text>,
         id -> Int4,
         bank_account -> Int4,
+        notes -> Nullable<Text>,
+        transfer_id -> Nullable<Int4>,
+        entry_id -> Int4,
+    }
+}
+
+table! {
+    transfers (id) {
+        id -> Int4,
+        user_id -> Nullable<Text>,
+        rate -> Numeric,
+        fee -> Numeric,
+        created_at -> Timestamp,
     }
 }
 
@@ -27,18 +224,68 @@ table! {
         name -> Text,
         username -> Text,
         password -> Text,
-        api_token -> Text,
-        role -> Bool,
+        role -> Text,
+        email -> Nullable<Text>,
+        base_currency -> Text,
+        tokens_valid_after -> Nullable<Timestamp>,
     }
 }
 
 joinable!(account -> users (user_id));
+joinable!(account_members -> account (account_id));
+joinable!(account_members -> users (user_id));
+joinable!(api_keys -> users (user_id));
+joinable!(deletion_requests -> users (user_id));
+joinable!(journal_entries -> users (user_id));
+joinable!(password_resets -> users (user_id));
+joinable!(postings -> account (account_id));
+joinable!(postings -> currencies (currency));
+joinable!(postings -> journal_entries (entry_id));
+joinable!(recovery_codes -> users (user_id));
+joinable!(refresh_tokens -> sessions (session_id));
+joinable!(role_permissions -> permissions (permission));
+joinable!(role_permissions -> roles (role));
+joinable!(sessions -> users (user_id));
+joinable!(totp_secrets -> users (user_id));
 joinable!(transaction -> account (bank_account));
+joinable!(transaction -> currencies (currency));
+joinable!(transaction -> journal_entries (entry_id));
+joinable!(transaction -> transfers (transfer_id));
 joinable!(transaction -> users (user_id));
+joinable!(transfers -> users (user_id));
+joinable!(users -> currencies (base_currency));
+joinable!(users -> roles (role));
 
-allow_tables_to_appear_in_same_query!(account, transaction, users,);
+allow_tables_to_appear_in_same_query!(
+    account,
+    account_members,
+    api_keys,
+    audit_events,
+    currencies,
+    deletion_requests,
+    exchange_rates,
+    journal_entries,
+    lockouts,
+    login_attempts,
+    password_resets,
+    permissions,
+    postings,
+    recovery_codes,
+    refresh_tokens,
+    role_permissions,
+    roles,
+    sessions,
+    totp_secrets,
+    transaction,
+    transfers,
+    users,
+);
 pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "account_kind"))]
+    pub struct AccountKind;
+
     #[derive(diesel::sql_types::SqlType)]
-    #[diesel(postgres_type(name = "currency_type"))]
-    pub struct CurrencyType;
+    #[diesel(postgres_type(name = "transaction_kind"))]
+    pub struct TransactionKind;
 }